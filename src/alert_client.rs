@@ -0,0 +1,320 @@
+use crate::types::SpotPrice;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::error::Error;
+use tracing::{debug, info, warn};
+
+pub struct AlertClientConfig {
+    webhook_url: String,
+    below: Option<f64>,
+    above: Option<f64>,
+    inclusive: bool,
+    negative_price_alert_enable: bool,
+}
+
+impl AlertClientConfig {
+    pub fn new(
+        webhook_url: &str,
+        below: Option<f64>,
+        above: Option<f64>,
+        inclusive: bool,
+        negative_price_alert_enable: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            webhook_url: webhook_url.to_string(),
+            below,
+            above,
+            inclusive,
+            negative_price_alert_enable,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let webhook_url = env::var("ALERT_WEBHOOK_URL").unwrap_or_default();
+        let below: Option<f64> = match env::var("ALERT_BELOW") {
+            Ok(b) => Some(b.parse()?),
+            Err(_) => None,
+        };
+        let above: Option<f64> = match env::var("ALERT_ABOVE") {
+            Ok(a) => Some(a.parse()?),
+            Err(_) => None,
+        };
+        let inclusive: bool = env::var("ALERT_INCLUSIVE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let negative_price_alert_enable: bool = env::var("NEGATIVE_PRICE_ALERT_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        Self::new(
+            &webhook_url,
+            below,
+            above,
+            inclusive,
+            negative_price_alert_enable,
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AlertPayload<'a> {
+    below: Option<f64>,
+    above: Option<f64>,
+    spot_prices: Vec<&'a SpotPrice>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NegativePriceAlertPayload<'a> {
+    alert: &'static str,
+    spot_prices: Vec<&'a SpotPrice>,
+}
+
+pub struct AlertClient {
+    config: AlertClientConfig,
+}
+
+impl AlertClient {
+    pub fn new(config: AlertClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(AlertClientConfig::from_env()?))
+    }
+
+    fn matches(&self, total_price: f64) -> bool {
+        let below = self.config.below.map_or(false, |b| {
+            if self.config.inclusive {
+                total_price <= b
+            } else {
+                total_price < b
+            }
+        });
+        let above = self.config.above.map_or(false, |a| {
+            if self.config.inclusive {
+                total_price >= a
+            } else {
+                total_price > a
+            }
+        });
+
+        below || above
+    }
+
+    /// Returns the future spot prices whose total price crosses one of the configured thresholds.
+    pub fn matching_spot_prices<'a>(
+        &self,
+        spot_prices: &'a [SpotPrice],
+        now: DateTime<Utc>,
+    ) -> Vec<&'a SpotPrice> {
+        spot_prices
+            .iter()
+            .filter(|sp| sp.till > now && self.matches(sp.total_price()))
+            .collect()
+    }
+
+    pub async fn alert(
+        &self,
+        spot_prices: &[SpotPrice],
+        now: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.config.webhook_url.is_empty()
+            || (self.config.below.is_none() && self.config.above.is_none())
+        {
+            return Ok(());
+        }
+
+        let matching_spot_prices = self.matching_spot_prices(spot_prices, now);
+        if matching_spot_prices.is_empty() {
+            debug!("No future spot prices crossing alert thresholds");
+            return Ok(());
+        }
+
+        let payload = AlertPayload {
+            below: self.config.below,
+            above: self.config.above,
+            spot_prices: matching_spot_prices,
+        };
+
+        self.post(&payload).await?;
+
+        info!(
+            "Sent alert for {} spot prices crossing thresholds",
+            payload.spot_prices.len()
+        );
+
+        Ok(())
+    }
+
+    /// Returns the future spot prices with a negative market price.
+    pub fn negative_spot_prices<'a>(
+        spot_prices: &'a [SpotPrice],
+        now: DateTime<Utc>,
+    ) -> Vec<&'a SpotPrice> {
+        spot_prices
+            .iter()
+            .filter(|sp| sp.till > now && sp.market_price < 0.0)
+            .collect()
+    }
+
+    /// Warns about upcoming hours with a negative market price and notifies the webhook if one is
+    /// configured; only when enabled with NEGATIVE_PRICE_ALERT_ENABLE.
+    pub async fn alert_negative_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+        now: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.config.negative_price_alert_enable {
+            return Ok(());
+        }
+
+        let negative_spot_prices = Self::negative_spot_prices(spot_prices, now);
+        if negative_spot_prices.is_empty() {
+            debug!("No future spot prices below zero");
+            return Ok(());
+        }
+
+        warn!(
+            "Negative spot prices in {} upcoming hours starting at {}",
+            negative_spot_prices.len(),
+            negative_spot_prices
+                .iter()
+                .map(|sp| sp.from.to_rfc3339())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        if self.config.webhook_url.is_empty() {
+            return Ok(());
+        }
+
+        let payload = NegativePriceAlertPayload {
+            alert: "negativePrice",
+            spot_prices: negative_spot_prices,
+        };
+
+        self.post(&payload).await?;
+
+        info!(
+            "Sent alert for {} spot prices below zero",
+            payload.spot_prices.len()
+        );
+
+        Ok(())
+    }
+
+    async fn post<T: Serialize>(&self, payload: &T) -> Result<(), Box<dyn Error>> {
+        let response = reqwest::Client::new()
+            .post(&self.config.webhook_url)
+            .json(payload)
+            .send()
+            .await?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(Box::<dyn Error>::from(format!(
+                "Alert webhook status code {} indicates failure",
+                status_code
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn spot_prices(start: DateTime<Utc>, market_prices: &[f64]) -> Vec<SpotPrice> {
+        market_prices
+            .iter()
+            .enumerate()
+            .map(|(i, market_price)| SpotPrice {
+                market_price: *market_price,
+                market_price_tax: 0.0,
+                ..SpotPrice::test_at(start + Duration::hours(i as i64))
+            })
+            .collect()
+    }
+
+    fn alert_client(below: Option<f64>, above: Option<f64>, inclusive: bool) -> AlertClient {
+        AlertClient::new(
+            AlertClientConfig::new("http://localhost/alert", below, above, inclusive, false)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn matching_spot_prices_below() {
+        let now: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(now, &[0.25, 0.05, 0.1, 0.3]);
+
+        let matching = alert_client(Some(0.1), None, false).matching_spot_prices(&spot_prices, now);
+
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].market_price, 0.05);
+    }
+
+    #[test]
+    fn matching_spot_prices_above() {
+        let now: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(now, &[0.25, 0.05, 0.1, 0.3]);
+
+        let matching =
+            alert_client(None, Some(0.25), false).matching_spot_prices(&spot_prices, now);
+
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].market_price, 0.3);
+    }
+
+    #[test]
+    fn matching_spot_prices_inclusive_boundaries() {
+        let now: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(now, &[0.25, 0.05, 0.1, 0.3]);
+
+        let matching =
+            alert_client(Some(0.1), Some(0.25), true).matching_spot_prices(&spot_prices, now);
+
+        assert_eq!(matching.len(), 4);
+    }
+
+    #[test]
+    fn matching_spot_prices_no_trigger() {
+        let now: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(now, &[0.25, 0.05, 0.1, 0.3]);
+
+        let matching =
+            alert_client(Some(0.01), Some(0.5), false).matching_spot_prices(&spot_prices, now);
+
+        assert!(matching.is_empty());
+    }
+
+    #[test]
+    fn matching_spot_prices_ignores_past_hours() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[0.05, 0.25]);
+
+        let matching = alert_client(Some(0.1), None, false)
+            .matching_spot_prices(&spot_prices, start + Duration::hours(1));
+
+        assert!(matching.is_empty());
+    }
+
+    #[test]
+    fn negative_spot_prices() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[-0.05, 0.25, -0.01, 0.0]);
+
+        let negative = AlertClient::negative_spot_prices(&spot_prices, start + Duration::hours(1));
+
+        assert_eq!(negative.len(), 1);
+        assert_eq!(negative[0].market_price, -0.01);
+    }
+}
@@ -1,13 +1,108 @@
-use crate::types::SpotPrice;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::error::ExporterError;
+use crate::retry::RetryPolicy;
+use crate::sink::{SpotPriceSink, SpotPriceSnapshot};
+use crate::types::{Consumption, DailyPriceSummary, SpotPrice, StoredSpotPriceKey};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use gcp_bigquery_client::error::BQError;
+use gcp_bigquery_client::model::clustering::Clustering;
+use gcp_bigquery_client::model::dataset::Dataset;
+use gcp_bigquery_client::model::query_request::QueryRequest;
 use gcp_bigquery_client::model::table::Table;
 use gcp_bigquery_client::model::table_data_insert_all_request::TableDataInsertAllRequest;
+use gcp_bigquery_client::model::table_data_insert_all_response_insert_errors::TableDataInsertAllResponseInsertErrors;
 use gcp_bigquery_client::model::table_field_schema::TableFieldSchema;
 use gcp_bigquery_client::model::table_schema::TableSchema;
 use gcp_bigquery_client::model::time_partitioning::TimePartitioning;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::env;
 use std::error::Error;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
 use std::{thread, time};
-use tracing::info;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const SPOT_PRICE_MERGE_CONDITION: &str =
+    "T.source = S.source AND IFNULL(T.homeId, '') = IFNULL(S.homeId, '') AND T.`from` = S.`from`";
+const DAILY_SUMMARY_MERGE_CONDITION: &str = concat!(
+    "IFNULL(T.source, '') = IFNULL(S.source, '') AND IFNULL(T.homeId, '') = IFNULL(S.homeId, '')",
+    " AND T.`date` = S.`date`"
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigqueryWriteMode {
+    Stream,
+    Merge,
+}
+
+impl FromStr for BigqueryWriteMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "stream" => Ok(BigqueryWriteMode::Stream),
+            "merge" => Ok(BigqueryWriteMode::Merge),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown bigquery write mode {}, expected stream or merge",
+                s
+            ))),
+        }
+    }
+}
+
+/// Timestamp column the spot price table is partitioned by per day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigqueryPartitionField {
+    From,
+    Till,
+}
+
+impl BigqueryPartitionField {
+    fn column(&self) -> &'static str {
+        match self {
+            BigqueryPartitionField::From => "from",
+            BigqueryPartitionField::Till => "till",
+        }
+    }
+}
+
+impl FromStr for BigqueryPartitionField {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "from" => Ok(BigqueryPartitionField::From),
+            "till" => Ok(BigqueryPartitionField::Till),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown bigquery partition field {}, expected from or till",
+                s
+            ))),
+        }
+    }
+}
+
+/// Row as inserted into bigquery, with computed columns next to the spot price fields.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SpotPriceRow<'a> {
+    #[serde(flatten)]
+    spot_price: &'a SpotPrice,
+    total_price: f64,
+}
+
+impl<'a> From<&'a SpotPrice> for SpotPriceRow<'a> {
+    fn from(spot_price: &'a SpotPrice) -> Self {
+        Self {
+            spot_price,
+            total_price: spot_price.total_price(),
+        }
+    }
+}
 
 pub struct BigqueryClientConfig {
     project_id: String,
@@ -15,22 +110,58 @@ pub struct BigqueryClientConfig {
     table: String,
     enable: bool,
     init: bool,
+    dry_run: bool,
+    location: Option<String>,
+    allowed_locations: Vec<String>,
+    partition_expiration_days: Option<u64>,
+    partition_field: BigqueryPartitionField,
+    clustering_enable: bool,
+    retry_policy: RetryPolicy,
+    write_mode: BigqueryWriteMode,
+    daily_enable: bool,
+    daily_table: String,
+    daily_time_zone: Tz,
+    consumption_enable: bool,
+    consumption_table: String,
+    circuit_breaker: CircuitBreaker,
     client: gcp_bigquery_client::Client,
 }
 
 impl BigqueryClientConfig {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         project_id: &str,
         dataset: &str,
         table: &str,
         google_application_credentials: &str,
+        use_default_credentials: bool,
         enable: bool,
         init: bool,
+        dry_run: bool,
+        location: Option<String>,
+        allowed_locations: Vec<String>,
+        partition_expiration_days: Option<u64>,
+        partition_field: BigqueryPartitionField,
+        clustering_enable: bool,
+        retry_policy: RetryPolicy,
+        write_mode: BigqueryWriteMode,
+        daily_enable: bool,
+        daily_table: &str,
+        daily_time_zone: Tz,
+        consumption_enable: bool,
+        consumption_table: &str,
+        circuit_breaker: CircuitBreaker,
     ) -> Result<Self, Box<dyn Error>> {
-        let client = gcp_bigquery_client::Client::from_service_account_key_file(
-            google_application_credentials,
-        )
-        .await;
+        let client =
+            if use_default_credentials || !Path::new(google_application_credentials).exists() {
+                info!("Using application default credentials for bigquery");
+                gcp_bigquery_client::Client::from_application_default_credentials().await?
+            } else {
+                gcp_bigquery_client::Client::from_service_account_key_file(
+                    google_application_credentials,
+                )
+                .await
+            };
 
         Ok(Self {
             project_id: project_id.to_string(),
@@ -38,6 +169,20 @@ impl BigqueryClientConfig {
             table: table.to_string(),
             enable,
             init,
+            dry_run,
+            location,
+            allowed_locations,
+            partition_expiration_days,
+            partition_field,
+            clustering_enable,
+            retry_policy,
+            write_mode,
+            daily_enable,
+            daily_table: daily_table.to_string(),
+            daily_time_zone,
+            consumption_enable,
+            consumption_table: consumption_table.to_string(),
+            circuit_breaker,
             client,
         })
     }
@@ -45,9 +190,15 @@ impl BigqueryClientConfig {
     pub async fn from_env() -> Result<Self, Box<dyn Error>> {
         let google_application_credentials = env::var("GOOGLE_APPLICATION_CREDENTIALS")
             .unwrap_or_else(|_| String::from("/secrets/keyfile.json"));
+        let use_default_credentials: bool = env::var("BQ_USE_DEFAULT_CREDENTIALS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
         let project_id = env::var("BQ_PROJECT_ID")?;
         let dataset = env::var("BQ_DATASET")?;
-        let table = env::var("BQ_TABLE")?;
+        // BQ_TABLE can contain {source} to write each source to its own table
+        let source = env::var("SOURCE").unwrap_or_default();
+        let table = BigqueryClient::resolve_table_name(&env::var("BQ_TABLE")?, &source)?;
         let enable: bool = env::var("BQ_ENABLE")
             .unwrap_or_else(|_| "true".to_string())
             .parse()
@@ -56,14 +207,98 @@ impl BigqueryClientConfig {
             .unwrap_or_else(|_| "true".to_string())
             .parse()
             .unwrap_or(true);
+        let dry_run: bool = env::var("BQ_DRY_RUN")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let location = BigqueryClient::parse_location(&env::var("BQ_LOCATION").unwrap_or_default());
+        let allowed_locations = BigqueryClient::parse_allowed_locations(
+            &env::var("BQ_ALLOWED_LOCATIONS").unwrap_or_default(),
+        );
+        let partition_expiration_days: Option<u64> = match env::var("BQ_PARTITION_EXPIRATION_DAYS")
+        {
+            Ok(d) if !d.is_empty() => Some(d.parse()?),
+            _ => None,
+        };
+        let partition_field: BigqueryPartitionField = env::var("BQ_PARTITION_FIELD")
+            .unwrap_or_else(|_| "from".to_string())
+            .parse()?;
+        let clustering_enable: bool = env::var("BQ_CLUSTERING_ENABLE")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .unwrap_or(true);
+        // BQ_RETRIES overrides the general retry policy for bigquery requests only
+        let retry_policy = match env::var("BQ_RETRIES")
+            .ok()
+            .and_then(|r| r.parse::<usize>().ok())
+        {
+            Some(retries) => RetryPolicy::from_env().with_max_attempts(retries + 1),
+            None => RetryPolicy::from_env(),
+        };
+        let write_mode: BigqueryWriteMode = env::var("BQ_WRITE_MODE")
+            .unwrap_or_else(|_| "stream".to_string())
+            .parse()?;
+        let daily_enable: bool = env::var("BQ_DAILY_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let daily_table = match env::var("BQ_DAILY_TABLE") {
+            Ok(daily_table) => BigqueryClient::resolve_table_name(&daily_table, &source)?,
+            Err(_) => format!("{}_daily", table),
+        };
+        let daily_time_zone: Tz = env::var("BQ_DAILY_TIME_ZONE")
+            .unwrap_or_else(|_| "UTC".to_string())
+            .parse()?;
+        let consumption_enable: bool = env::var("CONSUMPTION_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let consumption_table = match env::var("BQ_CONSUMPTION_TABLE") {
+            Ok(consumption_table) => {
+                BigqueryClient::resolve_table_name(&consumption_table, &source)?
+            }
+            Err(_) => format!("{}_consumption", table),
+        };
+        let circuit_breaker_failures: usize = env::var("BQ_CIRCUIT_BREAKER_FAILURES")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse()
+            .unwrap_or(5);
+        let circuit_breaker_window_seconds: u64 = env::var("BQ_CIRCUIT_BREAKER_WINDOW_SECONDS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .unwrap_or(300);
+        let circuit_breaker_cooldown_seconds: u64 = env::var("BQ_CIRCUIT_BREAKER_COOLDOWN_SECONDS")
+            .unwrap_or_else(|_| "600".to_string())
+            .parse()
+            .unwrap_or(600);
 
         Self::new(
             &project_id,
             &dataset,
             &table,
             &google_application_credentials,
+            use_default_credentials,
             enable,
             init,
+            dry_run,
+            location,
+            allowed_locations,
+            partition_expiration_days,
+            partition_field,
+            clustering_enable,
+            retry_policy,
+            write_mode,
+            daily_enable,
+            &daily_table,
+            daily_time_zone,
+            consumption_enable,
+            &consumption_table,
+            CircuitBreaker::new(
+                "bigquery",
+                circuit_breaker_failures,
+                time::Duration::from_secs(circuit_breaker_window_seconds),
+                time::Duration::from_secs(circuit_breaker_cooldown_seconds),
+            ),
         )
         .await
     }
@@ -82,6 +317,78 @@ impl BigqueryClient {
         Ok(Self::new(BigqueryClientConfig::from_env().await?))
     }
 
+    /// An empty location leaves it to bigquery, which defaults to the US.
+    fn parse_location(location: &str) -> Option<String> {
+        Some(location.trim().to_string()).filter(|l| !l.is_empty())
+    }
+
+    /// Comma separated locations, ignoring blanks.
+    fn parse_allowed_locations(allowed_locations: &str) -> Vec<String> {
+        allowed_locations
+            .split(',')
+            .filter_map(Self::parse_location)
+            .collect()
+    }
+
+    /// Expands `{source}` in the table name template and checks the result is a valid table name:
+    /// at most 1024 letters, digits, underscores or dashes.
+    fn resolve_table_name(template: &str, source: &str) -> Result<String, ExporterError> {
+        let table = template.replace("{source}", source);
+
+        let is_valid = !table.is_empty()
+            && table.len() <= 1024
+            && table
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !is_valid {
+            return Err(ExporterError::Config(format!(
+                "Bigquery table name {} resolved from {} is invalid",
+                table, template
+            )));
+        }
+
+        Ok(table)
+    }
+
+    /// Server errors and rate limiting are worth retrying, other errors like an invalid schema won't
+    /// go away by themselves.
+    fn is_retryable(err: &BQError) -> bool {
+        match err {
+            BQError::ResponseError { error } => error.error.code == 429 || error.error.code >= 500,
+            BQError::RequestError(_) => true,
+            _ => false,
+        }
+    }
+
+    async fn with_retry<T, A, F>(&self, action: A) -> Result<T, BQError>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = Result<T, BQError>>,
+    {
+        self.config
+            .retry_policy
+            .retry("Bigquery request", action, Self::is_retryable)
+            .await
+    }
+
+    /// Query jobs run in the configured location, so they find datasets outside the US.
+    fn query_request(location: Option<&str>, query: &str) -> QueryRequest {
+        QueryRequest {
+            location: location.map(|l| l.to_string()),
+            ..QueryRequest::new(query)
+        }
+    }
+
+    async fn get_dataset(&self) -> Result<Dataset, BQError> {
+        self.with_retry(|| {
+            self.config
+                .client
+                .dataset()
+                .get(&self.config.project_id, &self.config.dataset)
+        })
+        .await
+    }
+
     pub async fn check_if_table_exists(&self) -> bool {
         if !self.config.enable {
             return false;
@@ -100,37 +407,122 @@ impl BigqueryClient {
             .is_ok()
     }
 
-    pub async fn create_table(&self, wait_ready: bool) -> Result<(), Box<dyn Error>> {
-        if !self.config.enable {
-            return Ok(());
+    pub fn table_fields() -> Vec<TableFieldSchema> {
+        vec![
+            TableFieldSchema::string("id"),
+            TableFieldSchema::string("source"),
+            TableFieldSchema::timestamp("from"),
+            TableFieldSchema::timestamp("till"),
+            TableFieldSchema::float("marketPrice"),
+            TableFieldSchema::float("marketPriceTax"),
+            TableFieldSchema::float("sourcingMarkupPrice"),
+            TableFieldSchema::float("energyTaxPrice"),
+            TableFieldSchema::string("recordType"),
+            TableFieldSchema::float("applianceCycleCost"),
+            TableFieldSchema::string("homeId"),
+            TableFieldSchema::string("currency"),
+            TableFieldSchema::string("priceLevel"),
+            TableFieldSchema::float("totalPrice"),
+            TableFieldSchema::string("originalCurrency"),
+            TableFieldSchema::float("exchangeRate"),
+            TableFieldSchema::string("timeZone"),
+            TableFieldSchema::date("localDate"),
+            TableFieldSchema::integer("resolution"),
+            TableFieldSchema::string("computedPriceLevel"),
+            TableFieldSchema::timestamp("ingestedAt"),
+        ]
+    }
+
+    fn daily_table_fields() -> Vec<TableFieldSchema> {
+        vec![
+            TableFieldSchema::string("source"),
+            TableFieldSchema::string("homeId"),
+            TableFieldSchema::date("date"),
+            TableFieldSchema::string("timeZone"),
+            TableFieldSchema::string("currency"),
+            TableFieldSchema::integer("count"),
+            TableFieldSchema::float("minTotalPrice"),
+            TableFieldSchema::float("maxTotalPrice"),
+            TableFieldSchema::float("averageTotalPrice"),
+        ]
+    }
+
+    fn consumption_table_fields() -> Vec<TableFieldSchema> {
+        vec![
+            TableFieldSchema::string("id"),
+            TableFieldSchema::string("source"),
+            TableFieldSchema::string("homeId"),
+            TableFieldSchema::timestamp("from"),
+            TableFieldSchema::timestamp("till"),
+            TableFieldSchema::float("consumption"),
+            TableFieldSchema::string("consumptionUnit"),
+            TableFieldSchema::float("cost"),
+            TableFieldSchema::float("unitPrice"),
+            TableFieldSchema::string("currency"),
+            TableFieldSchema::string("recordType"),
+        ]
+    }
+
+    fn time_partitioning(&self) -> TimePartitioning {
+        let time_partitioning =
+            TimePartitioning::per_day().field(self.config.partition_field.column());
+
+        match self.config.partition_expiration_days {
+            Some(days) => {
+                time_partitioning.expiration_ms(time::Duration::from_secs(days * 24 * 60 * 60))
+            }
+            None => time_partitioning,
         }
+    }
 
-        let dataset = &self
-            .config
-            .client
-            .dataset()
-            .get(&self.config.project_id, &self.config.dataset)
-            .await?;
+    fn fields_match(actual: &[TableFieldSchema], expected: &[TableFieldSchema]) -> bool {
+        actual.len() == expected.len()
+            && actual.iter().zip(expected.iter()).all(|(a, e)| {
+                a.name == e.name
+                    && serde_json::to_value(&a.r#type).ok() == serde_json::to_value(&e.r#type).ok()
+            })
+    }
 
-        dataset
-            .create_table(
-                &self.config.client,
-                Table::from_dataset(
-                    dataset,
+    /// Returns whether the existing table's fields match the expected names and types.
+    pub async fn schema_matches(&self) -> Result<bool, ExporterError> {
+        let table = self
+            .with_retry(|| {
+                self.config.client.table().get(
+                    &self.config.project_id,
+                    &self.config.dataset,
                     &self.config.table,
-                    TableSchema::new(vec![
-                        TableFieldSchema::string("id"),
-                        TableFieldSchema::string("source"),
-                        TableFieldSchema::timestamp("from"),
-                        TableFieldSchema::timestamp("till"),
-                        TableFieldSchema::float("marketPrice"),
-                        TableFieldSchema::float("marketPriceTax"),
-                        TableFieldSchema::float("sourcingMarkupPrice"),
-                        TableFieldSchema::float("energyTaxPrice"),
-                    ]),
+                    None,
                 )
-                .time_partitioning(TimePartitioning::per_day().field("from")),
-            )
+            })
+            .await?;
+
+        let actual_fields = table.schema.fields.unwrap_or_default();
+
+        Ok(Self::fields_match(&actual_fields, &Self::table_fields()))
+    }
+
+    pub async fn create_table(&self, wait_ready: bool) -> Result<(), ExporterError> {
+        if !self.config.enable {
+            return Ok(());
+        }
+
+        let dataset = &self.get_dataset().await?;
+
+        let mut table = Table::from_dataset(
+            dataset,
+            &self.config.table,
+            TableSchema::new(Self::table_fields()),
+        )
+        .time_partitioning(self.time_partitioning());
+
+        // clustering can only be set at creation, existing tables keep their (lack of) clustering
+        if self.config.clustering_enable {
+            table.clustering = Some(Clustering {
+                fields: Some(vec!["source".to_string(), "from".to_string()]),
+            });
+        }
+
+        self.with_retry(|| dataset.create_table(&self.config.client, table.clone()))
             .await?;
 
         if wait_ready {
@@ -148,88 +540,925 @@ impl BigqueryClient {
         Ok(())
     }
 
-    pub async fn update_table_schema(&self) -> Result<(), Box<dyn Error>> {
+    pub async fn update_table_schema(&self) -> Result<(), ExporterError> {
         if !self.config.enable {
             return Ok(());
         }
 
+        let dataset = &self.get_dataset().await?;
+
+        self.with_retry(|| {
+            self.config.client.table().update(
+                &self.config.project_id,
+                &self.config.dataset,
+                &self.config.table,
+                Table::from_dataset(
+                    dataset,
+                    &self.config.table,
+                    TableSchema::new(Self::table_fields()),
+                )
+                .time_partitioning(self.time_partitioning()),
+            )
+        })
+        .await?;
+
+        info!("Updated schema for bigquery table {}", &self.config.table);
+
+        Ok(())
+    }
+
+    /// Deterministic insert id so BigQuery's best-effort dedup drops rows re-inserted by a retried
+    /// run.
+    fn insert_id(spot_price: &SpotPrice) -> String {
+        let mut insert_id = spot_price.source.clone().unwrap_or_default();
+        if let Some(home_id) = &spot_price.home_id {
+            insert_id = format!("{}-{}", insert_id, home_id);
+        }
+
+        format!("{}-{}", insert_id, spot_price.from.timestamp())
+    }
+
+    /// Describes the first row bigquery rejected, with its index and contents; the other rows in
+    /// the batch are only reported as stopped, because a single bad row fails the whole batch.
+    fn insert_errors_message<R: Serialize>(
+        table: &str,
+        insert_errors: &[TableDataInsertAllResponseInsertErrors],
+        rows: &[R],
+    ) -> String {
+        let is_stopped = |insert_error: &&TableDataInsertAllResponseInsertErrors| {
+            insert_error
+                .errors
+                .iter()
+                .all(|e| e.reason.as_deref() == Some("stopped"))
+        };
+        let first_failure = insert_errors
+            .iter()
+            .find(|insert_error| !is_stopped(insert_error))
+            .or_else(|| insert_errors.first());
+
+        let mut message = format!(
+            "Inserting into bigquery table {} returned {} insert errors",
+            table,
+            insert_errors.len()
+        );
+        if let Some(first_failure) = first_failure {
+            let reasons: Vec<String> = first_failure
+                .errors
+                .iter()
+                .map(|e| {
+                    format!(
+                        "{}: {}",
+                        e.reason.as_deref().unwrap_or("unknown"),
+                        e.message.as_deref().unwrap_or_default()
+                    )
+                })
+                .collect();
+            let row = rows
+                .get(first_failure.index)
+                .and_then(|row| serde_json::to_string(row).ok())
+                .unwrap_or_else(|| "unknown".to_string());
+
+            message = format!(
+                "{}, first failing row {} ({}): {}",
+                message,
+                first_failure.index,
+                reasons.join("; "),
+                row
+            );
+        }
+
+        message
+    }
+
+    pub async fn insert_spot_price(&self, spot_price: &SpotPrice) -> Result<(), ExporterError> {
         if !self.config.enable {
             return Ok(());
         }
 
-        let dataset = &self
+        let row = SpotPriceRow::from(spot_price);
+
+        let mut insert_request = TableDataInsertAllRequest::new();
+        insert_request.add_row(Some(Self::insert_id(spot_price)), &row)?;
+
+        if self.config.dry_run {
+            info!(
+                "Dry run, not inserting row {} into bigquery table {}",
+                serde_json::to_string(&row)?,
+                &self.config.table
+            );
+            return Ok(());
+        }
+
+        let insert_response = self
+            .with_retry(|| {
+                self.config.client.tabledata().insert_all(
+                    &self.config.project_id,
+                    &self.config.dataset,
+                    &self.config.table,
+                    insert_request.clone(),
+                )
+            })
+            .await?;
+
+        if let Some(insert_errors) = insert_response.insert_errors {
+            if !insert_errors.is_empty() {
+                error!(
+                    "Inserting spot price {:?} into bigquery table {} failed: {:?}",
+                    &spot_price, &self.config.table, insert_errors
+                );
+                return Err(ExporterError::BigQueryInsert(Self::insert_errors_message(
+                    &self.config.table,
+                    &insert_errors,
+                    &[&row],
+                )));
+            }
+        }
+
+        info!(
+            "Inserted spot price {:#?} into bigquery table {}",
+            &spot_price, &self.config.table
+        );
+
+        Ok(())
+    }
+
+    /// Merges staged rows into the target table on the `on` condition, so rows are never
+    /// duplicated, not even by overlapping runs.
+    fn merge_statement(
+        project_id: &str,
+        dataset: &str,
+        table: &str,
+        staging_table: &str,
+        fields: &[TableFieldSchema],
+        on: &str,
+    ) -> String {
+        let columns: Vec<String> = fields
+            .iter()
+            .map(|field| format!("`{}`", field.name))
+            .collect();
+
+        format!(
+            r#"MERGE `{project}.{dataset}.{table}` T
+USING `{project}.{dataset}.{staging_table}` S
+ON {on}
+WHEN MATCHED THEN
+  UPDATE SET {updates}
+WHEN NOT MATCHED THEN
+  INSERT ({columns}) VALUES ({columns})"#,
+            project = project_id,
+            dataset = dataset,
+            table = table,
+            staging_table = staging_table,
+            on = on,
+            updates = columns
+                .iter()
+                .map(|column| format!("{} = S.{}", column, column))
+                .collect::<Vec<String>>()
+                .join(", "),
+            columns = columns.join(", "),
+        )
+    }
+
+    /// Merges spot prices on source, home and hour.
+    pub async fn merge_spot_prices(&self, spot_prices: &[SpotPrice]) -> Result<(), ExporterError> {
+        if !self.config.enable || spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        let rows: Vec<(String, SpotPriceRow)> = spot_prices
+            .iter()
+            .map(|spot_price| (Self::insert_id(spot_price), SpotPriceRow::from(spot_price)))
+            .collect();
+        self.merge_rows(
+            &self.config.table,
+            Self::table_fields(),
+            SPOT_PRICE_MERGE_CONDITION,
+            &rows,
+        )
+        .await?;
+
+        info!(
+            "Merged {} spot prices into bigquery table {}",
+            spot_prices.len(),
+            &self.config.table
+        );
+
+        Ok(())
+    }
+
+    /// Stages rows, along with their insert ids, in a temporary table with the given schema and
+    /// merges them into `table`.
+    async fn merge_rows<R: Serialize>(
+        &self,
+        table: &str,
+        fields: Vec<TableFieldSchema>,
+        on: &str,
+        rows: &[(String, R)],
+    ) -> Result<(), ExporterError> {
+        let staging_table = format!("{}_staging_{}", table, Uuid::new_v4().simple());
+        let merge_statement = Self::merge_statement(
+            &self.config.project_id,
+            &self.config.dataset,
+            table,
+            &staging_table,
+            &fields,
+            on,
+        );
+
+        if self.config.dry_run {
+            info!(
+                "Dry run, not merging {} rows into bigquery table {}:\n{}",
+                rows.len(),
+                table,
+                merge_statement
+            );
+            return Ok(());
+        }
+
+        let dataset = &self.get_dataset().await?;
+
+        // expires by itself in case the run fails before dropping it
+        let staging = Table::from_dataset(dataset, &staging_table, TableSchema::new(fields))
+            .expiration_time(time::SystemTime::now() + time::Duration::from_secs(60 * 60));
+
+        self.with_retry(|| dataset.create_table(&self.config.client, staging.clone()))
+            .await?;
+
+        let result = self
+            .stage_and_merge(&staging_table, &merge_statement, rows)
+            .await;
+
+        if let Err(e) = self
             .config
             .client
-            .dataset()
-            .get(&self.config.project_id, &self.config.dataset)
+            .table()
+            .delete(
+                &self.config.project_id,
+                &self.config.dataset,
+                &staging_table,
+            )
+            .await
+        {
+            error!(
+                "Dropping bigquery staging table {} failed: {}",
+                staging_table, e
+            );
+        }
+
+        result
+    }
+
+    /// Stages the rows in a temporary table and merges them into the target table; slower than
+    /// streaming inserts, but not subject to their best-effort dedup.
+    async fn stage_and_merge<R: Serialize>(
+        &self,
+        staging_table: &str,
+        merge_statement: &str,
+        rows: &[(String, R)],
+    ) -> Result<(), ExporterError> {
+        let mut insert_request = TableDataInsertAllRequest::new();
+        for (insert_id, row) in rows {
+            insert_request.add_row(Some(insert_id.clone()), row)?;
+        }
+
+        let insert_response = self
+            .with_retry(|| {
+                self.config.client.tabledata().insert_all(
+                    &self.config.project_id,
+                    &self.config.dataset,
+                    staging_table,
+                    insert_request.clone(),
+                )
+            })
             .await?;
 
-        self.config
+        if let Some(insert_errors) = insert_response.insert_errors {
+            if !insert_errors.is_empty() {
+                error!(
+                    "Staging rows in bigquery table {} failed: {:?}",
+                    staging_table, insert_errors
+                );
+                let staged_rows: Vec<&R> = rows.iter().map(|(_, row)| row).collect();
+                return Err(ExporterError::BigQueryInsert(Self::insert_errors_message(
+                    staging_table,
+                    &insert_errors,
+                    &staged_rows,
+                )));
+            }
+        }
+
+        self.with_retry(|| {
+            self.config.client.job().query(
+                &self.config.project_id,
+                Self::query_request(self.config.location.as_deref(), merge_statement),
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Matches the rows of the source and of its accounts, which are stored as `{source}-{account}`.
+    fn source_filter(source: &str) -> String {
+        let source = source.replace('\\', "\\\\").replace('\'', "\\'");
+
+        format!(
+            "(source = '{}' OR STARTS_WITH(source, '{}-'))",
+            source, source
+        )
+    }
+
+    fn existing_spot_price_keys_query(
+        project_id: &str,
+        dataset: &str,
+        table: &str,
+        source: &str,
+        since: DateTime<Utc>,
+    ) -> String {
+        format!(
+            "SELECT DISTINCT IFNULL(source, '') AS source, IFNULL(homeId, '') AS home_id, UNIX_SECONDS(`from`) AS from_seconds FROM `{}.{}.{}` WHERE {} AND `from` >= TIMESTAMP_SECONDS({})",
+            project_id,
+            dataset,
+            table,
+            Self::source_filter(source),
+            since.timestamp()
+        )
+    }
+
+    /// Returns the source, home and `from` of the rows of the source stored since `since`, so
+    /// already present prices aren't inserted again when the state got lost; other sources and
+    /// homes sharing the table don't count.
+    pub async fn existing_spot_price_keys(
+        &self,
+        source: &str,
+        since: DateTime<Utc>,
+    ) -> Result<HashSet<StoredSpotPriceKey>, ExporterError> {
+        if !self.config.enable || self.config.dry_run || !self.check_if_table_exists().await {
+            return Ok(HashSet::new());
+        }
+
+        let query = Self::existing_spot_price_keys_query(
+            &self.config.project_id,
+            &self.config.dataset,
+            &self.config.table,
+            source,
+            since,
+        );
+
+        let mut result_set = self
+            .with_retry(|| {
+                self.config.client.job().query(
+                    &self.config.project_id,
+                    Self::query_request(self.config.location.as_deref(), &query),
+                )
+            })
+            .await?;
+
+        let mut keys = HashSet::new();
+        while result_set.next_row() {
+            let source = result_set.get_string_by_name("source")?.unwrap_or_default();
+            let home_id = result_set
+                .get_string_by_name("home_id")?
+                .unwrap_or_default();
+            if let Some(from_seconds) = result_set.get_i64_by_name("from_seconds")? {
+                if let Some(from) = Utc.timestamp_opt(from_seconds, 0).single() {
+                    keys.insert(StoredSpotPriceKey {
+                        source,
+                        home_id,
+                        from,
+                    });
+                }
+            }
+        }
+
+        info!(
+            "Found {} existing rows since {} in bigquery table {}",
+            keys.len(),
+            since.to_rfc3339(),
+            &self.config.table
+        );
+
+        Ok(keys)
+    }
+
+    /// Takes the least recent of the latest `from` per account, so an account that fell behind
+    /// gets caught up; accounts are stored as `{source}-{account}`.
+    fn last_from_query(project_id: &str, dataset: &str, table: &str, source: &str) -> String {
+        format!(
+            "SELECT UNIX_SECONDS(MIN(last_from)) AS last_from_seconds FROM (SELECT MAX(`from`) AS last_from FROM `{}.{}.{}` WHERE {} GROUP BY source)",
+            project_id,
+            dataset,
+            table,
+            Self::source_filter(source)
+        )
+    }
+
+    /// Returns the latest `from` stored for the source, so the table itself can serve as state;
+    /// none if nothing is stored yet.
+    pub async fn last_from(&self, source: &str) -> Result<Option<DateTime<Utc>>, ExporterError> {
+        if !self.config.enable || self.config.dry_run || !self.check_if_table_exists().await {
+            return Ok(None);
+        }
+
+        let query = Self::last_from_query(
+            &self.config.project_id,
+            &self.config.dataset,
+            &self.config.table,
+            source,
+        );
+
+        let mut result_set = self
+            .with_retry(|| {
+                self.config.client.job().query(
+                    &self.config.project_id,
+                    Self::query_request(self.config.location.as_deref(), &query),
+                )
+            })
+            .await?;
+
+        let mut last_from = None;
+        if result_set.next_row() {
+            if let Some(last_from_seconds) = result_set.get_i64_by_name("last_from_seconds")? {
+                last_from = Utc.timestamp_opt(last_from_seconds, 0).single();
+            }
+        }
+
+        Ok(last_from)
+    }
+
+    fn is_location_allowed(location: &str, allowed_locations: &[String]) -> bool {
+        allowed_locations.is_empty()
+            || allowed_locations
+                .iter()
+                .any(|l| l.eq_ignore_ascii_case(location))
+    }
+
+    /// Without explicitly allowed locations the dataset is expected to be in the configured location.
+    fn expected_locations(location: Option<&str>, allowed_locations: &[String]) -> Vec<String> {
+        if !allowed_locations.is_empty() {
+            return allowed_locations.to_vec();
+        }
+
+        location.map(|l| vec![l.to_string()]).unwrap_or_default()
+    }
+
+    pub async fn check_data_residency(&self) -> Result<(), ExporterError> {
+        let allowed_locations = Self::expected_locations(
+            self.config.location.as_deref(),
+            &self.config.allowed_locations,
+        );
+
+        if !self.config.enable || self.config.dry_run || allowed_locations.is_empty() {
+            return Ok(());
+        }
+
+        let dataset = self.get_dataset().await?;
+
+        let location = dataset.location.unwrap_or_default();
+
+        if !Self::is_location_allowed(&location, &allowed_locations) {
+            error!(
+                "Bigquery dataset {} is in location {}, allowed locations are {:?}",
+                &self.config.dataset, location, allowed_locations
+            );
+            return Err(ExporterError::Config(format!(
+                "Bigquery dataset {} location {} is not allowed",
+                &self.config.dataset, location
+            )));
+        }
+
+        info!(
+            "Bigquery dataset {} is in allowed location {}",
+            &self.config.dataset, location
+        );
+
+        Ok(())
+    }
+
+    /// Creates the daily summaries table when it doesn't exist yet.
+    pub async fn init_daily_table(&self) -> Result<(), ExporterError> {
+        if !self.config.enable || !self.config.init || !self.config.daily_enable {
+            return Ok(());
+        }
+
+        if self.config.dry_run {
+            info!(
+                "Dry run, not initializing bigquery table {}",
+                &self.config.daily_table
+            );
+            return Ok(());
+        }
+
+        let exists = self
+            .config
             .client
             .table()
-            .update(
+            .get(
                 &self.config.project_id,
                 &self.config.dataset,
-                &self.config.table,
-                Table::from_dataset(
-                    dataset,
-                    &self.config.table,
-                    TableSchema::new(vec![
-                        TableFieldSchema::string("id"),
-                        TableFieldSchema::string("source"),
-                        TableFieldSchema::timestamp("from"),
-                        TableFieldSchema::timestamp("till"),
-                        TableFieldSchema::float("marketPrice"),
-                        TableFieldSchema::float("marketPriceTax"),
-                        TableFieldSchema::float("sourcingMarkupPrice"),
-                        TableFieldSchema::float("energyTaxPrice"),
-                    ]),
-                )
-                .time_partitioning(TimePartitioning::per_day().field("from")),
+                &self.config.daily_table,
+                None,
             )
+            .await
+            .is_ok();
+        if exists {
+            return Ok(());
+        }
+
+        let dataset = &self.get_dataset().await?;
+        let table = Table::from_dataset(
+            dataset,
+            &self.config.daily_table,
+            TableSchema::new(Self::daily_table_fields()),
+        )
+        .time_partitioning(TimePartitioning::per_day().field("date"));
+
+        self.with_retry(|| dataset.create_table(&self.config.client, table.clone()))
             .await?;
 
-        info!("Updated schema for bigquery table {}", &self.config.table);
+        info!("Created bigquery table {}", &self.config.daily_table);
 
         Ok(())
     }
 
-    pub async fn insert_spot_price(&self, spot_price: &SpotPrice) -> Result<(), Box<dyn Error>> {
-        if !self.config.enable {
+    /// Upserts daily summaries into the daily table on source, home and day, so the summary of a
+    /// day that gets written again replaces the earlier one.
+    pub async fn insert_daily_summaries(
+        &self,
+        daily_summaries: &[DailyPriceSummary],
+    ) -> Result<(), ExporterError> {
+        if !self.config.enable || !self.config.daily_enable || daily_summaries.is_empty() {
             return Ok(());
         }
 
-        let mut insert_request = TableDataInsertAllRequest::new();
-        insert_request.add_row(None, spot_price)?;
+        let rows: Vec<(String, &DailyPriceSummary)> = daily_summaries
+            .iter()
+            .map(|daily_summary| {
+                (
+                    format!(
+                        "{}-{}-{}",
+                        daily_summary.source.as_deref().unwrap_or_default(),
+                        daily_summary.home_id.as_deref().unwrap_or_default(),
+                        daily_summary.date
+                    ),
+                    daily_summary,
+                )
+            })
+            .collect();
+        self.merge_rows(
+            &self.config.daily_table,
+            Self::daily_table_fields(),
+            DAILY_SUMMARY_MERGE_CONDITION,
+            &rows,
+        )
+        .await?;
+
+        info!(
+            "Merged {} daily summaries into bigquery table {}",
+            daily_summaries.len(),
+            &self.config.daily_table
+        );
+
+        Ok(())
+    }
+
+    /// Creates the consumption table when it doesn't exist yet, or adds the columns it misses.
+    pub async fn init_consumption_table(&self) -> Result<(), ExporterError> {
+        if !self.config.enable || !self.config.init || !self.config.consumption_enable {
+            return Ok(());
+        }
+
+        if self.config.dry_run {
+            info!(
+                "Dry run, not initializing bigquery table {}",
+                &self.config.consumption_table
+            );
+            return Ok(());
+        }
+
+        let dataset = &self.get_dataset().await?;
+        let table = Table::from_dataset(
+            dataset,
+            &self.config.consumption_table,
+            TableSchema::new(Self::consumption_table_fields()),
+        )
+        .time_partitioning(TimePartitioning::per_day().field("from"));
+
+        if !self.check_if_consumption_table_exists().await {
+            self.with_retry(|| dataset.create_table(&self.config.client, table.clone()))
+                .await?;
+
+            info!("Created bigquery table {}", &self.config.consumption_table);
+
+            return Ok(());
+        }
+
+        // columns are only ever added, so existing tables get migrated in place
+        let existing_table = self
+            .with_retry(|| {
+                self.config.client.table().get(
+                    &self.config.project_id,
+                    &self.config.dataset,
+                    &self.config.consumption_table,
+                    None,
+                )
+            })
+            .await?;
+        if !Self::fields_match(
+            &existing_table.schema.fields.unwrap_or_default(),
+            &Self::consumption_table_fields(),
+        ) {
+            self.with_retry(|| {
+                self.config.client.table().update(
+                    &self.config.project_id,
+                    &self.config.dataset,
+                    &self.config.consumption_table,
+                    table.clone(),
+                )
+            })
+            .await?;
+
+            info!(
+                "Updated schema for bigquery table {}",
+                &self.config.consumption_table
+            );
+        }
+
+        Ok(())
+    }
 
+    async fn check_if_consumption_table_exists(&self) -> bool {
         self.config
             .client
-            .tabledata()
-            .insert_all(
+            .table()
+            .get(
                 &self.config.project_id,
                 &self.config.dataset,
-                &self.config.table,
-                insert_request,
+                &self.config.consumption_table,
+                None,
             )
+            .await
+            .is_ok()
+    }
+
+    /// Returns the home and `from` of all consumption stored since `since`; consumption is
+    /// retrieved for a sliding window of hours, so most of it is already stored by a previous run.
+    async fn existing_consumption_keys(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<HashSet<(String, DateTime<Utc>)>, ExporterError> {
+        if self.config.dry_run || !self.check_if_consumption_table_exists().await {
+            return Ok(HashSet::new());
+        }
+
+        let query = format!(
+            "SELECT DISTINCT IFNULL(homeId, '') AS home_id, UNIX_SECONDS(`from`) AS from_seconds FROM `{}.{}.{}` WHERE `from` >= TIMESTAMP_SECONDS({})",
+            self.config.project_id,
+            self.config.dataset,
+            self.config.consumption_table,
+            since.timestamp()
+        );
+
+        let mut result_set = self
+            .with_retry(|| {
+                self.config.client.job().query(
+                    &self.config.project_id,
+                    Self::query_request(self.config.location.as_deref(), &query),
+                )
+            })
             .await?;
 
+        let mut keys = HashSet::new();
+        while result_set.next_row() {
+            let home_id = result_set
+                .get_string_by_name("home_id")?
+                .unwrap_or_default();
+            if let Some(from_seconds) = result_set.get_i64_by_name("from_seconds")? {
+                if let Some(from) = Utc.timestamp_opt(from_seconds, 0).single() {
+                    keys.insert((home_id, from));
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Inserts consumption that isn't stored yet into the consumption table.
+    pub async fn insert_consumption(
+        &self,
+        consumption: &[Consumption],
+    ) -> Result<(), ExporterError> {
+        if !self.config.enable || !self.config.consumption_enable {
+            return Ok(());
+        }
+
+        let since = match consumption.iter().map(|c| c.from).min() {
+            Some(since) => since,
+            None => return Ok(()),
+        };
+        let existing_keys = self.existing_consumption_keys(since).await?;
+        let new_consumption: Vec<&Consumption> = consumption
+            .iter()
+            .filter(|c| !existing_keys.contains(&(c.home_id.clone().unwrap_or_default(), c.from)))
+            .collect();
+
+        if new_consumption.is_empty() {
+            info!(
+                "All consumption is already stored in bigquery table {}",
+                &self.config.consumption_table
+            );
+            return Ok(());
+        }
+
+        let mut insert_request = TableDataInsertAllRequest::new();
+        for c in &new_consumption {
+            let insert_id = format!(
+                "{}-{}-{}",
+                c.source.as_deref().unwrap_or_default(),
+                c.home_id.as_deref().unwrap_or_default(),
+                c.from.timestamp()
+            );
+            insert_request.add_row(Some(insert_id), c)?;
+        }
+
+        if self.config.dry_run {
+            info!(
+                "Dry run, not inserting consumption {} into bigquery table {}",
+                serde_json::to_string(&new_consumption)?,
+                &self.config.consumption_table
+            );
+            return Ok(());
+        }
+
+        let insert_response = self
+            .with_retry(|| {
+                self.config.client.tabledata().insert_all(
+                    &self.config.project_id,
+                    &self.config.dataset,
+                    &self.config.consumption_table,
+                    insert_request.clone(),
+                )
+            })
+            .await?;
+
+        if let Some(insert_errors) = insert_response.insert_errors {
+            if !insert_errors.is_empty() {
+                error!(
+                    "Inserting consumption into bigquery table {} failed: {:?}",
+                    &self.config.consumption_table, insert_errors
+                );
+                return Err(ExporterError::BigQueryInsert(Self::insert_errors_message(
+                    &self.config.consumption_table,
+                    &insert_errors,
+                    &new_consumption,
+                )));
+            }
+        }
+
         info!(
-            "Inserted spot price {:#?} into bigquery table {}",
-            &spot_price, &self.config.table
+            "Inserted {} consumption rows into bigquery table {}",
+            new_consumption.len(),
+            &self.config.consumption_table
         );
 
         Ok(())
     }
 
-    pub async fn init_table(&self) -> Result<(), Box<dyn Error>> {
+    /// Runs requests through the circuit breaker, failing fast while it's open; only failing
+    /// requests count towards opening it, rows bigquery rejects don't.
+    async fn guarded<T, F>(&self, request: F) -> Result<T, ExporterError>
+    where
+        F: Future<Output = Result<T, ExporterError>>,
+    {
+        self.config.circuit_breaker.check()?;
+
+        let result = request.await;
+        self.config
+            .circuit_breaker
+            .record(!matches!(result, Err(ExporterError::BigQuery(_))));
+
+        result
+    }
+
+    pub async fn init_table(&self) -> Result<(), ExporterError> {
         if !self.config.enable || !self.config.init {
             return Ok(());
         }
 
+        if self.config.dry_run {
+            info!(
+                "Dry run, not initializing bigquery table {}",
+                &self.config.table
+            );
+            return Ok(());
+        }
+
         if !self.check_if_table_exists().await {
             self.create_table(true).await?
-        } else {
+        } else if !self.schema_matches().await? {
             self.update_table_schema().await?
+        } else {
+            info!(
+                "Schema for bigquery table {} is up to date",
+                &self.config.table
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Creates the dataset in the configured location if it doesn't exist yet, like missing tables
+    /// are created.
+    async fn init_dataset(&self) -> Result<(), ExporterError> {
+        if !self.config.enable || !self.config.init || self.config.dry_run {
+            return Ok(());
+        }
+
+        match self.get_dataset().await {
+            Ok(_) => Ok(()),
+            Err(BQError::ResponseError { error }) if error.error.code == 404 => {
+                let mut dataset = Dataset::new(&self.config.project_id, &self.config.dataset);
+                if let Some(location) = &self.config.location {
+                    dataset = dataset.location(location);
+                }
+
+                self.with_retry(|| self.config.client.dataset().create(dataset.clone()))
+                    .await?;
+
+                info!(
+                    "Created bigquery dataset {} in location {}",
+                    &self.config.dataset,
+                    self.config.location.as_deref().unwrap_or("US")
+                );
+
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Checks the data residency and creates or migrates the dataset and all tables; runs every
+    /// time the sink is initialized, so it's guarded as a whole.
+    async fn init_tables(&self) -> Result<(), ExporterError> {
+        self.init_dataset().await?;
+        self.check_data_residency().await?;
+        self.init_table().await?;
+        self.init_daily_table().await?;
+        self.init_consumption_table().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for BigqueryClient {
+    fn name(&self) -> &str {
+        "bigquery"
+    }
+
+    async fn init(&self) -> Result<(), Box<dyn Error>> {
+        Ok(self.guarded(self.init_tables()).await?)
+    }
+
+    async fn existing_spot_price_keys(
+        &self,
+        source: &str,
+        since: DateTime<Utc>,
+    ) -> Result<HashSet<StoredSpotPriceKey>, Box<dyn Error>> {
+        Ok(self
+            .guarded(BigqueryClient::existing_spot_price_keys(
+                self, source, since,
+            ))
+            .await?)
+    }
+
+    async fn insert_consumption(&self, consumption: &[Consumption]) -> Result<(), Box<dyn Error>> {
+        Ok(self
+            .guarded(BigqueryClient::insert_consumption(self, consumption))
+            .await?)
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if self.config.write_mode == BigqueryWriteMode::Merge {
+            self.guarded(self.merge_spot_prices(spot_prices)).await?;
+        } else {
+            for spot_price in spot_prices {
+                self.guarded(self.insert_spot_price(spot_price)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Summarizes all prices of the run rather than the new ones, so a day that gets published
+    /// over multiple runs isn't summarized from part of its prices.
+    async fn write_snapshot(&self, snapshot: &SpotPriceSnapshot<'_>) -> Result<(), Box<dyn Error>> {
+        if self.config.daily_enable {
+            self.guarded(self.insert_daily_summaries(&SpotPrice::aggregate_daily(
+                snapshot.spot_prices,
+                self.config.daily_time_zone,
+            )))
+            .await?;
         }
 
         Ok(())
@@ -239,6 +1468,295 @@ impl BigqueryClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use gcp_bigquery_client::model::error_proto::ErrorProto;
+
+    #[test]
+    fn resolve_table_name() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            BigqueryClient::resolve_table_name("prices_{source}", "tibber")?,
+            "prices_tibber"
+        );
+        assert_eq!(
+            BigqueryClient::resolve_table_name("prices", "tibber")?,
+            "prices"
+        );
+        assert!(BigqueryClient::resolve_table_name("prices_{source}", "tibber.nl").is_err());
+        assert!(BigqueryClient::resolve_table_name("{source}", "").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_id() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            id: Some("a8d1e3b6-54a1-4b8b-b3a4-4d0a4c1e2f00".to_string()),
+            source: Some("tibber".to_string()),
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        assert_eq!(BigqueryClient::insert_id(&spot_price), "tibber-1651363200");
+        assert_eq!(
+            BigqueryClient::insert_id(&SpotPrice {
+                home_id: Some("home-1".to_string()),
+                ..spot_price
+            }),
+            "tibber-home-1-1651363200"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_price_row_includes_total_price() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            source: Some("tibber".to_string()),
+            market_price: 0.25,
+            market_price_tax: 0.0625,
+            energy_tax_price: 0.125,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        // act
+        let row = serde_json::to_value(SpotPriceRow::from(&spot_price))?;
+
+        assert_eq!(row["totalPrice"], 0.4375);
+        assert_eq!(row["marketPrice"], 0.25);
+        assert_eq!(row["source"], "tibber");
+
+        Ok(())
+    }
+
+    #[test]
+    fn fields_match() {
+        let expected_fields = BigqueryClient::table_fields();
+
+        assert!(BigqueryClient::fields_match(
+            &BigqueryClient::table_fields(),
+            &expected_fields
+        ));
+        assert!(!BigqueryClient::fields_match(
+            &expected_fields[..expected_fields.len() - 1],
+            &expected_fields
+        ));
+
+        let mut retyped_fields = BigqueryClient::table_fields();
+        retyped_fields[4] = TableFieldSchema::string("marketPrice");
+        assert!(!BigqueryClient::fields_match(
+            &retyped_fields,
+            &expected_fields
+        ));
+    }
+
+    #[test]
+    fn expected_locations() {
+        assert_eq!(
+            BigqueryClient::expected_locations(Some("europe-west4"), &[]),
+            vec!["europe-west4".to_string()]
+        );
+        assert_eq!(
+            BigqueryClient::expected_locations(Some("europe-west4"), &["EU".to_string()]),
+            vec!["EU".to_string()]
+        );
+        assert!(BigqueryClient::expected_locations(None, &[]).is_empty());
+    }
+
+    #[test]
+    fn is_location_allowed() {
+        let allowed_locations = vec!["EU".to_string(), "europe-west4".to_string()];
+
+        assert!(BigqueryClient::is_location_allowed(
+            "EU",
+            &allowed_locations
+        ));
+        assert!(BigqueryClient::is_location_allowed(
+            "EUROPE-WEST4",
+            &allowed_locations
+        ));
+        assert!(!BigqueryClient::is_location_allowed(
+            "US",
+            &allowed_locations
+        ));
+        assert!(BigqueryClient::is_location_allowed("US", &[]));
+    }
+
+    fn response_error(code: i64) -> Result<BQError, Box<dyn Error>> {
+        Ok(BQError::ResponseError {
+            error: serde_json::from_str(&format!(
+                r#"{{"error":{{"code":{},"errors":[],"message":"","status":""}}}}"#,
+                code
+            ))?,
+        })
+    }
+
+    #[test]
+    fn is_retryable() -> Result<(), Box<dyn Error>> {
+        assert!(BigqueryClient::is_retryable(&response_error(500)?));
+        assert!(BigqueryClient::is_retryable(&response_error(503)?));
+        assert!(BigqueryClient::is_retryable(&response_error(429)?));
+        assert!(!BigqueryClient::is_retryable(&response_error(400)?));
+        assert!(!BigqueryClient::is_retryable(&response_error(404)?));
+        assert!(!BigqueryClient::is_retryable(&BQError::NoDataAvailable));
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_statement() {
+        // act
+        let statement = BigqueryClient::merge_statement(
+            "my-project",
+            "jarvis",
+            "spot_prices",
+            "spot_prices_staging",
+            &BigqueryClient::table_fields(),
+            SPOT_PRICE_MERGE_CONDITION,
+        );
+
+        assert!(statement.starts_with(
+            "MERGE `my-project.jarvis.spot_prices` T\nUSING `my-project.jarvis.spot_prices_staging` S\n"
+        ));
+        assert!(statement.contains("T.`from` = S.`from`"));
+        assert!(statement.contains("UPDATE SET `id` = S.`id`, `source` = S.`source`"));
+        assert!(statement.contains("INSERT (`id`, `source`, `from`,"));
+    }
+
+    #[test]
+    fn merge_statement_for_daily_summaries() {
+        // act
+        let statement = BigqueryClient::merge_statement(
+            "my-project",
+            "jarvis",
+            "spot_prices_daily",
+            "spot_prices_daily_staging",
+            &BigqueryClient::daily_table_fields(),
+            DAILY_SUMMARY_MERGE_CONDITION,
+        );
+
+        assert!(statement
+            .contains("IFNULL(T.homeId, '') = IFNULL(S.homeId, '') AND T.`date` = S.`date`"));
+        assert!(statement.contains("`count` = S.`count`"));
+        assert!(statement.contains("INSERT (`source`, `homeId`, `date`,"));
+    }
+
+    #[test]
+    fn insert_errors_message_describes_first_failing_row() {
+        let error = |reason: &str, message: &str| ErrorProto {
+            debug_info: None,
+            location: None,
+            message: Some(message.to_string()),
+            reason: Some(reason.to_string()),
+        };
+        let insert_errors = vec![
+            TableDataInsertAllResponseInsertErrors {
+                errors: vec![error("stopped", "")],
+                index: 0,
+            },
+            TableDataInsertAllResponseInsertErrors {
+                errors: vec![error("invalid", "Cannot convert value to floating point.")],
+                index: 1,
+            },
+        ];
+        let rows = vec![
+            serde_json::json!({"marketPrice": 0.2}),
+            serde_json::json!({"marketPrice": "NaN"}),
+        ];
+
+        // act
+        let message = BigqueryClient::insert_errors_message("spot_prices", &insert_errors, &rows);
+
+        assert_eq!(
+            message,
+            "Inserting into bigquery table spot_prices returned 2 insert errors, first failing row 1 (invalid: Cannot convert value to floating point.): {\"marketPrice\":\"NaN\"}"
+        );
+    }
+
+    #[test]
+    fn last_from_query_escapes_source() {
+        // act
+        let query =
+            BigqueryClient::last_from_query("my-project", "jarvis", "spot_prices", "tibber's");
+
+        assert_eq!(
+            query,
+            "SELECT UNIX_SECONDS(MIN(last_from)) AS last_from_seconds FROM (SELECT MAX(`from`) AS last_from FROM `my-project.jarvis.spot_prices` WHERE (source = 'tibber\\'s' OR STARTS_WITH(source, 'tibber\\'s-')) GROUP BY source)"
+        );
+    }
+
+    #[test]
+    fn existing_spot_price_keys_query_filters_on_source() -> Result<(), Box<dyn Error>> {
+        // act
+        let query = BigqueryClient::existing_spot_price_keys_query(
+            "my-project",
+            "jarvis",
+            "spot_prices",
+            "tibber",
+            "2022-05-01T00:00:00Z".parse()?,
+        );
+
+        assert_eq!(
+            query,
+            "SELECT DISTINCT IFNULL(source, '') AS source, IFNULL(homeId, '') AS home_id, UNIX_SECONDS(`from`) AS from_seconds FROM `my-project.jarvis.spot_prices` WHERE (source = 'tibber' OR STARTS_WITH(source, 'tibber-')) AND `from` >= TIMESTAMP_SECONDS(1651363200)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_write_mode() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            "stream".parse::<BigqueryWriteMode>()?,
+            BigqueryWriteMode::Stream
+        );
+        assert_eq!(
+            "MERGE".parse::<BigqueryWriteMode>()?,
+            BigqueryWriteMode::Merge
+        );
+        assert!("upsert".parse::<BigqueryWriteMode>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_partition_field() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            "till".parse::<BigqueryPartitionField>()?,
+            BigqueryPartitionField::Till
+        );
+        assert_eq!(" From ".parse::<BigqueryPartitionField>()?.column(), "from");
+        assert!("localDate".parse::<BigqueryPartitionField>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_request_runs_in_location() {
+        // act
+        let query_request = BigqueryClient::query_request(Some("europe-west4"), "SELECT 1");
+
+        assert_eq!(query_request.location.as_deref(), Some("europe-west4"));
+        assert_eq!(query_request.query, "SELECT 1");
+        assert!(BigqueryClient::query_request(None, "SELECT 1")
+            .location
+            .is_none());
+    }
+
+    #[test]
+    fn parse_location() {
+        assert_eq!(
+            BigqueryClient::parse_location(" europe-west4 ").as_deref(),
+            Some("europe-west4")
+        );
+        assert!(BigqueryClient::parse_location("").is_none());
+    }
+
+    #[test]
+    fn parse_allowed_locations() {
+        assert_eq!(
+            BigqueryClient::parse_allowed_locations(" EU , europe-west4,"),
+            vec!["EU".to_string(), "europe-west4".to_string()]
+        );
+        assert!(BigqueryClient::parse_allowed_locations("").is_empty());
+    }
 
     #[tokio::test]
     #[ignore]
@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+/// Version and git sha of the running binary, to tell which image is running when debugging.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("GIT_SHA"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn serialize() -> Result<(), Box<dyn Error>> {
+        let build_info = BuildInfo {
+            version: "0.1.0",
+            git_sha: "c0c7ea4",
+        };
+
+        // act
+        let json = serde_json::to_string(&build_info)?;
+
+        assert_eq!(json, r#"{"version":"0.1.0","gitSha":"c0c7ea4"}"#);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::ExporterError;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    /// Consecutive failures still within the window, oldest first.
+    failures: Vec<Instant>,
+    open_until: Option<Instant>,
+    /// Set once the cooldown passed; the next failure opens the circuit again right away.
+    half_open: bool,
+}
+
+/// Fails fast for a cooldown period after `failure_threshold` consecutive failures within
+/// `window`, so a dead downstream doesn't use up the retry budget of every call; a threshold of 0
+/// disables it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    failure_threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, failure_threshold: usize, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            failure_threshold,
+            window,
+            cooldown,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Returns an error while the circuit is open.
+    pub fn check(&self) -> Result<(), ExporterError> {
+        self.check_at(Instant::now())
+    }
+
+    /// Records the outcome of a call that was let through.
+    pub fn record(&self, success: bool) {
+        self.record_at(success, Instant::now())
+    }
+
+    fn check_at(&self, now: Instant) -> Result<(), ExporterError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        match state.open_until {
+            Some(open_until) if now < open_until => Err(ExporterError::CircuitOpen {
+                name: self.name.clone(),
+                retry_in: open_until - now,
+            }),
+            Some(_) => {
+                info!(
+                    "Cooldown of {} circuit breaker passed, trying again",
+                    self.name
+                );
+                state.open_until = None;
+                state.half_open = true;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record_at(&self, success: bool, now: Instant) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if success {
+            *state = CircuitState::default();
+            return;
+        }
+
+        let window = self.window;
+        state
+            .failures
+            .retain(|failure| now.saturating_duration_since(*failure) <= window);
+        state.failures.push(now);
+
+        if state.half_open || state.failures.len() >= self.failure_threshold {
+            warn!(
+                "Opening {} circuit breaker for {}s after {} consecutive failures",
+                self.name,
+                self.cooldown.as_secs(),
+                state.failures.len()
+            );
+            *state = CircuitState {
+                failures: vec![],
+                open_until: Some(now + self.cooldown),
+                half_open: false,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circuit_breaker() -> CircuitBreaker {
+        CircuitBreaker::new(
+            "bigquery",
+            3,
+            Duration::from_secs(60),
+            Duration::from_secs(300),
+        )
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_within_window() {
+        let circuit_breaker = circuit_breaker();
+        let now = Instant::now();
+
+        // act
+        for i in 0..3 {
+            circuit_breaker.record_at(false, now + Duration::from_secs(i));
+        }
+
+        let result = circuit_breaker.check_at(now + Duration::from_secs(10));
+        assert!(matches!(
+            result,
+            Err(ExporterError::CircuitOpen { retry_in, .. }) if retry_in == Duration::from_secs(292)
+        ));
+        assert!(circuit_breaker
+            .check_at(now + Duration::from_secs(303))
+            .is_ok());
+    }
+
+    #[test]
+    fn stays_closed_when_failures_are_interrupted_or_spread_out() {
+        let circuit_breaker = circuit_breaker();
+        let now = Instant::now();
+
+        // act
+        circuit_breaker.record_at(false, now);
+        circuit_breaker.record_at(false, now);
+        circuit_breaker.record_at(true, now);
+        circuit_breaker.record_at(false, now);
+        circuit_breaker.record_at(false, now + Duration::from_secs(120));
+        circuit_breaker.record_at(false, now + Duration::from_secs(121));
+
+        assert!(circuit_breaker
+            .check_at(now + Duration::from_secs(122))
+            .is_ok());
+    }
+
+    #[test]
+    fn reopens_on_first_failure_after_cooldown() {
+        let circuit_breaker = circuit_breaker();
+        let now = Instant::now();
+        for _ in 0..3 {
+            circuit_breaker.record_at(false, now);
+        }
+        let after_cooldown = now + Duration::from_secs(301);
+        assert!(circuit_breaker.check_at(after_cooldown).is_ok());
+
+        // act
+        circuit_breaker.record_at(false, after_cooldown);
+
+        assert!(circuit_breaker.check_at(after_cooldown).is_err());
+    }
+
+    #[test]
+    fn disabled_with_zero_threshold() {
+        let circuit_breaker = CircuitBreaker::new(
+            "bigquery",
+            0,
+            Duration::from_secs(60),
+            Duration::from_secs(300),
+        );
+        let now = Instant::now();
+
+        // act
+        for _ in 0..10 {
+            circuit_breaker.record_at(false, now);
+        }
+
+        assert!(circuit_breaker.check_at(now).is_ok());
+    }
+}
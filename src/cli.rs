@@ -0,0 +1,223 @@
+use clap::{Arg, ArgMatches, Command};
+use std::env;
+
+/// Env vars that toggle behaviour; their flags can be passed without a value to enable them.
+const SWITCHES: [&str; 22] = [
+    "ALERT_INCLUSIVE",
+    "BQ_CLUSTERING_ENABLE",
+    "BQ_DAILY_ENABLE",
+    "BQ_DRY_RUN",
+    "BQ_ENABLE",
+    "BQ_INIT",
+    "BQ_USE_DEFAULT_CREDENTIALS",
+    "COALESCE_EQUAL_PRICES",
+    "COMPUTE_PRICE_LEVEL",
+    "CONSUMPTION_ENABLE",
+    "EMIT_JSON",
+    "FAIL_ON_INVALID_PRICES",
+    "INFLUXDB_ENABLE",
+    "INIT_ONLY",
+    "KAFKA_ENABLE",
+    "METRICS_ENABLE",
+    "NEGATIVE_PRICE_ALERT_ENABLE",
+    "PARQUET_ENABLE",
+    "POSTGRES_HYPERTABLE_ENABLE",
+    "POSTGRES_INIT",
+    "PUBSUB_ORDERING_ENABLE",
+    "STATE_ENABLE",
+];
+
+const OPTIONS: [&str; 109] = [
+    "ALERT_ABOVE",
+    "ALERT_BELOW",
+    "ALERT_WEBHOOK_URL",
+    "APPLIANCE_DURATION_HOURS",
+    "APPLIANCE_KWH",
+    "BACKFILL_HOURS",
+    "BQ_ALLOWED_LOCATIONS",
+    "BQ_CIRCUIT_BREAKER_COOLDOWN_SECONDS",
+    "BQ_CIRCUIT_BREAKER_FAILURES",
+    "BQ_CIRCUIT_BREAKER_WINDOW_SECONDS",
+    "BQ_CONSUMPTION_TABLE",
+    "BQ_DAILY_TABLE",
+    "BQ_DAILY_TIME_ZONE",
+    "BQ_DATASET",
+    "BQ_LOCATION",
+    "BQ_PARTITION_EXPIRATION_DAYS",
+    "BQ_PARTITION_FIELD",
+    "BQ_PROJECT_ID",
+    "BQ_RETRIES",
+    "BQ_TABLE",
+    "BQ_WRITE_MODE",
+    "CHEAPEST_WINDOW_HOURS",
+    "CONFIG_FILE",
+    "CONSUMPTION_HOURS",
+    "CSV_CONSUMPTION_FILE_PATH",
+    "CSV_FILE_PATH",
+    "DATABASE_URL",
+    "ENERGY_TAX_PRICE",
+    "ENERGY_TAX_RATES_PATH",
+    "ENTSOE_API_TOKEN",
+    "ENTSOE_API_URL",
+    "ENTSOE_BIDDING_ZONE",
+    "ENTSOE_REQUEST_TIMEOUT_SECONDS",
+    "EXCHANGE_RATE_API_URL",
+    "FETCH_WINDOW",
+    "GOOGLE_APPLICATION_CREDENTIALS",
+    "HEALTH_PORT",
+    "ICAL_PATH",
+    "ICAL_WINDOW_HOURS",
+    "INFLUXDB_BUCKET",
+    "INFLUXDB_ORG",
+    "INFLUXDB_TOKEN",
+    "INFLUXDB_URL",
+    "KAFKA_BROKERS",
+    "KAFKA_DELIVERY_TIMEOUT_SECONDS",
+    "KAFKA_SASL_MECHANISM",
+    "KAFKA_SASL_PASSWORD",
+    "KAFKA_SASL_USERNAME",
+    "KAFKA_SECURITY_PROTOCOL",
+    "KAFKA_TOPIC",
+    "LAST_RUN_FILE",
+    "LOG_FORMAT",
+    "LOG_LEVEL",
+    "MAX_STALENESS",
+    "METRICS_PORT",
+    "MQTT_CHEAPEST_WINDOW_TOPIC",
+    "MQTT_FORECAST_TOPIC",
+    "MQTT_HOST",
+    "MQTT_PASSWORD",
+    "MQTT_PORT",
+    "MQTT_TOPIC",
+    "MQTT_USERNAME",
+    "NORDPOOL_API_URL",
+    "NORDPOOL_AREA",
+    "NORDPOOL_CURRENCY",
+    "NORDPOOL_REQUEST_TIMEOUT_SECONDS",
+    "PARQUET_DIR",
+    "POSTGRES_TABLE",
+    "PRICE_SOURCE",
+    "PROXY_URL",
+    "PUBSUB_API_URL",
+    "PUBSUB_PROJECT_ID",
+    "PUBSUB_TIMEOUT_SECONDS",
+    "PUBSUB_TOPIC",
+    "RETRY_BASE_MILLIS",
+    "RETRY_DEADLINE_SECONDS",
+    "RETRY_MAX_ATTEMPTS",
+    "RETRY_MAX_DELAY_MILLIS",
+    "RUN_INTERVAL_SECONDS",
+    "RUN_JITTER_SECONDS",
+    "RUN_MODE",
+    "RUN_TIMEOUT_SECONDS",
+    "SCHEDULE_CRON",
+    "SOURCE",
+    "SOURCING_MARKUP_PRICE",
+    "STARTUP_SPLAY_SECONDS",
+    "STATE_BACKEND",
+    "STATE_CONFLICT_RETRIES",
+    "STATE_FALLBACK_FILE_PATH",
+    "STATE_FILE_CONFIG_MAP_NAME",
+    "STATE_FILE_PATH",
+    "STATE_FUTURE_LOOKBACK_HOURS",
+    "STATE_GCS_API_URL",
+    "STATE_GCS_BUCKET",
+    "STATE_GCS_OBJECT",
+    "STATE_GCS_REQUEST_TIMEOUT_SECONDS",
+    "STATE_NAMESPACE",
+    "STATE_RESOURCE_KIND",
+    "TARGET_CURRENCY",
+    "TIBBER_ACCESS_TOKEN",
+    "TIBBER_ACCESS_TOKEN_FILE",
+    "TIBBER_API_URL",
+    "TIBBER_HOME_ID",
+    "TIBBER_POOL_IDLE_TIMEOUT_SECONDS",
+    "TIBBER_POOL_MAX_IDLE_PER_HOST",
+    "TIBBER_REQUEST_TIMEOUT_SECONDS",
+    "TOMORROW_RETRY_INTERVAL_SECONDS",
+    "WEBHOOK_TIMEOUT_SECONDS",
+    "WEBHOOK_TOKEN",
+];
+
+/// Turns an env var name into its flag, e.g. BQ_DRY_RUN into bq-dry-run.
+fn flag_name(env_var: &str) -> String {
+    env_var.to_lowercase().replace('_', "-")
+}
+
+fn command() -> Command {
+    let switches = SWITCHES.iter().map(|env_var| {
+        Arg::new(*env_var)
+            .long(flag_name(env_var))
+            .env(*env_var)
+            .num_args(0..=1)
+            .default_missing_value("true")
+    });
+    let options = OPTIONS.iter().map(|env_var| {
+        Arg::new(*env_var)
+            .long(flag_name(env_var))
+            .env(*env_var)
+            .hide_env_values(true)
+    });
+
+    Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(switches)
+        .args(options)
+}
+
+/// Returns the env vars with the value passed as flag, or taken over from the environment.
+fn env_values(matches: &ArgMatches) -> Vec<(&'static str, String)> {
+    SWITCHES
+        .iter()
+        .chain(OPTIONS.iter())
+        .filter_map(|env_var| {
+            matches
+                .get_one::<String>(env_var)
+                .map(|value| (*env_var, value.clone()))
+        })
+        .collect()
+}
+
+/// Parses the command line arguments and exports them as env vars, so all clients keep being
+/// configured through their `from_env` functions; a flag takes precedence over the env var.
+pub fn apply_args_to_env() {
+    let matches = command().get_matches();
+
+    for (env_var, value) in env_values(&matches) {
+        env::set_var(env_var, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn env_values() -> Result<(), Box<dyn Error>> {
+        // act
+        let matches = command().try_get_matches_from([
+            "jarvis-tibber-price-exporter",
+            "--source",
+            "test",
+            "--bq-dry-run",
+            "--init-only",
+            "--backfill-hours",
+            "48",
+        ])?;
+
+        let env_values = super::env_values(&matches);
+
+        assert!(env_values.contains(&("SOURCE", "test".to_string())));
+        assert!(env_values.contains(&("BQ_DRY_RUN", "true".to_string())));
+        assert!(env_values.contains(&("INIT_ONLY", "true".to_string())));
+        assert!(env_values.contains(&("BACKFILL_HOURS", "48".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn flag_name() {
+        assert_eq!(super::flag_name("BQ_DRY_RUN"), "bq-dry-run");
+    }
+}
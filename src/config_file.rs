@@ -0,0 +1,178 @@
+use crate::error::ExporterError;
+use serde_yaml::Value;
+use std::env;
+use std::fs;
+
+/// Reads the yaml or toml file set in CONFIG_FILE and exports its values as env vars, so all
+/// clients keep being configured through their `from_env` functions. Env vars that are already set
+/// take precedence over the file.
+///
+/// Nested keys are joined with underscores and uppercased, so `bq: { dry_run: true }` sets
+/// BQ_DRY_RUN; lists of values are joined with commas.
+pub fn apply_config_file_to_env() -> Result<(), ExporterError> {
+    let config_file = env::var("CONFIG_FILE").unwrap_or_default();
+    if config_file.is_empty() {
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(&config_file)?;
+    let value = parse(&contents, config_file.ends_with(".toml"))?;
+
+    for (env_var, env_value) in env_values(&value)? {
+        if env::var(&env_var).is_err() {
+            env::set_var(env_var, env_value);
+        }
+    }
+
+    Ok(())
+}
+
+fn parse(contents: &str, is_toml: bool) -> Result<Value, ExporterError> {
+    if is_toml {
+        let value: toml::Value = toml::from_str(contents)
+            .map_err(|e| ExporterError::Config(format!("Config file is not valid toml: {}", e)))?;
+
+        Ok(serde_yaml::to_value(value)?)
+    } else {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+}
+
+fn env_values(value: &Value) -> Result<Vec<(String, String)>, ExporterError> {
+    let mut env_values = vec![];
+    flatten("", value, &mut env_values)?;
+
+    Ok(env_values)
+}
+
+fn flatten(
+    prefix: &str,
+    value: &Value,
+    env_values: &mut Vec<(String, String)>,
+) -> Result<(), ExporterError> {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = scalar(key).ok_or_else(|| {
+                    ExporterError::Config(format!(
+                        "Config file key under {} is not a string",
+                        prefix
+                    ))
+                })?;
+                let env_var = if prefix.is_empty() {
+                    key.to_uppercase()
+                } else {
+                    format!("{}_{}", prefix, key.to_uppercase())
+                };
+
+                flatten(&env_var, value, env_values)?;
+            }
+        }
+        Value::Sequence(sequence) => {
+            let values = sequence
+                .iter()
+                .map(scalar)
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| {
+                    ExporterError::Config(format!(
+                        "Config file value for {} can only be a list of plain values",
+                        prefix
+                    ))
+                })?;
+
+            env_values.push((prefix.to_string(), values.join(",")));
+        }
+        Value::Null => {}
+        value => {
+            if let Some(value) = scalar(value) {
+                env_values.push((prefix.to_string(), value));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn env_values_from_yaml_and_toml_match() -> Result<(), Box<dyn Error>> {
+        let yaml = r#"
+source: tibber
+sourcing_markup_price: 0.0175
+bq:
+  project_id: my-project
+  dry_run: true
+  allowed_locations:
+    - EU
+    - europe-west4
+mqtt:
+  host: homeassistant.local
+  port: 1883
+"#;
+        let toml = r#"
+source = "tibber"
+sourcing_markup_price = 0.0175
+
+[bq]
+project_id = "my-project"
+dry_run = true
+allowed_locations = ["EU", "europe-west4"]
+
+[mqtt]
+host = "homeassistant.local"
+port = 1883
+"#;
+
+        // act
+        let mut yaml_env_values = env_values(&parse(yaml, false)?)?;
+        let mut toml_env_values = env_values(&parse(toml, true)?)?;
+
+        yaml_env_values.sort();
+        toml_env_values.sort();
+
+        assert_eq!(
+            yaml_env_values,
+            vec![
+                (
+                    "BQ_ALLOWED_LOCATIONS".to_string(),
+                    "EU,europe-west4".to_string()
+                ),
+                ("BQ_DRY_RUN".to_string(), "true".to_string()),
+                ("BQ_PROJECT_ID".to_string(), "my-project".to_string()),
+                ("MQTT_HOST".to_string(), "homeassistant.local".to_string()),
+                ("MQTT_PORT".to_string(), "1883".to_string()),
+                ("SOURCE".to_string(), "tibber".to_string()),
+                ("SOURCING_MARKUP_PRICE".to_string(), "0.0175".to_string()),
+            ]
+        );
+        assert_eq!(yaml_env_values, toml_env_values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_values_rejects_nested_lists() -> Result<(), Box<dyn Error>> {
+        // act
+        let result = env_values(&parse(
+            "bq:\n  allowed_locations:\n    - name: EU\n",
+            false,
+        )?);
+
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::sink::SpotPriceSink;
+use crate::types::{Consumption, SpotPrice};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use tracing::info;
+
+pub struct CsvClientConfig {
+    file_path: String,
+    consumption_file_path: String,
+}
+
+impl CsvClientConfig {
+    pub fn new(file_path: &str, consumption_file_path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            file_path: file_path.to_string(),
+            consumption_file_path: consumption_file_path.to_string(),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let file_path = env::var("CSV_FILE_PATH").unwrap_or_default();
+        let consumption_file_path = env::var("CSV_CONSUMPTION_FILE_PATH").unwrap_or_default();
+
+        Self::new(&file_path, &consumption_file_path)
+    }
+}
+
+pub struct CsvClient {
+    config: CsvClientConfig,
+}
+
+impl CsvClient {
+    pub fn new(config: CsvClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(CsvClientConfig::from_env()?))
+    }
+
+    /// Appends one row per record in the field order of its struct, writing the header only when
+    /// the file is new; timestamps are serialized as RFC3339.
+    fn append<R: Serialize>(file_path: &str, records: &[R]) -> Result<(), Box<dyn Error>> {
+        let is_new = fs::metadata(file_path)
+            .map(|metadata| metadata.len() == 0)
+            .unwrap_or(true);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)?;
+
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+
+        for record in records {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn append_spot_prices(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if self.config.file_path.is_empty() || spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        Self::append(&self.config.file_path, spot_prices)?;
+
+        info!(
+            "Appended {} spot prices to csv file {}",
+            spot_prices.len(),
+            &self.config.file_path
+        );
+
+        Ok(())
+    }
+
+    /// Appends consumption to its own file, as its columns differ from those of spot prices.
+    pub fn append_consumption(&self, consumption: &[Consumption]) -> Result<(), Box<dyn Error>> {
+        if self.config.consumption_file_path.is_empty() || consumption.is_empty() {
+            return Ok(());
+        }
+
+        Self::append(&self.config.consumption_file_path, consumption)?;
+
+        info!(
+            "Appended {} consumption rows to csv file {}",
+            consumption.len(),
+            &self.config.consumption_file_path
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for CsvClient {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    async fn insert_consumption(&self, consumption: &[Consumption]) -> Result<(), Box<dyn Error>> {
+        self.append_consumption(consumption)
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.append_spot_prices(spot_prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RecordType;
+    use uuid::Uuid;
+
+    #[test]
+    fn append_spot_prices() -> Result<(), Box<dyn Error>> {
+        let file_path = env::temp_dir().join(format!("{}.csv", Uuid::new_v4()));
+        let csv_client = CsvClient::new(CsvClientConfig::new(file_path.to_str().unwrap(), "")?);
+        let spot_price = SpotPrice {
+            source: Some("tibber".to_string()),
+            price_level: Some("CHEAP".to_string()),
+            ingested_at: "2022-04-30T13:07:00Z".parse()?,
+            market_price: 0.25,
+            market_price_tax: 0.0525,
+            energy_tax_price: 0.125,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        // act
+        csv_client.append_spot_prices(&[spot_price.clone()])?;
+        csv_client.append_spot_prices(&[spot_price])?;
+
+        let contents = fs::read_to_string(&file_path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "id,source,homeId,recordType,currency,priceLevel,computedPriceLevel,from,till,marketPrice,marketPriceTax,sourcingMarkupPrice,energyTaxPrice,applianceCycleCost,originalCurrency,exchangeRate,timeZone,localDate,resolution,ingestedAt"
+        );
+        assert_eq!(
+            lines[1],
+            ",tibber,,spot,EUR,CHEAP,,2022-05-01T00:00:00Z,2022-05-01T01:00:00Z,0.25,0.0525,0.0,0.125,,,,,,,2022-04-30T13:07:00Z"
+        );
+        assert_eq!(lines[1], lines[2]);
+
+        fs::remove_file(&file_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn append_consumption() -> Result<(), Box<dyn Error>> {
+        let file_path = env::temp_dir().join(format!("{}.csv", Uuid::new_v4()));
+        let csv_client = CsvClient::new(CsvClientConfig::new("", file_path.to_str().unwrap())?);
+        let consumption = Consumption {
+            id: None,
+            source: Some("tibber".to_string()),
+            home_id: Some("home-1".to_string()),
+            record_type: RecordType::Consumption,
+            from: "2022-05-01T00:00:00Z".parse()?,
+            till: "2022-05-01T01:00:00Z".parse()?,
+            consumption: 1.5,
+            consumption_unit: Some("kWh".to_string()),
+            cost: Some(0.45),
+            unit_price: Some(0.3),
+            currency: Some("EUR".to_string()),
+        };
+
+        // act
+        csv_client.append_consumption(&[consumption])?;
+
+        let contents = fs::read_to_string(&file_path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "id,source,homeId,recordType,from,till,consumption,consumptionUnit,cost,unitPrice,currency",
+                ",tibber,home-1,consumption,2022-05-01T00:00:00Z,2022-05-01T01:00:00Z,1.5,kWh,0.45,0.3,EUR"
+            ]
+        );
+
+        fs::remove_file(&file_path)?;
+
+        Ok(())
+    }
+}
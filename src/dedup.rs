@@ -0,0 +1,168 @@
+use crate::types::SpotPriceKey;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Decides which spot prices still need to be written during a single run.
+///
+/// Invariant: every key is claimed at most once, no matter how many tasks
+/// consult the set concurrently, and `last_written` only ever moves forward to
+/// the largest `from` that was actually written. Both are guarded by the same
+/// lock so a skip/write decision and the resulting `last_from` can't disagree.
+///
+/// When the previous state recorded which spot prices were written, dedup is
+/// purely set-based; `previous_last_from` is only used for older states that
+/// didn't track them yet.
+#[derive(Clone, Default)]
+pub struct DedupSet {
+    inner: Arc<Mutex<DedupSetInner>>,
+}
+
+#[derive(Default)]
+struct DedupSetInner {
+    previous_last_from: Option<DateTime<Utc>>,
+    set_based: bool,
+    claimed: HashSet<SpotPriceKey>,
+    written: BTreeSet<SpotPriceKey>,
+    last_written: Option<DateTime<Utc>>,
+}
+
+impl DedupSet {
+    pub fn new(
+        previous_last_from: Option<DateTime<Utc>>,
+        previous_written_spot_prices: &[SpotPriceKey],
+    ) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(DedupSetInner {
+                previous_last_from,
+                set_based: !previous_written_spot_prices.is_empty(),
+                claimed: previous_written_spot_prices.iter().cloned().collect(),
+                written: previous_written_spot_prices.iter().cloned().collect(),
+                last_written: None,
+            })),
+        }
+    }
+
+    /// Returns true if the caller should write the spot price identified by `key`;
+    /// only the first caller for a given key gets true.
+    pub fn claim(&self, key: &SpotPriceKey) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+
+        if !inner.set_based {
+            if let Some(previous_last_from) = inner.previous_last_from {
+                if key.from <= previous_last_from {
+                    return false;
+                }
+            }
+        }
+
+        inner.claimed.insert(key.clone())
+    }
+
+    /// Records that the spot price identified by `key` was written successfully.
+    pub fn mark_written(&self, key: SpotPriceKey) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.last_written.map_or(true, |lw| key.from > lw) {
+            inner.last_written = Some(key.from);
+        }
+        inner.written.insert(key);
+    }
+
+    /// Returns the largest `from` written during this run.
+    pub fn last_written(&self) -> Option<DateTime<Utc>> {
+        self.inner.lock().unwrap().last_written
+    }
+
+    /// Returns all spot prices known to be written, both previously and during this run, sorted
+    /// ascending.
+    pub fn written_spot_prices(&self) -> Vec<SpotPriceKey> {
+        self.inner.lock().unwrap().written.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::thread;
+
+    fn key(from: DateTime<Utc>) -> SpotPriceKey {
+        SpotPriceKey {
+            home_id: None,
+            from,
+        }
+    }
+
+    #[test]
+    fn claim_skips_already_present_prices() {
+        let last_from: DateTime<Utc> = "2022-05-01T10:00:00Z".parse().unwrap();
+        let dedup_set = DedupSet::new(Some(last_from), &[]);
+
+        assert!(!dedup_set.claim(&key(last_from - Duration::hours(1))));
+        assert!(!dedup_set.claim(&key(last_from)));
+        assert!(dedup_set.claim(&key(last_from + Duration::hours(1))));
+        assert!(!dedup_set.claim(&key(last_from + Duration::hours(1))));
+    }
+
+    #[test]
+    fn claim_distinguishes_homes() {
+        let from: DateTime<Utc> = "2022-05-01T10:00:00Z".parse().unwrap();
+        let dedup_set = DedupSet::new(None, &[]);
+
+        assert!(dedup_set.claim(&SpotPriceKey {
+            home_id: Some("home-1".to_string()),
+            from
+        }));
+        assert!(dedup_set.claim(&SpotPriceKey {
+            home_id: Some("home-2".to_string()),
+            from
+        }));
+    }
+
+    #[test]
+    fn claim_is_exclusive_across_threads() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let dedup_set = DedupSet::new(None, &[]);
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let dedup_set = dedup_set.clone();
+                thread::spawn(move || {
+                    let mut claimed = 0;
+                    for hour in 0..48 {
+                        let key = key(start + Duration::hours(hour));
+                        if dedup_set.claim(&key) {
+                            dedup_set.mark_written(key);
+                            claimed += 1;
+                        }
+                    }
+                    claimed
+                })
+            })
+            .collect();
+
+        let total_claimed: i32 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+        assert_eq!(total_claimed, 48);
+        assert_eq!(dedup_set.last_written(), Some(start + Duration::hours(47)));
+    }
+
+    #[test]
+    fn claim_does_not_skip_unwritten_boundary_hour() {
+        // a previous run recorded `last_from` for an hour whose insert never made it, but only
+        // the hour before it was actually written
+        let last_from: DateTime<Utc> = "2022-05-01T10:00:00Z".parse().unwrap();
+        let dedup_set = DedupSet::new(Some(last_from), &[key(last_from - Duration::hours(1))]);
+
+        assert!(!dedup_set.claim(&key(last_from - Duration::hours(1))));
+        assert!(dedup_set.claim(&key(last_from)));
+        dedup_set.mark_written(key(last_from));
+
+        assert_eq!(
+            dedup_set.written_spot_prices(),
+            vec![key(last_from - Duration::hours(1)), key(last_from)]
+        );
+        assert_eq!(dedup_set.last_written(), Some(last_from));
+    }
+}
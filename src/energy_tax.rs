@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EnergyTaxRate {
+    pub from: DateTime<Utc>,
+    pub till: Option<DateTime<Utc>>,
+    pub rate: f64,
+}
+
+/// Per kWh energy tax, with optional date ranges so a rate change is applied to the right hours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyTaxRates {
+    default_rate: f64,
+    rates: Vec<EnergyTaxRate>,
+}
+
+impl EnergyTaxRates {
+    pub fn new(default_rate: f64, rates: Vec<EnergyTaxRate>) -> Self {
+        Self {
+            default_rate,
+            rates,
+        }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let default_rate: f64 = env::var("ENERGY_TAX_PRICE")
+            .unwrap_or_else(|_| "0.0".to_string())
+            .parse()?;
+        let rates_path = env::var("ENERGY_TAX_RATES_PATH").unwrap_or_default();
+
+        let rates: Vec<EnergyTaxRate> = if rates_path.is_empty() {
+            vec![]
+        } else {
+            serde_yaml::from_str(&fs::read_to_string(&rates_path)?)?
+        };
+
+        Ok(Self::new(default_rate, rates))
+    }
+
+    /// Returns the rate of the first range containing `from`, or the default rate if none does.
+    pub fn rate_at(&self, from: DateTime<Utc>) -> f64 {
+        self.rates
+            .iter()
+            .find(|rate| rate.from <= from && rate.till.map_or(true, |till| from < till))
+            .map_or(self.default_rate, |rate| rate.rate)
+    }
+}
+
+impl Default for EnergyTaxRates {
+    fn default() -> Self {
+        Self::new(0.0, vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_at() -> Result<(), Box<dyn Error>> {
+        let rates: Vec<EnergyTaxRate> = serde_yaml::from_str(
+            "- from: 2022-01-01T00:00:00Z\n  till: 2022-07-01T00:00:00Z\n  rate: 0.03679\n- from: 2022-07-01T00:00:00Z\n  rate: 0.0\n",
+        )?;
+        let energy_tax_rates = EnergyTaxRates::new(0.12599, rates);
+
+        assert_eq!(
+            energy_tax_rates.rate_at("2021-12-31T23:00:00Z".parse()?),
+            0.12599
+        );
+        assert_eq!(
+            energy_tax_rates.rate_at("2022-01-01T00:00:00Z".parse()?),
+            0.03679
+        );
+        assert_eq!(
+            energy_tax_rates.rate_at("2022-06-30T23:00:00Z".parse()?),
+            0.03679
+        );
+        assert_eq!(
+            energy_tax_rates.rate_at("2022-07-01T00:00:00Z".parse()?),
+            0.0
+        );
+        assert_eq!(
+            energy_tax_rates.rate_at("2023-03-01T00:00:00Z".parse()?),
+            0.0
+        );
+
+        Ok(())
+    }
+}
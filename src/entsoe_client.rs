@@ -0,0 +1,432 @@
+use crate::error::ExporterError;
+use crate::price_source::{SpotPriceSource, SpotPricesResult};
+use crate::types::{RecordType, SpotPrice};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::time;
+use tracing::{info, warn};
+
+pub struct EntsoeClientConfig {
+    api_url: String,
+    api_token: String,
+    bidding_zone: String,
+    client: reqwest::Client,
+}
+
+impl EntsoeClientConfig {
+    pub fn new(
+        api_url: &str,
+        api_token: &str,
+        bidding_zone: &str,
+        request_timeout: time::Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()?;
+
+        Ok(Self {
+            api_url: api_url.to_string(),
+            api_token: api_token.to_string(),
+            bidding_zone: bidding_zone.to_string(),
+            client,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let api_url = env::var("ENTSOE_API_URL")
+            .unwrap_or_else(|_| "https://web-api.tp.entsoe.eu/api".to_string());
+        let api_token = env::var("ENTSOE_API_TOKEN")?;
+        let bidding_zone = env::var("ENTSOE_BIDDING_ZONE")?;
+        let request_timeout_seconds: u64 = env::var("ENTSOE_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        Self::new(
+            &api_url,
+            &api_token,
+            &bidding_zone,
+            time::Duration::from_secs(request_timeout_seconds),
+        )
+    }
+}
+
+/// Retrieves day-ahead prices for a bidding zone from the ENTSO-E transparency platform.
+pub struct EntsoeClient {
+    config: EntsoeClientConfig,
+}
+
+impl EntsoeClient {
+    pub fn new(config: EntsoeClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(EntsoeClientConfig::from_env()?))
+    }
+
+    /// Retrieves the day-ahead prices for the period between `start` and `end`.
+    async fn query(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let response = self
+            .config
+            .client
+            .get(&self.config.api_url)
+            .query(&[
+                ("securityToken", self.config.api_token.as_str()),
+                ("documentType", "A44"),
+                ("in_Domain", self.config.bidding_zone.as_str()),
+                ("out_Domain", self.config.bidding_zone.as_str()),
+                ("periodStart", &start.format("%Y%m%d%H%M").to_string()),
+                ("periodEnd", &end.format("%Y%m%d%H%M").to_string()),
+            ])
+            .send()
+            .await
+            .map_err(|e| ExporterError::EntsoeHttp(e.to_string()))?;
+
+        let status_code = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ExporterError::EntsoeHttp(e.to_string()))?;
+
+        // missing data is reported as an acknowledgement document with a client error status
+        if status_code.is_server_error() {
+            return Err(ExporterError::EntsoeHttp(format!(
+                "ENTSO-E status code {} indicates failure",
+                status_code
+            )));
+        }
+        if status_code.as_u16() == 401 {
+            return Err(ExporterError::EntsoeResponse(
+                "ENTSO-E rejected the api token".to_string(),
+            ));
+        }
+
+        Self::spot_prices_from_document(&body)
+    }
+
+    /// Maps the day-ahead prices document into hourly spot prices; prices are published per MWh so
+    /// they're converted to per kWh like Tibber's. Points left out because they repeat the previous
+    /// price are filled in.
+    fn spot_prices_from_document(document: &str) -> Result<Vec<SpotPrice>, ExporterError> {
+        let document = Document::parse(document).map_err(|e| {
+            ExporterError::EntsoeResponse(format!("ENTSO-E response is invalid: {}", e))
+        })?;
+        let root = document.root_element();
+
+        if root.tag_name().name() == "Acknowledgement_MarketDocument" {
+            let reason = root
+                .descendants()
+                .find(|n| n.tag_name().name() == "text")
+                .and_then(|n| n.text())
+                .unwrap_or("unknown reason");
+            warn!("ENTSO-E returned no prices: {}", reason);
+            return Ok(vec![]);
+        }
+
+        if root.tag_name().name() != "Publication_MarketDocument" {
+            return Err(ExporterError::EntsoeResponse(format!(
+                "ENTSO-E response has unexpected document {}",
+                root.tag_name().name()
+            )));
+        }
+
+        let mut spot_prices: Vec<SpotPrice> = vec![];
+
+        for time_series in Self::children(root, "TimeSeries") {
+            let currency = Self::child_text(time_series, "currency_Unit.name").unwrap_or("EUR");
+
+            for period in Self::children(time_series, "Period") {
+                let time_interval = Self::child(period, "timeInterval").ok_or_else(|| {
+                    ExporterError::EntsoeResponse("ENTSO-E period has no timeInterval".to_string())
+                })?;
+                let start = Self::parse_timestamp(Self::child_text(time_interval, "start"))?;
+                let end = Self::parse_timestamp(Self::child_text(time_interval, "end"))?;
+                let resolution = Self::parse_resolution(Self::child_text(period, "resolution"))?;
+
+                let mut prices: HashMap<i64, f64> = HashMap::new();
+                for point in Self::children(period, "Point") {
+                    let position = Self::child_text(point, "position").and_then(|p| p.parse().ok());
+                    let price =
+                        Self::child_text(point, "price.amount").and_then(|p| p.parse().ok());
+                    match (position, price) {
+                        (Some(position), Some(price)) => {
+                            prices.insert(position, price);
+                        }
+                        _ => {
+                            return Err(ExporterError::EntsoeResponse(
+                                "ENTSO-E point has an invalid position or price".to_string(),
+                            ))
+                        }
+                    }
+                }
+
+                let positions = (end - start).num_seconds() / resolution.num_seconds();
+                let mut last_price: Option<f64> = None;
+                for position in 1..=positions {
+                    let price = match prices.get(&position).copied().or(last_price) {
+                        Some(price) => price,
+                        None => continue,
+                    };
+                    last_price = Some(price);
+
+                    let from = start + resolution * (position - 1) as i32;
+                    spot_prices.push(SpotPrice {
+                        id: None,
+                        source: None,
+                        home_id: None,
+                        record_type: RecordType::Spot,
+                        currency: currency.to_string(),
+                        price_level: None,
+                        computed_price_level: None,
+                        ingested_at: Utc::now(),
+                        from,
+                        till: from + resolution,
+                        market_price: price / 1000.0,
+                        market_price_tax: 0.0,
+                        sourcing_markup_price: 0.0,
+                        energy_tax_price: 0.0,
+                        appliance_cycle_cost: None,
+                        original_currency: None,
+                        exchange_rate: None,
+                        time_zone: None,
+                        local_date: None,
+                        resolution: Some(resolution.num_minutes() as u32),
+                    });
+                }
+            }
+        }
+
+        spot_prices.sort_by_key(|sp| sp.from);
+
+        Ok(spot_prices)
+    }
+
+    fn children<'a, 'input>(
+        node: Node<'a, 'input>,
+        name: &'a str,
+    ) -> impl Iterator<Item = Node<'a, 'input>> {
+        node.children().filter(move |n| n.tag_name().name() == name)
+    }
+
+    fn child<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> Option<Node<'a, 'input>> {
+        Self::children(node, name).next()
+    }
+
+    fn child_text<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> Option<&'a str> {
+        Self::child(node, name)
+            .and_then(|n| n.text())
+            .map(str::trim)
+    }
+
+    fn parse_timestamp(timestamp: Option<&str>) -> Result<DateTime<Utc>, ExporterError> {
+        let timestamp = timestamp.unwrap_or_default();
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%MZ")
+            .map(|t| Utc.from_utc_datetime(&t))
+            .map_err(|_| {
+                ExporterError::EntsoeResponse(format!("ENTSO-E timestamp {} is invalid", timestamp))
+            })
+    }
+
+    fn parse_resolution(resolution: Option<&str>) -> Result<Duration, ExporterError> {
+        let resolution = resolution.unwrap_or_default();
+        let duration = if let Some(minutes) = resolution
+            .strip_prefix("PT")
+            .and_then(|r| r.strip_suffix('M'))
+        {
+            minutes.parse().ok().map(Duration::minutes)
+        } else if let Some(hours) = resolution
+            .strip_prefix("PT")
+            .and_then(|r| r.strip_suffix('H'))
+        {
+            hours.parse().ok().map(Duration::hours)
+        } else {
+            None
+        };
+
+        match duration {
+            Some(duration) if duration > Duration::zero() => Ok(duration),
+            _ => Err(ExporterError::EntsoeResponse(format!(
+                "ENTSO-E resolution {} is not supported",
+                resolution
+            ))),
+        }
+    }
+
+    /// Start of the current day in UTC; ENTSO-E periods are expressed in UTC.
+    fn start_of_today(now: DateTime<Utc>) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&now.date_naive().and_hms_opt(0, 0, 0).unwrap())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSource for EntsoeClient {
+    fn name(&self) -> &str {
+        "entsoe"
+    }
+
+    async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError> {
+        let today = Self::start_of_today(Utc::now());
+        let tomorrow = today + Duration::days(1);
+
+        let spot_prices = self.query(today, tomorrow + Duration::days(1)).await?;
+
+        let tomorrow_count = spot_prices.iter().filter(|sp| sp.from >= tomorrow).count();
+        if tomorrow_count > 0 {
+            info!(
+                "Tomorrow's prices are published with {} entries",
+                tomorrow_count
+            );
+        } else {
+            info!("Tomorrow's prices are not published yet");
+        }
+
+        Ok(SpotPricesResult {
+            spot_prices,
+            tomorrow_available: tomorrow_count > 0,
+        })
+    }
+
+    async fn get_historical_prices(
+        &self,
+        hours_back: u32,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let today = Self::start_of_today(Utc::now());
+
+        self.query(today - Duration::hours(hours_back as i64), today)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn spot_prices_from_document() -> Result<(), Box<dyn Error>> {
+        let document = fs::read_to_string("entsoe_day_ahead_prices.xml")?;
+
+        // act
+        let spot_prices = EntsoeClient::spot_prices_from_document(&document)?;
+
+        assert_eq!(spot_prices.len(), 24);
+        let start: DateTime<Utc> = "2023-05-01T22:00:00Z".parse()?;
+        assert_eq!(spot_prices[0].from, start);
+        assert_eq!(spot_prices[0].till, start + Duration::hours(1));
+        assert_eq!(spot_prices[0].currency, "EUR");
+        assert!((spot_prices[0].market_price - 0.0815).abs() < 1e-9);
+        assert_eq!(spot_prices[23].till, start + Duration::days(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_document_fills_in_left_out_points() -> Result<(), Box<dyn Error>> {
+        let document = fs::read_to_string("entsoe_day_ahead_prices.xml")?;
+
+        // act
+        let spot_prices = EntsoeClient::spot_prices_from_document(&document)?;
+
+        // position 5 is left out, so it repeats the price of position 4
+        assert_eq!(spot_prices[4].market_price, spot_prices[3].market_price);
+        assert_eq!(
+            spot_prices[4].from,
+            "2023-05-02T02:00:00Z".parse::<DateTime<Utc>>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_acknowledgement_is_empty() -> Result<(), Box<dyn Error>> {
+        let document = r#"<Acknowledgement_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-1:acknowledgementdocument:7:0">
+    <Reason>
+        <code>999</code>
+        <text>No matching data found</text>
+    </Reason>
+</Acknowledgement_MarketDocument>"#;
+
+        // act
+        let spot_prices = EntsoeClient::spot_prices_from_document(document)?;
+
+        assert!(spot_prices.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_resolution() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            EntsoeClient::parse_resolution(Some("PT60M"))?,
+            Duration::hours(1)
+        );
+        assert_eq!(
+            EntsoeClient::parse_resolution(Some("PT15M"))?,
+            Duration::minutes(15)
+        );
+        assert!(EntsoeClient::parse_resolution(Some("P1D")).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_queries_bidding_zone() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("securityToken", "token"))
+            .and(query_param("documentType", "A44"))
+            .and(query_param("in_Domain", "10YNL----------L"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(fs::read_to_string("entsoe_day_ahead_prices.xml")?),
+            )
+            .mount(&mock_server)
+            .await;
+        let entsoe_client = EntsoeClient::new(EntsoeClientConfig::new(
+            &mock_server.uri(),
+            "token",
+            "10YNL----------L",
+            time::Duration::from_secs(5),
+        )?);
+
+        // act
+        let result = entsoe_client.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 24);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_fails_on_server_error() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+        let entsoe_client = EntsoeClient::new(EntsoeClientConfig::new(
+            &mock_server.uri(),
+            "token",
+            "10YNL----------L",
+            time::Duration::from_secs(5),
+        )?);
+
+        // act
+        let result = entsoe_client.get_spot_prices().await;
+
+        assert!(matches!(result, Err(ExporterError::EntsoeHttp(_))));
+
+        Ok(())
+    }
+}
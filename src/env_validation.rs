@@ -0,0 +1,313 @@
+use crate::bigquery_client::{BigqueryPartitionField, BigqueryWriteMode};
+use crate::error::ExporterError;
+use crate::exporter_service::RunMode;
+use crate::logging::LogFormat;
+use crate::price_source::PriceSource;
+use crate::state_client::{StateBackend, StateResourceKind};
+use crate::tibber_client::FetchWindow;
+use chrono_tz::Tz;
+use cron::Schedule;
+use std::env;
+use std::str::FromStr;
+
+const REQUIRED: [&str; 4] = ["SOURCE", "BQ_PROJECT_ID", "BQ_DATASET", "BQ_TABLE"];
+
+const REQUIRED_FOR_TIBBER: [&str; 1] = ["TIBBER_ACCESS_TOKEN"];
+
+/// Secrets that can be mounted as a file instead, satisfying the requirement as well.
+const FILE_ALTERNATIVES: [(&str, &str); 1] = [("TIBBER_ACCESS_TOKEN", "TIBBER_ACCESS_TOKEN_FILE")];
+
+const REQUIRED_FOR_ENTSOE: [&str; 2] = ["ENTSOE_API_TOKEN", "ENTSOE_BIDDING_ZONE"];
+
+const REQUIRED_FOR_NORDPOOL: [&str; 1] = ["NORDPOOL_AREA"];
+
+const REQUIRED_FOR_GCS_STATE: [&str; 1] = ["STATE_GCS_BUCKET"];
+
+const REQUIRED_FOR_KAFKA: [&str; 1] = ["KAFKA_BROKERS"];
+
+const REQUIRED_FOR_INFLUXDB: [&str; 4] = [
+    "INFLUXDB_URL",
+    "INFLUXDB_TOKEN",
+    "INFLUXDB_ORG",
+    "INFLUXDB_BUCKET",
+];
+
+/// Env vars that fail construction when set to a value that can't be parsed, with a check whether
+/// the value is valid.
+const PARSED: [(&str, fn(&str) -> bool); 21] = [
+    ("SOURCING_MARKUP_PRICE", |v| v.parse::<f64>().is_ok()),
+    ("ENERGY_TAX_PRICE", |v| v.parse::<f64>().is_ok()),
+    ("APPLIANCE_KWH", |v| v.parse::<f64>().is_ok()),
+    ("APPLIANCE_DURATION_HOURS", |v| v.parse::<usize>().is_ok()),
+    ("ALERT_BELOW", |v| v.parse::<f64>().is_ok()),
+    ("ALERT_ABOVE", |v| v.parse::<f64>().is_ok()),
+    ("RUN_MODE", |v| v.parse::<RunMode>().is_ok()),
+    ("SCHEDULE_CRON", |v| {
+        v.trim().is_empty() || Schedule::from_str(v.trim()).is_ok()
+    }),
+    ("BACKFILL_HOURS", |v| {
+        v.is_empty() || v.parse::<u32>().is_ok()
+    }),
+    ("TOMORROW_RETRY_INTERVAL_SECONDS", |v| {
+        v.is_empty() || v.parse::<u64>().is_ok()
+    }),
+    ("RUN_TIMEOUT_SECONDS", |v| {
+        v.is_empty() || v.parse::<u64>().is_ok()
+    }),
+    ("MAX_STALENESS", |v| {
+        v.is_empty() || v.parse::<u64>().is_ok()
+    }),
+    ("BQ_PARTITION_EXPIRATION_DAYS", |v| {
+        v.is_empty() || v.parse::<u64>().is_ok()
+    }),
+    ("BQ_WRITE_MODE", |v| v.parse::<BigqueryWriteMode>().is_ok()),
+    ("BQ_PARTITION_FIELD", |v| {
+        v.parse::<BigqueryPartitionField>().is_ok()
+    }),
+    ("BQ_DAILY_TIME_ZONE", |v| v.parse::<Tz>().is_ok()),
+    ("FETCH_WINDOW", |v| v.parse::<FetchWindow>().is_ok()),
+    ("PRICE_SOURCE", |v| v.parse::<PriceSource>().is_ok()),
+    ("LOG_FORMAT", |v| v.parse::<LogFormat>().is_ok()),
+    ("STATE_BACKEND", |v| v.parse::<StateBackend>().is_ok()),
+    ("STATE_RESOURCE_KIND", |v| {
+        v.parse::<StateResourceKind>().is_ok()
+    }),
+];
+
+/// Checks all env vars up front, so a misconfigured deployment reports every problem at once
+/// instead of failing on the first one deep inside client construction.
+pub fn validate_env() -> Result<(), ExporterError> {
+    validate(|name| env::var(name).ok())
+}
+
+fn validate<F>(lookup: F) -> Result<(), ExporterError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let influxdb_enabled = lookup("INFLUXDB_ENABLE")
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    let gcs_state_enabled = lookup("STATE_ENABLE")
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false)
+        && matches!(
+            lookup("STATE_BACKEND").map(|v| v.parse::<StateBackend>()),
+            Some(Ok(StateBackend::Gcs))
+        );
+
+    let kafka_enabled = lookup("KAFKA_ENABLE")
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    let consumption_enabled = lookup("CONSUMPTION_ENABLE")
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    let price_source = lookup("PRICE_SOURCE").map(|v| v.parse::<PriceSource>());
+    let required_for_price_source = match price_source {
+        Some(Ok(PriceSource::Entsoe)) => REQUIRED_FOR_ENTSOE.iter(),
+        Some(Ok(PriceSource::NordPool)) => REQUIRED_FOR_NORDPOOL.iter(),
+        Some(Err(_)) => [].iter(),
+        _ => REQUIRED_FOR_TIBBER.iter(),
+    };
+    // consumption always comes from tibber, whatever source the prices come from
+    let required_for_consumption = match price_source {
+        Some(Ok(PriceSource::Entsoe)) | Some(Ok(PriceSource::NordPool)) if consumption_enabled => {
+            REQUIRED_FOR_TIBBER.iter()
+        }
+        _ => [].iter(),
+    };
+
+    let missing: Vec<&str> = REQUIRED
+        .iter()
+        .chain(required_for_price_source)
+        .chain(required_for_consumption)
+        .chain(if kafka_enabled {
+            REQUIRED_FOR_KAFKA.iter()
+        } else {
+            [].iter()
+        })
+        .chain(if gcs_state_enabled {
+            REQUIRED_FOR_GCS_STATE.iter()
+        } else {
+            [].iter()
+        })
+        .chain(if influxdb_enabled {
+            REQUIRED_FOR_INFLUXDB.iter()
+        } else {
+            [].iter()
+        })
+        .filter(|name| {
+            lookup(name).is_none()
+                && !FILE_ALTERNATIVES
+                    .iter()
+                    .any(|(secret, file)| secret == *name && lookup(file).is_some())
+        })
+        .copied()
+        .collect();
+
+    let invalid: Vec<&str> = PARSED
+        .iter()
+        .filter(|(name, is_valid)| lookup(name).map_or(false, |value| !is_valid(&value)))
+        .map(|(name, _)| *name)
+        .collect();
+
+    let mut problems = vec![];
+    if !missing.is_empty() {
+        problems.push(format!("missing: {}", missing.join(", ")));
+    }
+    if !invalid.is_empty() {
+        problems.push(format!("invalid: {}", invalid.join(", ")));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ExporterError::Config(format!(
+            "Invalid configuration, {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn validate_accepts_complete_configuration() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "tibber"),
+            ("TIBBER_ACCESS_TOKEN", "token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+            ("RUN_MODE", "scheduled"),
+            ("SCHEDULE_CRON", ""),
+        ]));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn validate_lists_all_problems() {
+        // act
+        let result = validate(lookup(&[
+            ("TIBBER_ACCESS_TOKEN", "token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("INFLUXDB_ENABLE", "true"),
+            ("INFLUXDB_URL", "http://localhost:8086"),
+            ("INFLUXDB_TOKEN", "token"),
+            ("INFLUXDB_ORG", "org"),
+            ("RUN_MODE", "forever"),
+            ("SOURCING_MARKUP_PRICE", "cheap"),
+        ]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration, missing: SOURCE, BQ_TABLE, INFLUXDB_BUCKET; invalid: SOURCING_MARKUP_PRICE, RUN_MODE"
+        );
+    }
+
+    #[test]
+    fn validate_requires_entsoe_configuration_instead_of_tibber() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "entsoe"),
+            ("PRICE_SOURCE", "entsoe"),
+            ("ENTSOE_API_TOKEN", "token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+        ]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration, missing: ENTSOE_BIDDING_ZONE"
+        );
+    }
+
+    #[test]
+    fn validate_requires_bucket_for_gcs_state() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "tibber"),
+            ("TIBBER_ACCESS_TOKEN", "token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+            ("STATE_ENABLE", "true"),
+            ("STATE_BACKEND", "gcs"),
+        ]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration, missing: STATE_GCS_BUCKET"
+        );
+    }
+
+    #[test]
+    fn validate_requires_tibber_token_for_consumption() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "entsoe"),
+            ("PRICE_SOURCE", "entsoe"),
+            ("ENTSOE_API_TOKEN", "token"),
+            ("ENTSOE_BIDDING_ZONE", "10YNL----------L"),
+            ("CONSUMPTION_ENABLE", "true"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+        ]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration, missing: TIBBER_ACCESS_TOKEN"
+        );
+    }
+
+    #[test]
+    fn validate_accepts_tibber_access_token_file() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "tibber"),
+            ("TIBBER_ACCESS_TOKEN_FILE", "/secrets/tibber-token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+        ]));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn validate_requires_brokers_for_kafka() {
+        // act
+        let result = validate(lookup(&[
+            ("SOURCE", "tibber"),
+            ("TIBBER_ACCESS_TOKEN", "token"),
+            ("BQ_PROJECT_ID", "project"),
+            ("BQ_DATASET", "dataset"),
+            ("BQ_TABLE", "table"),
+            ("KAFKA_ENABLE", "true"),
+        ]));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid configuration, missing: KAFKA_BROKERS"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use gcp_bigquery_client::error::BQError;
+use std::time;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExporterError {
+    #[error("Tibber rejected the access token with status code {0}")]
+    TibberAuth(u16),
+    #[error("Tibber request failed: {0}")]
+    TibberHttp(String),
+    #[error("Tibber rate limited the request, retry after {}s", .retry_after.as_secs())]
+    TibberRateLimited { retry_after: time::Duration },
+    #[error("{0}")]
+    TibberResponse(String),
+    #[error("ENTSO-E request failed: {0}")]
+    EntsoeHttp(String),
+    #[error("{0}")]
+    EntsoeResponse(String),
+    #[error("Nord Pool request failed: {0}")]
+    NordPoolHttp(String),
+    #[error("{0}")]
+    NordPoolResponse(String),
+    #[error("Bigquery request failed: {0}")]
+    BigQuery(#[from] BQError),
+    #[error("{0}")]
+    BigQueryInsert(String),
+    #[error("{0}")]
+    StateConflict(String),
+    #[error("GCS request failed: {0}")]
+    Gcs(String),
+    #[error("Kubernetes request failed: {0}")]
+    Kube(#[from] kube::Error),
+    #[error("Skipping {name} for another {}s after repeated failures", .retry_in.as_secs())]
+    CircuitOpen {
+        name: String,
+        retry_in: time::Duration,
+    },
+    #[error("Run exceeded timeout of {}s", .0.as_secs())]
+    RunTimeout(time::Duration),
+    #[error("Invalid spot price: {0}")]
+    InvalidSpotPrice(#[from] ValidationError),
+    #[error("{0}")]
+    Config(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// Reasons a spot price is rejected before it's stored.
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationError {
+    #[error("{field} is {value}, which isn't a finite number")]
+    NotFinite { field: &'static str, value: f64 },
+    #[error("interval from {from} till {till} doesn't end after it starts")]
+    EmptyInterval {
+        from: DateTime<Utc>,
+        till: DateTime<Utc>,
+    },
+}
+
+impl ExporterError {
+    /// Whether retrying the whole operation could succeed; bigquery errors are already retried
+    /// inside the client and auth, response or configuration errors won't go away by themselves.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ExporterError::TibberHttp(_)
+            | ExporterError::EntsoeHttp(_)
+            | ExporterError::NordPoolHttp(_)
+            | ExporterError::TibberRateLimited { .. }
+            | ExporterError::StateConflict(_) => true,
+            ExporterError::Kube(kube::Error::Api(e)) => e.code >= 500,
+            _ => false,
+        }
+    }
+
+    /// How long the source asked to wait before trying again, if it did.
+    pub fn retry_after(&self) -> Option<time::Duration> {
+        match self {
+            ExporterError::TibberRateLimited { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_retryable() {
+        assert!(ExporterError::TibberHttp("connection reset".to_string()).is_retryable());
+        assert!(ExporterError::TibberRateLimited {
+            retry_after: time::Duration::from_secs(60)
+        }
+        .is_retryable());
+        assert!(ExporterError::EntsoeHttp("timed out".to_string()).is_retryable());
+        assert!(!ExporterError::TibberAuth(401).is_retryable());
+        assert!(!ExporterError::TibberResponse("no data".to_string()).is_retryable());
+        assert!(!ExporterError::Config("invalid".to_string()).is_retryable());
+        assert!(!ExporterError::RunTimeout(time::Duration::from_secs(600)).is_retryable());
+    }
+
+    #[test]
+    fn retry_after() {
+        assert_eq!(
+            ExporterError::TibberRateLimited {
+                retry_after: time::Duration::from_secs(60)
+            }
+            .retry_after(),
+            Some(time::Duration::from_secs(60))
+        );
+        assert_eq!(
+            ExporterError::TibberHttp("connection reset".to_string()).retry_after(),
+            None
+        );
+    }
+
+    #[test]
+    fn run_timeout_message() {
+        // act
+        let message = ExporterError::RunTimeout(time::Duration::from_secs(600)).to_string();
+
+        assert_eq!(message, "Run exceeded timeout of 600s");
+    }
+}
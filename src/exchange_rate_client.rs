@@ -0,0 +1,188 @@
+use crate::types::SpotPrice;
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::sync::Mutex;
+use tracing::info;
+
+/// Response of a frankfurter.app compatible rate api, e.g. `{"rates":{"EUR":0.0954}}`.
+#[derive(Deserialize, Debug)]
+struct ExchangeRateResponse {
+    rates: HashMap<String, f64>,
+}
+
+pub struct ExchangeRateClientConfig {
+    target_currency: String,
+    rate_api_url: String,
+}
+
+impl ExchangeRateClientConfig {
+    pub fn new(target_currency: &str, rate_api_url: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            target_currency: target_currency.to_uppercase(),
+            rate_api_url: rate_api_url.to_string(),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let target_currency = env::var("TARGET_CURRENCY").unwrap_or_default();
+        let rate_api_url = env::var("EXCHANGE_RATE_API_URL")
+            .unwrap_or_else(|_| "https://api.frankfurter.app/latest".to_string());
+
+        Self::new(&target_currency, &rate_api_url)
+    }
+}
+
+pub struct ExchangeRateClient {
+    config: ExchangeRateClientConfig,
+    rates: Mutex<HashMap<(NaiveDate, String), f64>>,
+}
+
+impl ExchangeRateClient {
+    pub fn new(config: ExchangeRateClientConfig) -> Self {
+        Self {
+            config,
+            rates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(ExchangeRateClientConfig::from_env()?))
+    }
+
+    /// Returns the rate to convert from `currency` into the target currency, fetched at most once
+    /// per day.
+    async fn get_rate(&self, currency: &str) -> Result<f64, Box<dyn Error>> {
+        let key = (Utc::now().date_naive(), currency.to_string());
+        if let Some(rate) = self.rates.lock().unwrap().get(&key) {
+            return Ok(*rate);
+        }
+
+        let response = reqwest::Client::new()
+            .get(&self.config.rate_api_url)
+            .query(&[
+                ("from", currency),
+                ("to", self.config.target_currency.as_str()),
+            ])
+            .send()
+            .await?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(Box::<dyn Error>::from(format!(
+                "Exchange rate api status code {} indicates failure",
+                status_code
+            )));
+        }
+
+        let exchange_rate_response: ExchangeRateResponse = response.json().await?;
+        let rate = *exchange_rate_response
+            .rates
+            .get(&self.config.target_currency)
+            .ok_or_else(|| {
+                Box::<dyn Error>::from(format!(
+                    "Exchange rate api returned no rate from {} to {}",
+                    currency, &self.config.target_currency
+                ))
+            })?;
+
+        info!(
+            "Retrieved exchange rate {} from {} to {}",
+            rate, currency, &self.config.target_currency
+        );
+        self.rates.lock().unwrap().insert(key, rate);
+
+        Ok(rate)
+    }
+
+    /// Converts the prices retrieved from Tibber into the target currency, if one is configured.
+    pub async fn convert_spot_prices(
+        &self,
+        spot_prices: Vec<SpotPrice>,
+    ) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
+        if self.config.target_currency.is_empty() {
+            return Ok(spot_prices);
+        }
+
+        let mut converted_spot_prices = vec![];
+        for spot_price in spot_prices {
+            if spot_price.currency.is_empty()
+                || spot_price.currency.to_uppercase() == self.config.target_currency
+            {
+                converted_spot_prices.push(spot_price);
+                continue;
+            }
+
+            let rate = self.get_rate(&spot_price.currency).await?;
+            converted_spot_prices.push(Self::convert(
+                spot_price,
+                &self.config.target_currency,
+                rate,
+            ));
+        }
+
+        Ok(converted_spot_prices)
+    }
+
+    fn convert(spot_price: SpotPrice, target_currency: &str, rate: f64) -> SpotPrice {
+        SpotPrice {
+            currency: target_currency.to_string(),
+            market_price: spot_price.market_price * rate,
+            market_price_tax: spot_price.market_price_tax * rate,
+            original_currency: Some(spot_price.currency.clone()),
+            exchange_rate: Some(rate),
+            ..spot_price
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            currency: "NOK".to_string(),
+            market_price: 2.0,
+            market_price_tax: 0.5,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        // act
+        let spot_price = ExchangeRateClient::convert(spot_price, "EUR", 0.125);
+
+        assert_eq!(spot_price.currency, "EUR");
+        assert_eq!(spot_price.market_price, 0.25);
+        assert_eq!(spot_price.market_price_tax, 0.0625);
+        assert_eq!(spot_price.original_currency.as_deref(), Some("NOK"));
+        assert_eq!(spot_price.exchange_rate, Some(0.125));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn convert_spot_prices_is_noop_without_target_currency() -> Result<(), Box<dyn Error>> {
+        let exchange_rate_client =
+            ExchangeRateClient::new(ExchangeRateClientConfig::new("", "http://localhost")?);
+        let spot_price = SpotPrice {
+            currency: "NOK".to_string(),
+            market_price: 2.0,
+            market_price_tax: 0.5,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        // act
+        let spot_prices = exchange_rate_client
+            .convert_spot_prices(vec![spot_price])
+            .await?;
+
+        assert_eq!(spot_prices[0].currency, "NOK");
+        assert_eq!(spot_prices[0].market_price, 2.0);
+        assert_eq!(spot_prices[0].original_currency, None);
+
+        Ok(())
+    }
+}
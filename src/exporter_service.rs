@@ -1,45 +1,294 @@
-use crate::bigquery_client::BigqueryClient;
+use crate::alert_client::AlertClient;
+use crate::dedup::DedupSet;
+use crate::energy_tax::EnergyTaxRates;
+use crate::error::ExporterError;
+use crate::exchange_rate_client::ExchangeRateClient;
+use crate::health_server::Health;
+use crate::ical_client::IcalClient;
+use crate::last_run_client::LastRunClient;
+use crate::metrics_server::Metrics;
+use crate::price_source::{SpotPriceSource, SpotPricesResult};
+use crate::price_window::{
+    appliance_cycle_costs, cheapest_window, coalesce_equal_prices, ApplianceProfile,
+    SpotPriceSummary,
+};
+use crate::retry::RetryPolicy;
+use crate::sink::{SpotPriceSink, SpotPriceSnapshot};
 use crate::state_client::StateClient;
 use crate::tibber_client::TibberClient;
 use crate::types::*;
 use chrono::{DateTime, Utc};
+use cron::Schedule;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
-use tokio_retry::strategy::{jitter, ExponentialBackoff};
-use tokio_retry::Retry;
-use tracing::info;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    Once,
+    Scheduled,
+}
+
+impl FromStr for RunMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "once" => Ok(RunMode::Once),
+            "scheduled" => Ok(RunMode::Scheduled),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown run mode {}, expected once or scheduled",
+                s
+            ))),
+        }
+    }
+}
+
 pub struct ExporterServiceConfig {
-    bigquery_client: BigqueryClient,
-    tibber_client: TibberClient,
+    price_source: Box<dyn SpotPriceSource>,
     state_client: StateClient,
+    ical_client: IcalClient,
+    alert_client: AlertClient,
+    exchange_rate_client: ExchangeRateClient,
+    sinks: Vec<Box<dyn SpotPriceSink>>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
     source: String,
+    sourcing_markup_price: f64,
+    energy_tax_rates: EnergyTaxRates,
+    coalesce_equal_prices: bool,
+    appliance_profile: Option<ApplianceProfile>,
+    run_mode: RunMode,
+    run_interval: Duration,
+    schedule_cron: Option<Schedule>,
+    run_jitter: Duration,
+    startup_splay: Duration,
+    run_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    backfill_hours: Option<u32>,
+    tomorrow_retry_interval: Option<Duration>,
+    cheapest_window_hours: usize,
+    emit_json: bool,
+    last_run_client: LastRunClient,
+    state_future_lookback: Duration,
+    consumption_client: Option<TibberClient>,
+    consumption_hours: u32,
+    fail_on_invalid_prices: bool,
+    compute_price_level: bool,
+    init_only: bool,
 }
 
 impl ExporterServiceConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        bigquery_client: BigqueryClient,
-        tibber_client: TibberClient,
+        price_source: Box<dyn SpotPriceSource>,
         state_client: StateClient,
+        ical_client: IcalClient,
+        alert_client: AlertClient,
+        exchange_rate_client: ExchangeRateClient,
+        sinks: Vec<Box<dyn SpotPriceSink>>,
+        metrics: Arc<Metrics>,
+        health: Arc<Health>,
         source: &str,
+        sourcing_markup_price: f64,
+        energy_tax_rates: EnergyTaxRates,
+        coalesce_equal_prices: bool,
+        appliance_profile: Option<ApplianceProfile>,
+        run_mode: RunMode,
+        run_interval: Duration,
+        schedule_cron: Option<Schedule>,
+        run_jitter: Duration,
+        startup_splay: Duration,
+        run_timeout: Option<Duration>,
+        retry_policy: RetryPolicy,
+        backfill_hours: Option<u32>,
+        tomorrow_retry_interval: Option<Duration>,
+        cheapest_window_hours: usize,
+        emit_json: bool,
+        last_run_client: LastRunClient,
+        state_future_lookback: Duration,
+        consumption_client: Option<TibberClient>,
+        consumption_hours: u32,
+        fail_on_invalid_prices: bool,
+        compute_price_level: bool,
+        init_only: bool,
     ) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
-            bigquery_client,
-            tibber_client,
+            price_source,
             state_client,
+            ical_client,
+            alert_client,
+            exchange_rate_client,
+            sinks,
+            metrics,
+            health,
             source: source.to_string(),
+            sourcing_markup_price,
+            energy_tax_rates,
+            coalesce_equal_prices,
+            appliance_profile,
+            run_mode,
+            run_interval,
+            schedule_cron,
+            run_jitter,
+            startup_splay,
+            run_timeout,
+            retry_policy,
+            backfill_hours,
+            tomorrow_retry_interval,
+            cheapest_window_hours,
+            emit_json,
+            last_run_client,
+            state_future_lookback,
+            consumption_client,
+            consumption_hours,
+            fail_on_invalid_prices,
+            compute_price_level,
+            init_only,
         })
     }
 
     pub fn from_env(
-        bigquery_client: BigqueryClient,
-        tibber_client: TibberClient,
+        price_source: Box<dyn SpotPriceSource>,
         state_client: StateClient,
+        ical_client: IcalClient,
+        alert_client: AlertClient,
+        exchange_rate_client: ExchangeRateClient,
+        sinks: Vec<Box<dyn SpotPriceSink>>,
+        metrics: Arc<Metrics>,
+        health: Arc<Health>,
     ) -> Result<Self, Box<dyn Error>> {
         let source = env::var("SOURCE")?;
+        let sourcing_markup_price: f64 = env::var("SOURCING_MARKUP_PRICE")
+            .unwrap_or_else(|_| "0.0".to_string())
+            .parse()?;
+        let energy_tax_rates = EnergyTaxRates::from_env()?;
+        let coalesce_equal_prices: bool = env::var("COALESCE_EQUAL_PRICES")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let appliance_profile = match env::var("APPLIANCE_KWH") {
+            Ok(kwh) => Some(ApplianceProfile {
+                kwh: kwh.parse()?,
+                duration_hours: env::var("APPLIANCE_DURATION_HOURS")
+                    .unwrap_or_else(|_| "1".to_string())
+                    .parse()?,
+            }),
+            Err(_) => None,
+        };
+        let run_mode: RunMode = env::var("RUN_MODE")
+            .unwrap_or_else(|_| "once".to_string())
+            .parse()?;
+        let run_interval_seconds: u64 = env::var("RUN_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse()
+            .unwrap_or(3600);
+        let schedule_cron = match env::var("SCHEDULE_CRON") {
+            Ok(expression) if !expression.trim().is_empty() => {
+                Some(Schedule::from_str(expression.trim())?)
+            }
+            _ => None,
+        };
+        let run_jitter_seconds: u64 = env::var("RUN_JITTER_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+        let startup_splay_seconds: u64 = env::var("STARTUP_SPLAY_SECONDS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+        let run_timeout: Option<Duration> = match env::var("RUN_TIMEOUT_SECONDS") {
+            Ok(seconds) if !seconds.is_empty() => Some(Duration::from_secs(seconds.parse()?)),
+            _ => None,
+        };
+        let backfill_hours: Option<u32> = match env::var("BACKFILL_HOURS") {
+            Ok(hours) if !hours.is_empty() => Some(hours.parse()?),
+            _ => None,
+        };
+        let tomorrow_retry_interval: Option<Duration> =
+            match env::var("TOMORROW_RETRY_INTERVAL_SECONDS") {
+                Ok(seconds) if !seconds.is_empty() => Some(Duration::from_secs(seconds.parse()?)),
+                _ => None,
+            };
+        let cheapest_window_hours: usize = env::var("CHEAPEST_WINDOW_HOURS")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .unwrap_or(3);
+        let emit_json: bool = env::var("EMIT_JSON")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let state_future_lookback_hours: u64 = env::var("STATE_FUTURE_LOOKBACK_HOURS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+        // consumption is only available from tibber, whatever source the prices come from
+        let consumption_enable: bool = env::var("CONSUMPTION_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let consumption_client = if consumption_enable {
+            Some(TibberClient::from_env()?)
+        } else {
+            None
+        };
+        let consumption_hours: u32 = env::var("CONSUMPTION_HOURS")
+            .unwrap_or_else(|_| "48".to_string())
+            .parse()
+            .unwrap_or(48);
+        let fail_on_invalid_prices: bool = env::var("FAIL_ON_INVALID_PRICES")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let compute_price_level: bool = env::var("COMPUTE_PRICE_LEVEL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let init_only: bool = env::var("INIT_ONLY")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
 
-        Self::new(bigquery_client, tibber_client, state_client, &source)
+        Self::new(
+            price_source,
+            state_client,
+            ical_client,
+            alert_client,
+            exchange_rate_client,
+            sinks,
+            metrics,
+            health,
+            &source,
+            sourcing_markup_price,
+            energy_tax_rates,
+            coalesce_equal_prices,
+            appliance_profile,
+            run_mode,
+            Duration::from_secs(run_interval_seconds),
+            schedule_cron,
+            Duration::from_secs(run_jitter_seconds),
+            Duration::from_secs(startup_splay_seconds),
+            run_timeout,
+            RetryPolicy::from_env(),
+            backfill_hours,
+            tomorrow_retry_interval,
+            cheapest_window_hours,
+            emit_json,
+            LastRunClient::from_env()?,
+            Duration::from_secs(state_future_lookback_hours * 60 * 60),
+            consumption_client,
+            consumption_hours,
+            fail_on_invalid_prices,
+            compute_price_level,
+            init_only,
+        )
     }
 }
 
@@ -53,78 +302,1395 @@ impl ExporterService {
     }
 
     pub fn from_env(
-        bigquery_client: BigqueryClient,
-        tibber_client: TibberClient,
+        price_source: Box<dyn SpotPriceSource>,
         state_client: StateClient,
+        ical_client: IcalClient,
+        alert_client: AlertClient,
+        exchange_rate_client: ExchangeRateClient,
+        sinks: Vec<Box<dyn SpotPriceSink>>,
+        metrics: Arc<Metrics>,
+        health: Arc<Health>,
     ) -> Result<Self, Box<dyn Error>> {
         Ok(Self::new(ExporterServiceConfig::from_env(
-            bigquery_client,
-            tibber_client,
+            price_source,
             state_client,
+            ical_client,
+            alert_client,
+            exchange_rate_client,
+            sinks,
+            metrics,
+            health,
         )?))
     }
 
+    /// Retrieves past prices when backfilling is configured, leaving out the ones already
+    /// retrieved as today's prices.
+    async fn get_historical_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let hours_back = match self.config.backfill_hours {
+            Some(hours_back) if hours_back > 0 => hours_back,
+            _ => return Ok(vec![]),
+        };
+
+        let historical_spot_prices = self
+            .config
+            .retry_policy
+            .retry_with_requested_delay(
+                "Retrieving historical prices",
+                || self.config.price_source.get_historical_prices(hours_back),
+                ExporterError::is_retryable,
+                ExporterError::retry_after,
+            )
+            .await?;
+
+        Ok(Self::exclude_known_spot_prices(
+            historical_spot_prices,
+            spot_prices,
+        ))
+    }
+
+    fn exclude_known_spot_prices(
+        spot_prices: Vec<SpotPrice>,
+        known_spot_prices: &[SpotPrice],
+    ) -> Vec<SpotPrice> {
+        let known_keys: HashSet<SpotPriceKey> =
+            known_spot_prices.iter().map(|sp| sp.key()).collect();
+
+        spot_prices
+            .into_iter()
+            .filter(|spot_price| !known_keys.contains(&spot_price.key()))
+            .collect()
+    }
+
+    /// Source of the prices of one account when retrieving for multiple accounts, so each account
+    /// is stored apart.
+    fn account_source(source: &str, account: Option<&str>) -> String {
+        match account {
+            Some(account) => format!("{}-{}", source, account),
+            None => source.to_string(),
+        }
+    }
+
+    /// Sets the fields the price source doesn't provide: a unique id, the configured source,
+    /// suffixed with the account the price was retrieved for when there's more than one, the
+    /// configured markup, the energy tax applicable at the time of the price and when it was
+    /// ingested.
+    fn enrich_spot_prices(
+        spot_prices: Vec<SpotPrice>,
+        source: &str,
+        sourcing_markup_price: f64,
+        energy_tax_rates: &EnergyTaxRates,
+        ingested_at: DateTime<Utc>,
+    ) -> Vec<SpotPrice> {
+        spot_prices
+            .into_iter()
+            .map(|spot_price| SpotPrice {
+                id: Some(Uuid::new_v4().to_string()),
+                source: Some(Self::account_source(source, spot_price.source.as_deref())),
+                sourcing_markup_price,
+                energy_tax_price: energy_tax_rates.rate_at(spot_price.from),
+                ingested_at,
+                ..spot_price
+            })
+            .collect()
+    }
+
+    /// Drops spot prices that don't validate with a warning, so upstream bugs don't end up in the
+    /// sinks; fails on the first invalid one instead if configured to.
+    fn validate_spot_prices(
+        spot_prices: Vec<SpotPrice>,
+        fail_on_invalid: bool,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let mut valid_spot_prices = Vec::with_capacity(spot_prices.len());
+        for spot_price in spot_prices {
+            match spot_price.validate() {
+                Ok(()) => valid_spot_prices.push(spot_price),
+                Err(e) if fail_on_invalid => return Err(e.into()),
+                Err(e) => warn!(
+                    "Skipping invalid spot price from {}: {}",
+                    spot_price.from, e
+                ),
+            }
+        }
+
+        Ok(valid_spot_prices)
+    }
+
+    /// Whether any retrieved price starts after the last one exported for its home in a previous
+    /// run, so a home that lags behind the others still gets its prices written.
+    fn has_new_spot_prices(state: Option<&State>, spot_prices: &[SpotPrice]) -> bool {
+        let state = match state {
+            Some(state) => state,
+            None => return !spot_prices.is_empty(),
+        };
+
+        // older states didn't track written prices, only the last one overall
+        if state.written_spot_prices.is_empty() {
+            return spot_prices.iter().any(|sp| sp.from > state.last_from);
+        }
+
+        let mut last_written: HashMap<&Option<String>, DateTime<Utc>> = HashMap::new();
+        for key in &state.written_spot_prices {
+            let last_from = last_written.entry(&key.home_id).or_insert(key.from);
+            *last_from = (*last_from).max(key.from);
+        }
+
+        spot_prices.iter().any(|sp| {
+            last_written
+                .get(&sp.home_id)
+                .map_or(true, |last_from| sp.from > *last_from)
+        })
+    }
+
+    /// Whether any of the spot prices hasn't been exported in a previous run; unlike
+    /// `has_new_spot_prices` it doesn't assume everything before the last written price made it,
+    /// so gaps found while backfilling get filled.
+    fn has_unwritten_spot_prices(state: Option<&State>, spot_prices: &[SpotPrice]) -> bool {
+        let state = match state {
+            Some(state) => state,
+            None => return !spot_prices.is_empty(),
+        };
+
+        // older states didn't track written prices, so there's no telling what's missing
+        if state.written_spot_prices.is_empty() {
+            return spot_prices.iter().any(|sp| sp.from > state.last_from);
+        }
+
+        let written: HashSet<&SpotPriceKey> = state.written_spot_prices.iter().collect();
+        spot_prices.iter().any(|sp| !written.contains(&sp.key()))
+    }
+
+    /// Keeps only spot prices that haven't passed yet, or passed within the lookback, so the state
+    /// doesn't grow with every run.
+    fn prune_expired_spot_prices(
+        spot_prices: &[SpotPrice],
+        now: DateTime<Utc>,
+        lookback: Duration,
+    ) -> Vec<SpotPrice> {
+        let threshold =
+            now - chrono::Duration::from_std(lookback).unwrap_or_else(|_| chrono::Duration::zero());
+
+        spot_prices
+            .iter()
+            .filter(|spot_price| spot_price.till > threshold)
+            .cloned()
+            .collect()
+    }
+
     pub async fn run(&self) -> Result<(), Box<dyn Error>> {
+        // lets a deployment gate on the schema migration, separate from exporting prices
+        if self.config.init_only {
+            self.init_sinks().await?;
+            info!("Initialized sinks, skipping the export in init only mode");
+            return Ok(());
+        }
+
+        // spread runs started by the same cron schedule, so they don't all hit tibber at once
+        let startup_splay = Self::random_delay(self.config.startup_splay);
+        if !startup_splay.is_zero() {
+            info!(
+                "Sleeping {} seconds before the first run",
+                startup_splay.num_seconds()
+            );
+            tokio::time::sleep(startup_splay.to_std().unwrap_or(Duration::ZERO)).await;
+        }
+
+        match self.config.run_mode {
+            RunMode::Once => self.run_once_with_timeout().await.map(|_| ()),
+            RunMode::Scheduled => loop {
+                let tomorrow_available = match self.run_once_with_timeout().await {
+                    Ok(tomorrow_available) => tomorrow_available,
+                    Err(e) => {
+                        error!("Run failed: {}", e);
+                        true
+                    }
+                };
+
+                let now = Utc::now();
+                let mut next_run = Self::next_scheduled_run(
+                    self.config.schedule_cron.as_ref(),
+                    self.config.run_interval,
+                    now,
+                ) + Self::random_delay(self.config.run_jitter);
+
+                // check back sooner if tomorrow's prices weren't published yet
+                if let (false, Some(tomorrow_retry_interval)) =
+                    (tomorrow_available, self.config.tomorrow_retry_interval)
+                {
+                    if let Ok(tomorrow_retry_interval) =
+                        chrono::Duration::from_std(tomorrow_retry_interval)
+                    {
+                        next_run = next_run.min(now + tomorrow_retry_interval);
+                    }
+                }
+
+                info!("Next run scheduled at {}", next_run.to_rfc3339());
+                tokio::time::sleep((next_run - now).to_std().unwrap_or(Duration::ZERO)).await;
+            },
+        }
+    }
+
+    /// Returns the next fire time of the cron schedule if configured, otherwise now plus the fixed
+    /// interval.
+    fn next_scheduled_run(
+        schedule_cron: Option<&Schedule>,
+        run_interval: Duration,
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        schedule_cron
+            .and_then(|schedule| schedule.after(&now).next())
+            .unwrap_or_else(|| {
+                now + chrono::Duration::from_std(run_interval)
+                    .unwrap_or_else(|_| chrono::Duration::hours(1))
+            })
+    }
+
+    /// Random delay of up to max, so multiple replicas don't hit Tibber at the same time.
+    fn random_delay(max: Duration) -> chrono::Duration {
+        let jitter_millis = max.as_millis() as i64;
+        if jitter_millis > 0 {
+            chrono::Duration::milliseconds(rand::thread_rng().gen_range(0..=jitter_millis))
+        } else {
+            chrono::Duration::zero()
+        }
+    }
+
+    /// Retrieves the consumption of the past hours and hands it to the sinks, which skip what
+    /// they already hold.
+    async fn export_consumption(&self) -> Result<(), Box<dyn Error>> {
+        let consumption_client = match &self.config.consumption_client {
+            Some(consumption_client) => consumption_client,
+            None => return Ok(()),
+        };
+
+        info!("Retrieving consumption...");
+        let consumption: Vec<Consumption> = self
+            .config
+            .retry_policy
+            .retry(
+                "Retrieving consumption",
+                || consumption_client.get_consumption(self.config.consumption_hours),
+                ExporterError::is_retryable,
+            )
+            .await?
+            .into_iter()
+            .map(|consumption| Consumption {
+                id: Some(Uuid::new_v4().to_string()),
+                source: Some(Self::account_source(
+                    &self.config.source,
+                    consumption.source.as_deref(),
+                )),
+                ..consumption
+            })
+            .collect();
+        info!("Retrieved {} hours of consumption", consumption.len());
+
+        for sink in &self.config.sinks {
+            info!("Writing consumption to {} sink...", sink.name());
+            self.retry_sink(
+                &format!("Writing consumption to {} sink", sink.name()),
+                || sink.insert_consumption(&consumption),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the level computed from the distribution of each day's prices, for sources that don't
+    /// return a level themselves.
+    fn with_computed_price_levels(spot_prices: Vec<SpotPrice>) -> Vec<SpotPrice> {
+        let levels = SpotPrice::classify_levels(&spot_prices);
+
+        spot_prices
+            .into_iter()
+            .zip(levels)
+            .map(|(spot_price, level)| SpotPrice {
+                computed_price_level: Some(level),
+                ..spot_price
+            })
+            .collect()
+    }
+
+    /// Runs a sink call with the shared retry policy; errors a sink already retried itself, or that
+    /// won't go away by themselves like an open circuit, aren't retried again.
+    #[allow(clippy::borrowed_box)]
+    async fn retry_sink<T, A, F>(&self, operation: &str, action: A) -> Result<T, Box<dyn Error>>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = Result<T, Box<dyn Error>>>,
+    {
+        self.config
+            .retry_policy
+            .retry(operation, action, |e: &Box<dyn Error>| {
+                e.downcast_ref::<ExporterError>()
+                    .map_or(true, ExporterError::is_retryable)
+            })
+            .await
+    }
+
+    /// Creates or migrates the tables of all sinks.
+    async fn init_sinks(&self) -> Result<(), Box<dyn Error>> {
+        for sink in &self.config.sinks {
+            info!("Initializing {} sink...", sink.name());
+            self.retry_sink(&format!("Initializing {} sink", sink.name()), || {
+                sink.init()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves the current day-ahead prices and validates, converts and enriches them like a run
+    /// does, without reading state or writing to any sink; for embedding the exporter as a library.
+    pub async fn fetch_only(&self) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
         let now: DateTime<Utc> = Utc::now();
 
-        info!("Initalizing BigQuery table...");
-        self.config.bigquery_client.init_table().await?;
+        let SpotPricesResult { spot_prices, .. } = self
+            .config
+            .retry_policy
+            .retry_with_requested_delay(
+                "Retrieving day-ahead prices",
+                || self.config.price_source.get_spot_prices(),
+                ExporterError::is_retryable,
+                ExporterError::retry_after,
+            )
+            .await?;
+        let spot_prices =
+            Self::validate_spot_prices(spot_prices, self.config.fail_on_invalid_prices)?;
+        let spot_prices = self
+            .config
+            .exchange_rate_client
+            .convert_spot_prices(spot_prices)
+            .await?;
+        let spot_prices = Self::enrich_spot_prices(
+            spot_prices,
+            &self.config.source,
+            self.config.sourcing_markup_price,
+            &self.config.energy_tax_rates,
+            now,
+        );
+
+        Ok(if self.config.compute_price_level {
+            Self::with_computed_price_levels(spot_prices)
+        } else {
+            spot_prices
+        })
+    }
+
+    /// Bounds a single run, so a wedged step can't hang past the next scheduled run.
+    async fn run_once_with_timeout(&self) -> Result<bool, Box<dyn Error>> {
+        match self.config.run_timeout {
+            Some(run_timeout) => tokio::time::timeout(run_timeout, self.run_once())
+                .await
+                .map_err(|_| ExporterError::RunTimeout(run_timeout))?,
+            None => self.run_once().await,
+        }
+    }
+
+    /// Runs a single export; returns whether tomorrow's prices were available, so a follow-up run
+    /// can be scheduled if they weren't.
+    async fn run_once(&self) -> Result<bool, Box<dyn Error>> {
+        let now: DateTime<Utc> = Utc::now();
 
         info!("Reading previous state...");
-        let state = self.config.state_client.read_state()?;
+        let state = self.config.state_client.read_state().await?;
 
-        info!("Retrieving day-ahead prices...");
-        let spot_prices = Retry::spawn(
-            ExponentialBackoff::from_millis(100).map(jitter).take(3),
-            || self.config.tibber_client.get_spot_prices(),
-        )
-        .await?;
+        info!(
+            "Retrieving day-ahead prices from {}...",
+            self.config.price_source.name()
+        );
+        let SpotPricesResult {
+            spot_prices,
+            tomorrow_available,
+        } = self
+            .config
+            .retry_policy
+            .retry_with_requested_delay(
+                "Retrieving day-ahead prices",
+                || self.config.price_source.get_spot_prices(),
+                ExporterError::is_retryable,
+                ExporterError::retry_after,
+            )
+            .await?;
 
         info!("Retrieved {} day-ahead prices", spot_prices.len());
+        let spot_prices =
+            Self::validate_spot_prices(spot_prices, self.config.fail_on_invalid_prices)?;
+        self.config
+            .metrics
+            .inc_tibber_prices_fetched(spot_prices.len());
 
-        info!("Storing retrieved day-ahead prices...");
-        let mut future_spot_prices: Vec<SpotPrice> = vec![];
-        let mut last_from: Option<DateTime<Utc>> = None;
-        for spot_price in &spot_prices {
-            let spot_price = SpotPrice {
-                id: Some(Uuid::new_v4().to_string()),
-                source: Some(self.config.source.clone()),
-                ..spot_price.clone()
-            };
+        // consumption keeps coming in whether or not there are new prices; its table has to exist
+        // before it gets written to
+        self.init_sinks().await?;
+        self.export_consumption().await?;
+
+        let historical_spot_prices = Self::validate_spot_prices(
+            self.get_historical_prices(&spot_prices).await?,
+            self.config.fail_on_invalid_prices,
+        )?;
+        if !historical_spot_prices.is_empty() {
+            info!(
+                "Retrieved {} historical prices to backfill",
+                historical_spot_prices.len()
+            );
+        }
+
+        if !Self::has_new_spot_prices(state.as_ref(), &spot_prices)
+            && !Self::has_unwritten_spot_prices(state.as_ref(), &historical_spot_prices)
+        {
+            info!("Nothing new to export");
+            self.mark_successful_run()?;
+            return Ok(tomorrow_available);
+        }
+
+        let spot_prices = self
+            .config
+            .exchange_rate_client
+            .convert_spot_prices(spot_prices)
+            .await?;
+        let historical_spot_prices = self
+            .config
+            .exchange_rate_client
+            .convert_spot_prices(historical_spot_prices)
+            .await?;
+
+        let spot_prices = Self::enrich_spot_prices(
+            spot_prices,
+            &self.config.source,
+            self.config.sourcing_markup_price,
+            &self.config.energy_tax_rates,
+            now,
+        );
 
-            info!("{:?}", spot_price);
-            if spot_price.till > now {
-                future_spot_prices.push(spot_price.clone());
+        let spot_prices = match &self.config.appliance_profile {
+            Some(appliance_profile) => {
+                let costs = appliance_cycle_costs(&spot_prices, appliance_profile);
+                spot_prices
+                    .into_iter()
+                    .zip(costs)
+                    .map(|(spot_price, appliance_cycle_cost)| SpotPrice {
+                        appliance_cycle_cost,
+                        ..spot_price
+                    })
+                    .collect()
             }
+            None => spot_prices,
+        };
 
-            let write_spot_price = if let Some(st) = &state {
-                spot_price.from > st.last_from
-            } else {
-                true
-            };
+        let spot_prices = if self.config.compute_price_level {
+            Self::with_computed_price_levels(spot_prices)
+        } else {
+            spot_prices
+        };
+
+        // past prices are only stored, they're of no use for windows, alerts or publishing; the
+        // dedup set keeps them from being written twice
+        let historical_spot_prices = Self::enrich_spot_prices(
+            historical_spot_prices,
+            &self.config.source,
+            self.config.sourcing_markup_price,
+            &self.config.energy_tax_rates,
+            now,
+        );
+        let mut hourly_spot_prices = if self.config.compute_price_level {
+            Self::with_computed_price_levels(historical_spot_prices)
+        } else {
+            historical_spot_prices
+        };
+        hourly_spot_prices.extend_from_slice(&spot_prices);
 
-            if write_spot_price {
-                Retry::spawn(
-                    ExponentialBackoff::from_millis(100).map(jitter).take(3),
-                    || self.config.bigquery_client.insert_spot_price(&spot_price),
-                )
-                .await?;
-                last_from = Some(spot_price.from);
+        // keep hourly prices for windowing and alerts, only coalesce what gets stored
+        let stored_spot_prices = if self.config.coalesce_equal_prices {
+            coalesce_equal_prices(&hourly_spot_prices)
+        } else {
+            hourly_spot_prices.clone()
+        };
+
+        info!("Storing retrieved day-ahead prices...");
+        let dedup_set = match &state {
+            Some(st) => DedupSet::new(Some(st.last_from), &st.written_spot_prices),
+            None => DedupSet::new(None, &[]),
+        };
+        // don't rely on the state alone, it may have been lost while the stored rows survived
+        let mut existing_spot_price_keys = HashSet::new();
+        if let Some(since) = hourly_spot_prices.iter().map(|sp| sp.from).min() {
+            for sink in &self.config.sinks {
+                existing_spot_price_keys.extend(
+                    self.retry_sink(
+                        &format!("Querying existing prices in {} sink", sink.name()),
+                        || sink.existing_spot_price_keys(&self.config.source, since),
+                    )
+                    .await?,
+                );
+            }
+        }
+
+        // dedup by the hour and only coalesce what's left, a range starting at an hour that was
+        // written before may still extend into hours that weren't
+        let mut new_hourly_spot_prices: Vec<SpotPrice> = vec![];
+        for spot_price in &hourly_spot_prices {
+            // the forecast goes to stdout as a whole when emitting json
+            if !self.config.emit_json {
+                info!("{:?}", spot_price);
+            }
+
+            if existing_spot_price_keys.contains(&spot_price.stored_key()) {
+                info!("Skipping writing, already stored");
+                if dedup_set.claim(&spot_price.key()) {
+                    dedup_set.mark_written(spot_price.key());
+                }
+                self.config.metrics.inc_spot_prices_skipped();
+            } else if dedup_set.claim(&spot_price.key()) {
+                new_hourly_spot_prices.push(spot_price.clone());
             } else {
-                info!("Skipping writing to BigQuery, already present");
+                info!("Skipping writing, already present");
+                self.config.metrics.inc_spot_prices_skipped();
             }
         }
 
-        if last_from.is_some() {
+        let new_spot_prices = if self.config.coalesce_equal_prices {
+            let coalesced_spot_prices = coalesce_equal_prices(&new_hourly_spot_prices);
+            info!(
+                "Coalesced {} day-ahead prices into {} ranges",
+                new_hourly_spot_prices.len(),
+                coalesced_spot_prices.len()
+            );
+            coalesced_spot_prices
+        } else {
+            new_hourly_spot_prices.clone()
+        };
+
+        for sink in &self.config.sinks {
+            info!("Writing to {} sink...", sink.name());
+            self.retry_sink(&format!("Writing to {} sink", sink.name()), || {
+                sink.insert(&new_spot_prices)
+            })
+            .await?;
+        }
+
+        for spot_price in &new_hourly_spot_prices {
+            dedup_set.mark_written(spot_price.key());
+        }
+        for _ in &new_spot_prices {
+            self.config.metrics.inc_spot_prices_written();
+        }
+
+        info!("Writing cheapest windows calendar...");
+        self.config
+            .ical_client
+            .write_cheapest_windows(&spot_prices)?;
+
+        // a window can't start in the past, so only consider prices that are still to come
+        let upcoming_spot_prices: Vec<SpotPrice> = spot_prices
+            .iter()
+            .filter(|spot_price| spot_price.from >= now)
+            .cloned()
+            .collect();
+        let upcoming_cheapest_window =
+            cheapest_window(&upcoming_spot_prices, self.config.cheapest_window_hours);
+
+        let snapshot = SpotPriceSnapshot {
+            stored_spot_prices: &stored_spot_prices,
+            spot_prices: &spot_prices,
+            cheapest_window: upcoming_cheapest_window.as_ref(),
+            now,
+        };
+        for sink in &self.config.sinks {
+            self.retry_sink(&format!("Writing snapshot to {} sink", sink.name()), || {
+                sink.write_snapshot(&snapshot)
+            })
+            .await?;
+        }
+
+        info!("Evaluating price alerts...");
+        self.config.alert_client.alert(&spot_prices, now).await?;
+        self.config
+            .alert_client
+            .alert_negative_prices(&spot_prices, now)
+            .await?;
+
+        if let Some(last_from) = dedup_set.last_written() {
             info!("Writing new state...");
+            // only keep written spot prices that can still be returned by tibber, older ones will
+            // never be offered for writing again
+            let earliest_from = stored_spot_prices.iter().map(|sp| sp.from).min();
+            let written_spot_prices = dedup_set
+                .written_spot_prices()
+                .into_iter()
+                .filter(|key| earliest_from.map_or(true, |ef| key.from >= ef))
+                .collect();
+
             let new_state = State {
-                future_spot_prices,
-                last_from: last_from.unwrap(),
+                future_spot_prices: Self::prune_expired_spot_prices(
+                    &stored_spot_prices,
+                    now,
+                    self.config.state_future_lookback,
+                ),
+                last_from,
+                written_spot_prices,
             };
 
             self.config.state_client.store_state(&new_state).await?;
         }
 
+        info!(
+            "Spot price summary: {}",
+            serde_json::to_string(&SpotPriceSummary::from_spot_prices(
+                &spot_prices,
+                upcoming_cheapest_window
+            ))?
+        );
+
+        if self.config.emit_json {
+            println!("{}", serde_json::to_string(&spot_prices)?);
+        }
+
+        self.mark_successful_run()?;
+
+        Ok(tomorrow_available)
+    }
+
+    fn mark_successful_run(&self) -> Result<(), Box<dyn Error>> {
+        let now = Utc::now();
+
+        self.config.last_run_client.write_last_run(now)?;
+        self.config.metrics.set_last_successful_run(now);
+        self.config.health.set_ready();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert_client::AlertClientConfig;
+    use crate::energy_tax::EnergyTaxRate;
+    use crate::error::ValidationError;
+    use crate::exchange_rate_client::ExchangeRateClientConfig;
+    use crate::ical_client::IcalClientConfig;
+    use crate::last_run_client::LastRunClientConfig;
+    use crate::state_client::{StateBackend, StateClientConfig, StateResourceKind};
+    use crate::tibber_client::{FetchWindow, TibberClientConfig};
+    use async_trait::async_trait;
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn parse_run_mode() -> Result<(), Box<dyn Error>> {
+        assert_eq!("once".parse::<RunMode>()?, RunMode::Once);
+        assert_eq!("Scheduled".parse::<RunMode>()?, RunMode::Scheduled);
+        assert!("forever".parse::<RunMode>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_scheduled_run_uses_cron_expression() -> Result<(), Box<dyn Error>> {
+        let schedule = Schedule::from_str("0 5 0,13 * * *")?;
+
+        // act
+        let next_run = ExporterService::next_scheduled_run(
+            Some(&schedule),
+            Duration::from_secs(3600),
+            "2022-05-01T10:00:00Z".parse()?,
+        );
+
+        assert_eq!(next_run, "2022-05-01T13:05:00Z".parse::<DateTime<Utc>>()?);
+
+        let next_run = ExporterService::next_scheduled_run(
+            Some(&schedule),
+            Duration::from_secs(3600),
+            "2022-05-01T13:05:00Z".parse()?,
+        );
+
+        assert_eq!(next_run, "2022-05-02T00:05:00Z".parse::<DateTime<Utc>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_scheduled_run_falls_back_to_interval() -> Result<(), Box<dyn Error>> {
+        // act
+        let next_run = ExporterService::next_scheduled_run(
+            None,
+            Duration::from_secs(1800),
+            "2022-05-01T10:00:00Z".parse()?,
+        );
+
+        assert_eq!(next_run, "2022-05-01T10:30:00Z".parse::<DateTime<Utc>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_delay_stays_within_max() {
+        // act
+        let delays: Vec<chrono::Duration> = (0..100)
+            .map(|_| ExporterService::random_delay(Duration::from_secs(5)))
+            .collect();
+
+        assert!(delays
+            .iter()
+            .all(|d| *d >= chrono::Duration::zero() && *d <= chrono::Duration::seconds(5)));
+        assert_eq!(
+            ExporterService::random_delay(Duration::ZERO),
+            chrono::Duration::zero()
+        );
+    }
+
+    fn spot_prices(start: DateTime<Utc>, count: i64) -> Vec<SpotPrice> {
+        (0..count)
+            .map(|i| SpotPrice::test_at(start + chrono::Duration::hours(i)))
+            .collect()
+    }
+
+    #[test]
+    fn enrich_spot_prices_applies_sourcing_markup_price() -> Result<(), Box<dyn Error>> {
+        let spot_prices = spot_prices("2022-05-01T00:00:00Z".parse()?, 2);
+
+        // act
+        let spot_prices = ExporterService::enrich_spot_prices(
+            spot_prices,
+            "tibber",
+            -0.0125,
+            &EnergyTaxRates::default(),
+            "2022-04-30T13:07:00Z".parse()?,
+        );
+
+        for spot_price in &spot_prices {
+            assert!(spot_price.id.is_some());
+            assert_eq!(spot_price.source.as_deref(), Some("tibber"));
+            assert_eq!(
+                spot_price.ingested_at,
+                "2022-04-30T13:07:00Z".parse::<DateTime<Utc>>()?
+            );
+
+            let row = serde_json::to_value(spot_price)?;
+            assert_eq!(row["sourcingMarkupPrice"], -0.0125);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn enrich_spot_prices_prefixes_account_with_source() -> Result<(), Box<dyn Error>> {
+        let spot_prices: Vec<SpotPrice> = spot_prices("2022-05-01T00:00:00Z".parse()?, 2)
+            .into_iter()
+            .map(|spot_price| SpotPrice {
+                source: Some("alice".to_string()),
+                ..spot_price
+            })
+            .collect();
+
+        // act
+        let spot_prices = ExporterService::enrich_spot_prices(
+            spot_prices,
+            "tibber",
+            0.0,
+            &EnergyTaxRates::default(),
+            Utc::now(),
+        );
+
+        assert_eq!(spot_prices[0].source.as_deref(), Some("tibber-alice"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn enrich_spot_prices_applies_energy_tax_rate_per_hour() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-06-30T23:00:00Z".parse()?;
+        let energy_tax_rates = EnergyTaxRates::new(
+            0.12599,
+            vec![EnergyTaxRate {
+                from: "2022-07-01T00:00:00Z".parse()?,
+                till: None,
+                rate: 0.0,
+            }],
+        );
+
+        // act
+        let spot_prices = ExporterService::enrich_spot_prices(
+            spot_prices(start, 2),
+            "tibber",
+            0.0,
+            &energy_tax_rates,
+            Utc::now(),
+        );
+
+        assert_eq!(spot_prices[0].energy_tax_price, 0.12599);
+        assert_eq!(spot_prices[1].energy_tax_price, 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn has_new_spot_prices() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices = spot_prices(start, 24);
+        let state = |last_from| State {
+            future_spot_prices: vec![],
+            last_from,
+            written_spot_prices: vec![],
+        };
+
+        assert!(ExporterService::has_new_spot_prices(None, &spot_prices));
+        assert!(ExporterService::has_new_spot_prices(
+            Some(&state(start + chrono::Duration::hours(22))),
+            &spot_prices
+        ));
+        assert!(!ExporterService::has_new_spot_prices(
+            Some(&state(start + chrono::Duration::hours(23))),
+            &spot_prices
+        ));
+        assert!(!ExporterService::has_new_spot_prices(None, &[]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn has_new_spot_prices_per_home() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices: Vec<SpotPrice> = ["home-1", "home-2"]
+            .iter()
+            .flat_map(|home_id| {
+                spot_prices(start, 24)
+                    .into_iter()
+                    .map(|spot_price| SpotPrice {
+                        home_id: Some(home_id.to_string()),
+                        ..spot_price
+                    })
+            })
+            .collect();
+        let state = |home_2_hours| State {
+            future_spot_prices: vec![],
+            last_from: start + chrono::Duration::hours(23),
+            written_spot_prices: spot_prices
+                .iter()
+                .filter(|sp| {
+                    sp.home_id.as_deref() == Some("home-1")
+                        || sp.from < start + chrono::Duration::hours(home_2_hours)
+                })
+                .map(|sp| sp.key())
+                .collect(),
+        };
+
+        // act
+        assert!(ExporterService::has_new_spot_prices(
+            Some(&state(12)),
+            &spot_prices
+        ));
+        assert!(!ExporterService::has_new_spot_prices(
+            Some(&state(24)),
+            &spot_prices
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn has_unwritten_spot_prices() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices = spot_prices(start, 24);
+        let state = |written_hours| State {
+            future_spot_prices: vec![],
+            last_from: start + chrono::Duration::hours(23),
+            written_spot_prices: spot_prices
+                .iter()
+                .filter(|sp| sp.from >= start + chrono::Duration::hours(written_hours))
+                .map(|sp| sp.key())
+                .collect(),
+        };
+
+        // act
+        assert!(ExporterService::has_unwritten_spot_prices(
+            Some(&state(12)),
+            &spot_prices
+        ));
+        assert!(!ExporterService::has_unwritten_spot_prices(
+            Some(&state(0)),
+            &spot_prices
+        ));
+        assert!(ExporterService::has_unwritten_spot_prices(
+            None,
+            &spot_prices
+        ));
+        assert!(!ExporterService::has_unwritten_spot_prices(None, &[]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn exclude_known_spot_prices() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let historical_spot_prices = spot_prices(start, 4);
+        let known_spot_prices = spot_prices(start + chrono::Duration::hours(2), 24);
+
+        // act
+        let historical_spot_prices =
+            ExporterService::exclude_known_spot_prices(historical_spot_prices, &known_spot_prices);
+
+        assert_eq!(historical_spot_prices.len(), 2);
+        assert_eq!(
+            historical_spot_prices[1].from,
+            start + chrono::Duration::hours(1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn prune_expired_spot_prices_drops_stale_entries() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices = spot_prices(start, 4);
+
+        // act
+        let future_spot_prices = ExporterService::prune_expired_spot_prices(
+            &spot_prices,
+            start + chrono::Duration::hours(2),
+            Duration::ZERO,
+        );
+
+        assert_eq!(future_spot_prices.len(), 2);
+        assert_eq!(
+            future_spot_prices[0].from,
+            start + chrono::Duration::hours(2)
+        );
+        assert_eq!(
+            future_spot_prices[1].from,
+            start + chrono::Duration::hours(3)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn prune_expired_spot_prices_keeps_lookback() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices = spot_prices(start, 4);
+
+        // act
+        let future_spot_prices = ExporterService::prune_expired_spot_prices(
+            &spot_prices,
+            start + chrono::Duration::hours(2),
+            Duration::from_secs(60 * 60),
+        );
+
+        assert_eq!(future_spot_prices.len(), 3);
+        assert_eq!(
+            future_spot_prices[0].from,
+            start + chrono::Duration::hours(1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_spot_prices_skips_invalid() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let mut spot_prices = spot_prices(start, 3);
+        spot_prices[1].market_price = f64::NAN;
+
+        // act
+        let valid_spot_prices = ExporterService::validate_spot_prices(spot_prices, false)?;
+
+        assert_eq!(valid_spot_prices.len(), 2);
+        assert_eq!(
+            valid_spot_prices[1].from,
+            start + chrono::Duration::hours(2)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_spot_prices_fails_on_invalid_when_configured() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let mut spot_prices = spot_prices(start, 3);
+        spot_prices[1].till = spot_prices[1].from;
+
+        // act
+        let result = ExporterService::validate_spot_prices(spot_prices, true);
+
+        assert!(matches!(
+            result,
+            Err(ExporterError::InvalidSpotPrice(
+                ValidationError::EmptyInterval { .. }
+            ))
+        ));
+
+        Ok(())
+    }
+
+    struct FixedPriceSource {
+        spot_prices: Vec<SpotPrice>,
+        historical_spot_prices: Vec<SpotPrice>,
+    }
+
+    #[async_trait(?Send)]
+    impl SpotPriceSource for FixedPriceSource {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError> {
+            Ok(SpotPricesResult {
+                spot_prices: self.spot_prices.clone(),
+                tomorrow_available: true,
+            })
+        }
+
+        async fn get_historical_prices(
+            &self,
+            _hours_back: u32,
+        ) -> Result<Vec<SpotPrice>, ExporterError> {
+            Ok(self.historical_spot_prices.clone())
+        }
+    }
+
+    #[derive(Default)]
+    struct MemorySinkRows {
+        initialized: bool,
+        spot_prices: Vec<SpotPrice>,
+        consumption: Vec<Consumption>,
+    }
+
+    /// Keeps rows in memory and, like a table that doesn't exist yet, fails writes until it's
+    /// initialized; clones share their rows, so they can be checked after handing one to the
+    /// service.
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        rows: Rc<RefCell<MemorySinkRows>>,
+        fail_init: bool,
+    }
+
+    impl MemorySink {
+        fn check_initialized(&self) -> Result<(), Box<dyn Error>> {
+            if !self.rows.borrow().initialized {
+                return Err(Box::<dyn Error>::from("Not found: table spot_prices"));
+            }
+
+            Ok(())
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl SpotPriceSink for MemorySink {
+        fn name(&self) -> &str {
+            "memory"
+        }
+
+        async fn init(&self) -> Result<(), Box<dyn Error>> {
+            if self.fail_init {
+                return Err(Box::<dyn Error>::from(
+                    "Dataset is in disallowed location US",
+                ));
+            }
+
+            self.rows.borrow_mut().initialized = true;
+            Ok(())
+        }
+
+        async fn existing_spot_price_keys(
+            &self,
+            _source: &str,
+            since: DateTime<Utc>,
+        ) -> Result<HashSet<StoredSpotPriceKey>, Box<dyn Error>> {
+            self.check_initialized()?;
+
+            Ok(self
+                .rows
+                .borrow()
+                .spot_prices
+                .iter()
+                .filter(|spot_price| spot_price.from >= since)
+                .map(SpotPrice::stored_key)
+                .collect())
+        }
+
+        async fn insert_consumption(
+            &self,
+            consumption: &[Consumption],
+        ) -> Result<(), Box<dyn Error>> {
+            self.check_initialized()?;
+
+            self.rows
+                .borrow_mut()
+                .consumption
+                .extend_from_slice(consumption);
+            Ok(())
+        }
+
+        async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+            self.check_initialized()?;
+
+            self.rows
+                .borrow_mut()
+                .spot_prices
+                .extend_from_slice(spot_prices);
+            Ok(())
+        }
+    }
+
+    fn exporter_service(
+        price_source: FixedPriceSource,
+        sink: &MemorySink,
+        state_file_path: &Path,
+        consumption_client: Option<TibberClient>,
+        backfill_hours: Option<u32>,
+        coalesce_equal_prices: bool,
+    ) -> Result<ExporterService, Box<dyn Error>> {
+        Ok(ExporterService::new(ExporterServiceConfig::new(
+            Box::new(price_source),
+            StateClient::new(StateClientConfig::new(
+                None,
+                StateBackend::File,
+                StateResourceKind::ConfigMap,
+                state_file_path.to_str().unwrap(),
+                "jarvis-tibber-price-exporter",
+                "",
+                "",
+                3,
+                true,
+                None,
+                "tibber",
+                None,
+            )?),
+            IcalClient::new(IcalClientConfig::new("", 3)?),
+            AlertClient::new(AlertClientConfig::new("", None, None, false, false)?),
+            ExchangeRateClient::new(ExchangeRateClientConfig::new("", "")?),
+            vec![Box::new(sink.clone())],
+            Arc::new(Metrics::default()),
+            Arc::new(Health::default()),
+            "tibber",
+            0.0,
+            EnergyTaxRates::default(),
+            coalesce_equal_prices,
+            None,
+            RunMode::Once,
+            Duration::from_secs(3600),
+            None,
+            Duration::ZERO,
+            Duration::ZERO,
+            None,
+            RetryPolicy::new(1, 1, Duration::from_millis(1), None),
+            backfill_hours,
+            None,
+            3,
+            false,
+            LastRunClient::new(LastRunClientConfig::new(None, None)?),
+            Duration::ZERO,
+            consumption_client,
+            48,
+            false,
+            false,
+            false,
+        )?))
+    }
+
+    async fn consumption_client(mock_server: &MockServer) -> Result<TibberClient, Box<dyn Error>> {
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "viewer": {
+                        "homes": [{
+                            "id": "home-1",
+                            "consumption": {
+                                "nodes": [{
+                                    "from": "2022-05-01T00:00:00+02:00",
+                                    "to": "2022-05-01T01:00:00+02:00",
+                                    "consumption": 0.5,
+                                    "consumptionUnit": "kWh",
+                                    "cost": 0.125,
+                                    "unitPrice": 0.25,
+                                    "currency": "EUR"
+                                }]
+                            }
+                        }]
+                    }
+                }
+            })))
+            .mount(mock_server)
+            .await;
+
+        Ok(TibberClient::new(TibberClientConfig::new(
+            &mock_server.uri(),
+            "token",
+            None,
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            1,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?))
+    }
+
+    #[tokio::test]
+    async fn run_once_writes_consumption_to_fresh_table() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        let sink = MemorySink::default();
+        let state_file_path = env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        // without prices there's nothing new, so only consumption gets written
+        let exporter_service = exporter_service(
+            FixedPriceSource {
+                spot_prices: vec![],
+                historical_spot_prices: vec![],
+            },
+            &sink,
+            &state_file_path,
+            Some(consumption_client(&mock_server).await?),
+            None,
+            false,
+        )?;
+
+        // act
+        exporter_service.run_once().await?;
+
+        assert_eq!(sink.rows.borrow().consumption.len(), 1);
+        assert_eq!(
+            sink.rows.borrow().consumption[0].home_id.as_deref(),
+            Some("home-1")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_once_writes_nothing_when_sink_init_fails() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        let sink = MemorySink {
+            fail_init: true,
+            ..MemorySink::default()
+        };
+        // accepts writes, so only the failed init can keep them out
+        sink.rows.borrow_mut().initialized = true;
+        let state_file_path = env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        let exporter_service = exporter_service(
+            FixedPriceSource {
+                spot_prices: spot_prices("2022-05-01T00:00:00Z".parse()?, 24),
+                historical_spot_prices: vec![],
+            },
+            &sink,
+            &state_file_path,
+            Some(consumption_client(&mock_server).await?),
+            None,
+            false,
+        )?;
+
+        // act
+        let result = exporter_service.run_once().await;
+
+        assert!(result.is_err());
+        assert!(sink.rows.borrow().consumption.is_empty());
+        assert!(sink.rows.borrow().spot_prices.is_empty());
+        assert!(!state_file_path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_once_backfills_gap_when_state_is_up_to_date() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let sink = MemorySink::default();
+        let state_file_path = env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        exporter_service(
+            FixedPriceSource {
+                spot_prices: spot_prices(start, 24),
+                historical_spot_prices: vec![],
+            },
+            &sink,
+            &state_file_path,
+            None,
+            None,
+            false,
+        )?
+        .run_once()
+        .await?;
+        // the previous day never made it to the sink
+        let exporter_service = exporter_service(
+            FixedPriceSource {
+                spot_prices: spot_prices(start, 24),
+                historical_spot_prices: spot_prices(start - chrono::Duration::hours(24), 48),
+            },
+            &sink,
+            &state_file_path,
+            None,
+            Some(48),
+            false,
+        )?;
+
+        // act
+        exporter_service.run_once().await?;
+
+        let rows = sink.rows.borrow();
+        assert_eq!(rows.spot_prices.len(), 48);
+        assert_eq!(
+            rows.spot_prices.iter().map(|sp| sp.from).min(),
+            Some(start - chrono::Duration::hours(24))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_once_writes_extended_range_without_gaps() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let sink = MemorySink::default();
+        let state_file_path = env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        exporter_service(
+            FixedPriceSource {
+                spot_prices: spot_prices(start, 24),
+                historical_spot_prices: vec![],
+            },
+            &sink,
+            &state_file_path,
+            None,
+            None,
+            true,
+        )?
+        .run_once()
+        .await?;
+        // tomorrow's prices equal today's, so the range stored yesterday now runs on into them
+        let exporter_service = exporter_service(
+            FixedPriceSource {
+                spot_prices: spot_prices(start, 48),
+                historical_spot_prices: vec![],
+            },
+            &sink,
+            &state_file_path,
+            None,
+            None,
+            true,
+        )?;
+
+        // act
+        exporter_service.run_once().await?;
+
+        let rows = sink.rows.borrow();
+        let ranges: Vec<(DateTime<Utc>, DateTime<Utc>)> = rows
+            .spot_prices
+            .iter()
+            .map(|sp| (sp.from, sp.till))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (start, start + chrono::Duration::hours(24)),
+                (
+                    start + chrono::Duration::hours(24),
+                    start + chrono::Duration::hours(48)
+                ),
+            ]
+        );
+
         Ok(())
     }
 }
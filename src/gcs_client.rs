@@ -0,0 +1,251 @@
+use crate::error::ExporterError;
+use crate::google_auth;
+use std::env;
+use std::error::Error;
+use std::time;
+use yup_oauth2::authenticator::DefaultAuthenticator;
+
+const READ_WRITE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+pub struct GcsClientConfig {
+    api_url: String,
+    bucket: String,
+    object: String,
+    /// Without an authenticator requests are sent anonymously, e.g. to a local emulator.
+    authenticator: Option<DefaultAuthenticator>,
+    client: reqwest::Client,
+}
+
+impl GcsClientConfig {
+    pub fn new(
+        api_url: &str,
+        bucket: &str,
+        object: &str,
+        authenticator: Option<DefaultAuthenticator>,
+        request_timeout: time::Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()?;
+
+        Ok(Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            object: object.to_string(),
+            authenticator,
+            client,
+        })
+    }
+
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        let api_url = env::var("STATE_GCS_API_URL")
+            .unwrap_or_else(|_| "https://storage.googleapis.com".to_string());
+        let bucket = env::var("STATE_GCS_BUCKET")?;
+        let object = env::var("STATE_GCS_OBJECT")
+            .unwrap_or_else(|_| "jarvis-tibber-price-exporter/state.yaml".to_string());
+        let request_timeout_seconds: u64 = env::var("STATE_GCS_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        let authenticator = google_auth::authenticator_from_env("gcs").await?;
+
+        Self::new(
+            &api_url,
+            &bucket,
+            &object,
+            Some(authenticator),
+            time::Duration::from_secs(request_timeout_seconds),
+        )
+    }
+}
+
+/// Reads and writes a single object in a Google Cloud Storage bucket.
+pub struct GcsClient {
+    config: GcsClientConfig,
+}
+
+impl GcsClient {
+    pub fn new(config: GcsClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(GcsClientConfig::from_env().await?))
+    }
+
+    /// Location of the object in log lines.
+    pub fn location(&self) -> String {
+        format!("gs://{}/{}", self.config.bucket, self.config.object)
+    }
+
+    fn object_url(&self, path_prefix: &str) -> Result<reqwest::Url, ExporterError> {
+        let mut url = reqwest::Url::parse(&format!("{}{}", self.config.api_url, path_prefix))
+            .map_err(|e| ExporterError::Config(format!("Invalid gcs api url: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| ExporterError::Config("Invalid gcs api url".to_string()))?
+            .push(&self.config.bucket)
+            .push("o");
+
+        Ok(url)
+    }
+
+    async fn authorize(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::RequestBuilder, ExporterError> {
+        match &self.config.authenticator {
+            Some(authenticator) => {
+                let token = authenticator
+                    .token(&[READ_WRITE_SCOPE])
+                    .await
+                    .map_err(|e| {
+                        ExporterError::Gcs(format!("Retrieving gcs token failed: {}", e))
+                    })?;
+
+                Ok(request.bearer_auth(token.as_str()))
+            }
+            None => Ok(request),
+        }
+    }
+
+    /// Returns the contents of the object, or none if it doesn't exist yet.
+    pub async fn download(&self) -> Result<Option<String>, ExporterError> {
+        let mut url = self.object_url("/storage/v1/b")?;
+        url.path_segments_mut()
+            .map_err(|_| ExporterError::Config("Invalid gcs api url".to_string()))?
+            .push(&self.config.object);
+        url.query_pairs_mut().append_pair("alt", "media");
+
+        let response = self
+            .authorize(self.config.client.get(url))
+            .await?
+            .send()
+            .await
+            .map_err(|e| ExporterError::Gcs(e.to_string()))?;
+
+        let status_code = response.status();
+        if status_code == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status_code.is_success() {
+            return Err(ExporterError::Gcs(format!(
+                "Downloading {} returned status code {}",
+                self.location(),
+                status_code
+            )));
+        }
+
+        let contents = response
+            .text()
+            .await
+            .map_err(|e| ExporterError::Gcs(e.to_string()))?;
+
+        Ok(Some(contents))
+    }
+
+    /// Creates or overwrites the object.
+    pub async fn upload(&self, contents: &str) -> Result<(), ExporterError> {
+        let mut url = self.object_url("/upload/storage/v1/b")?;
+        url.query_pairs_mut()
+            .append_pair("uploadType", "media")
+            .append_pair("name", &self.config.object);
+
+        let response = self
+            .authorize(self.config.client.post(url))
+            .await?
+            .header("content-type", "application/x-yaml")
+            .body(contents.to_string())
+            .send()
+            .await
+            .map_err(|e| ExporterError::Gcs(e.to_string()))?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(ExporterError::Gcs(format!(
+                "Uploading {} returned status code {}",
+                self.location(),
+                status_code
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn gcs_client(mock_server: &MockServer) -> Result<GcsClient, Box<dyn Error>> {
+        Ok(GcsClient::new(GcsClientConfig::new(
+            &mock_server.uri(),
+            "jarvis",
+            "exporter/state.yaml",
+            None,
+            time::Duration::from_secs(5),
+        )?))
+    }
+
+    #[tokio::test]
+    async fn download_existing_object() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/storage/v1/b/jarvis/o/exporter%2Fstate.yaml"))
+            .and(query_param("alt", "media"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string("lastFrom: 2022-05-01T00:00:00Z\n"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let contents = gcs_client(&mock_server)?.download().await?;
+
+        assert_eq!(
+            contents,
+            Some("lastFrom: 2022-05-01T00:00:00Z\n".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_missing_object() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let contents = gcs_client(&mock_server)?.download().await?;
+
+        assert_eq!(contents, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_object() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/upload/storage/v1/b/jarvis/o"))
+            .and(query_param("uploadType", "media"))
+            .and(query_param("name", "exporter/state.yaml"))
+            .and(body_string("lastFrom: 2022-05-01T00:00:00Z\n"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        gcs_client(&mock_server)?
+            .upload("lastFrom: 2022-05-01T00:00:00Z\n")
+            .await?;
+
+        Ok(())
+    }
+}
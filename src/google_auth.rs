@@ -0,0 +1,43 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use tracing::info;
+use yup_oauth2::authenticator::{ApplicationDefaultCredentialsTypes, DefaultAuthenticator};
+use yup_oauth2::{
+    ApplicationDefaultCredentialsAuthenticator, ApplicationDefaultCredentialsFlowOpts,
+    ServiceAccountAuthenticator,
+};
+
+/// Creates an authenticator for Google apis from the same credentials the bigquery client uses:
+/// the service account key file, or application default credentials if there's none or they're
+/// requested explicitly.
+pub async fn authenticator_from_env(
+    service_name: &str,
+) -> Result<DefaultAuthenticator, Box<dyn Error>> {
+    let google_application_credentials = env::var("GOOGLE_APPLICATION_CREDENTIALS")
+        .unwrap_or_else(|_| String::from("/secrets/keyfile.json"));
+    let use_default_credentials: bool = env::var("BQ_USE_DEFAULT_CREDENTIALS")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .unwrap_or(false);
+
+    if use_default_credentials || !Path::new(&google_application_credentials).exists() {
+        info!("Using application default credentials for {}", service_name);
+        let authenticator = match ApplicationDefaultCredentialsAuthenticator::builder(
+            ApplicationDefaultCredentialsFlowOpts::default(),
+        )
+        .await
+        {
+            ApplicationDefaultCredentialsTypes::ServiceAccount(builder) => builder.build().await?,
+            ApplicationDefaultCredentialsTypes::InstanceMetadata(builder) => {
+                builder.build().await?
+            }
+        };
+
+        return Ok(authenticator);
+    }
+
+    let key = yup_oauth2::read_service_account_key(&google_application_credentials).await?;
+
+    Ok(ServiceAccountAuthenticator::builder(key).build().await?)
+}
@@ -0,0 +1,142 @@
+use crate::build_info::BuildInfo;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Default)]
+pub struct Health {
+    ready: AtomicBool,
+}
+
+impl Health {
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+pub struct HealthServerConfig {
+    port: Option<u16>,
+}
+
+impl HealthServerConfig {
+    pub fn new(port: Option<u16>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self { port })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let port: Option<u16> = match env::var("HEALTH_PORT") {
+            Ok(port) if !port.is_empty() => Some(port.parse()?),
+            _ => None,
+        };
+
+        Self::new(port)
+    }
+}
+
+pub struct HealthServer {
+    config: HealthServerConfig,
+    health: Arc<Health>,
+}
+
+impl HealthServer {
+    pub fn new(config: HealthServerConfig, health: Arc<Health>) -> Self {
+        Self { config, health }
+    }
+
+    pub fn from_env(health: Arc<Health>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(HealthServerConfig::from_env()?, health))
+    }
+
+    /// Serves /healthz and /readyz in the background for kubernetes liveness and readiness probes,
+    /// along with /version.
+    pub fn spawn(self) {
+        let port = match self.config.port {
+            Some(port) => port,
+            None => return,
+        };
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        let health = self.health;
+
+        let make_service = make_service_fn(move |_| {
+            let health = health.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let health = health.clone();
+                    async move { Ok::<_, Infallible>(Self::handle(&health, &request)) }
+                }))
+            }
+        });
+
+        info!("Serving health probes on {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = Server::bind(&addr).serve(make_service).await {
+                error!("Health server failed: {}", e);
+            }
+        });
+    }
+
+    fn handle(health: &Health, request: &Request<Body>) -> Response<Body> {
+        let status = match request.uri().path() {
+            "/version" => return version_response(),
+            "/healthz" => StatusCode::OK,
+            "/readyz" if health.is_ready() => StatusCode::OK,
+            "/readyz" => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::NOT_FOUND,
+        };
+
+        Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+/// Responds with the build info as json.
+pub fn version_response() -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&BuildInfo::current()).unwrap(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(health: &Health, path: &str) -> Result<StatusCode, Box<dyn Error>> {
+        Ok(
+            HealthServer::handle(health, &Request::builder().uri(path).body(Body::empty())?)
+                .status(),
+        )
+    }
+
+    #[test]
+    fn handle() -> Result<(), Box<dyn Error>> {
+        let health = Health::default();
+
+        assert_eq!(status(&health, "/healthz")?, StatusCode::OK);
+        assert_eq!(status(&health, "/readyz")?, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status(&health, "/version")?, StatusCode::OK);
+        assert_eq!(status(&health, "/other")?, StatusCode::NOT_FOUND);
+
+        // act
+        health.set_ready();
+
+        assert_eq!(status(&health, "/readyz")?, StatusCode::OK);
+
+        Ok(())
+    }
+}
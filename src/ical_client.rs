@@ -0,0 +1,162 @@
+use crate::price_window::{cheapest_window_per_day, PriceWindow};
+use crate::types::SpotPrice;
+use chrono::{DateTime, Utc};
+use std::env;
+use std::error::Error;
+use std::fs;
+use tracing::info;
+
+pub struct IcalClientConfig {
+    path: String,
+    window_hours: usize,
+}
+
+impl IcalClientConfig {
+    pub fn new(path: &str, window_hours: usize) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            path: path.to_string(),
+            window_hours,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let path = env::var("ICAL_PATH").unwrap_or_default();
+        let window_hours: usize = env::var("ICAL_WINDOW_HOURS")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .unwrap_or(3);
+
+        Self::new(&path, window_hours)
+    }
+}
+
+pub struct IcalClient {
+    config: IcalClientConfig,
+}
+
+impl IcalClient {
+    pub fn new(config: IcalClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(IcalClientConfig::from_env()?))
+    }
+
+    pub fn write_cheapest_windows(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if self.config.path.is_empty() {
+            return Ok(());
+        }
+
+        let windows = cheapest_window_per_day(spot_prices, self.config.window_hours);
+        let calendar = Self::render_calendar(&windows, self.config.window_hours, Utc::now());
+
+        fs::write(&self.config.path, calendar)?;
+
+        info!(
+            "Wrote {} cheapest {}h windows to {}",
+            windows.len(),
+            self.config.window_hours,
+            &self.config.path
+        );
+
+        Ok(())
+    }
+
+    fn render_calendar(windows: &[PriceWindow], window_hours: usize, now: DateTime<Utc>) -> String {
+        const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+        let mut lines: Vec<String> = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//jarvis-tibber-price-exporter//EN".to_string(),
+        ];
+
+        for window in windows {
+            // windows of different homes can start at the same time, so they're part of the uid
+            let uid = [window.source.as_deref(), window.home_id.as_deref()]
+                .into_iter()
+                .flatten()
+                .fold(window.from.format(FORMAT).to_string(), |uid, part| {
+                    format!("{}-{}", uid, part)
+                });
+
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}@jarvis-tibber-price-exporter", uid));
+            lines.push(format!("DTSTAMP:{}", now.format(FORMAT)));
+            lines.push(format!("DTSTART:{}", window.from.format(FORMAT)));
+            lines.push(format!("DTEND:{}", window.till.format(FORMAT)));
+            lines.push(format!(
+                "SUMMARY:Cheapest {}h window (avg {:.4}/kWh)",
+                window_hours, window.average_price
+            ));
+            lines.push("END:VEVENT".to_string());
+        }
+
+        lines.push("END:VCALENDAR".to_string());
+
+        // RFC 5545 requires CRLF line endings
+        lines.join("\r\n") + "\r\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_calendar() {
+        let windows = vec![PriceWindow {
+            source: None,
+            home_id: None,
+            from: "2022-05-01T11:00:00Z".parse().unwrap(),
+            till: "2022-05-01T14:00:00Z".parse().unwrap(),
+            average_price: 0.1234,
+        }];
+
+        let calendar =
+            IcalClient::render_calendar(&windows, 3, "2022-04-30T13:05:00Z".parse().unwrap());
+
+        assert_eq!(
+            calendar,
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//jarvis-tibber-price-exporter//EN\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:20220501T110000Z@jarvis-tibber-price-exporter\r\n\
+             DTSTAMP:20220430T130500Z\r\n\
+             DTSTART:20220501T110000Z\r\n\
+             DTEND:20220501T140000Z\r\n\
+             SUMMARY:Cheapest 3h window (avg 0.1234/kWh)\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn render_calendar_distinguishes_homes_in_uid() {
+        let window = PriceWindow {
+            source: Some("tibber".to_string()),
+            home_id: Some("home-1".to_string()),
+            from: "2022-05-01T11:00:00Z".parse().unwrap(),
+            till: "2022-05-01T14:00:00Z".parse().unwrap(),
+            average_price: 0.1234,
+        };
+        let windows = vec![
+            window.clone(),
+            PriceWindow {
+                home_id: Some("home-2".to_string()),
+                ..window
+            },
+        ];
+
+        // act
+        let calendar =
+            IcalClient::render_calendar(&windows, 3, "2022-04-30T13:05:00Z".parse().unwrap());
+
+        assert!(calendar
+            .contains("UID:20220501T110000Z-tibber-home-1@jarvis-tibber-price-exporter\r\n"));
+        assert!(calendar
+            .contains("UID:20220501T110000Z-tibber-home-2@jarvis-tibber-price-exporter\r\n"));
+    }
+}
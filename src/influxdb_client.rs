@@ -0,0 +1,182 @@
+use crate::sink::SpotPriceSink;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use std::env;
+use std::error::Error;
+use tracing::info;
+
+pub struct InfluxDbClientConfig {
+    url: String,
+    token: String,
+    org: String,
+    bucket: String,
+    enable: bool,
+}
+
+impl InfluxDbClientConfig {
+    pub fn new(
+        url: &str,
+        token: &str,
+        org: &str,
+        bucket: &str,
+        enable: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            org: org.to_string(),
+            bucket: bucket.to_string(),
+            enable,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let enable: bool = env::var("INFLUXDB_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        if !enable {
+            return Self::new("", "", "", "", enable);
+        }
+
+        let url = env::var("INFLUXDB_URL")?;
+        let token = env::var("INFLUXDB_TOKEN")?;
+        let org = env::var("INFLUXDB_ORG")?;
+        let bucket = env::var("INFLUXDB_BUCKET")?;
+
+        Self::new(&url, &token, &org, &bucket, enable)
+    }
+}
+
+pub struct InfluxDbClient {
+    config: InfluxDbClientConfig,
+}
+
+impl InfluxDbClient {
+    pub fn new(config: InfluxDbClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(InfluxDbClientConfig::from_env()?))
+    }
+
+    /// Escapes commas, equal signs and spaces in tag values as required by the line protocol.
+    fn escape_tag_value(value: &str) -> String {
+        value
+            .replace(',', "\\,")
+            .replace('=', "\\=")
+            .replace(' ', "\\ ")
+    }
+
+    fn line_protocol(spot_price: &SpotPrice) -> String {
+        let mut tags = vec![format!(
+            "source={}",
+            Self::escape_tag_value(spot_price.source.as_deref().unwrap_or("unknown"))
+        )];
+        if !spot_price.currency.is_empty() {
+            tags.push(format!(
+                "currency={}",
+                Self::escape_tag_value(&spot_price.currency)
+            ));
+        }
+        if let Some(home_id) = &spot_price.home_id {
+            tags.push(format!("homeId={}", Self::escape_tag_value(home_id)));
+        }
+
+        format!(
+            "spot_price,{} marketPrice={},marketPriceTax={},sourcingMarkupPrice={},energyTaxPrice={} {}",
+            tags.join(","),
+            spot_price.market_price,
+            spot_price.market_price_tax,
+            spot_price.sourcing_markup_price,
+            spot_price.energy_tax_price,
+            spot_price.from.timestamp()
+        )
+    }
+
+    pub async fn insert_spot_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.config.enable || spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        let body = spot_prices
+            .iter()
+            .map(Self::line_protocol)
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/v2/write", self.config.url))
+            .query(&[
+                ("org", self.config.org.as_str()),
+                ("bucket", self.config.bucket.as_str()),
+                ("precision", "s"),
+            ])
+            .header("Authorization", format!("Token {}", self.config.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body)
+            .send()
+            .await?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(Box::<dyn Error>::from(format!(
+                "Influxdb write status code {} indicates failure: {}",
+                status_code,
+                response.text().await.unwrap_or_default()
+            )));
+        }
+
+        info!(
+            "Inserted {} spot prices into influxdb bucket {}",
+            spot_prices.len(),
+            &self.config.bucket
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for InfluxDbClient {
+    fn name(&self) -> &str {
+        "influxdb"
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.insert_spot_prices(spot_prices).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_protocol() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            source: Some("tibber".to_string()),
+            home_id: Some("my home".to_string()),
+            ingested_at: chrono::Utc::now(),
+            market_price: 0.25,
+            market_price_tax: 0.0525,
+            energy_tax_price: 0.125,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        // act
+        let line = InfluxDbClient::line_protocol(&spot_price);
+
+        assert_eq!(
+            line,
+            "spot_price,source=tibber,currency=EUR,homeId=my\\ home marketPrice=0.25,marketPriceTax=0.0525,sourcingMarkupPrice=0,energyTaxPrice=0.125 1651363200"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::sink::SpotPriceSink;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use std::env;
+use std::error::Error;
+use std::time;
+use tracing::info;
+
+pub struct KafkaClientConfig {
+    topic: String,
+    delivery_timeout: time::Duration,
+    /// Only created when enabled, so the brokers don't need to be configured otherwise.
+    producer: Option<FutureProducer>,
+}
+
+impl KafkaClientConfig {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        enable: bool,
+        brokers: &str,
+        topic: &str,
+        security_protocol: Option<String>,
+        sasl_mechanism: Option<String>,
+        sasl_username: Option<String>,
+        sasl_password: Option<String>,
+        delivery_timeout: time::Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let producer = if enable {
+            let mut client_config = ClientConfig::new();
+            client_config
+                .set("bootstrap.servers", brokers)
+                .set(
+                    "message.timeout.ms",
+                    delivery_timeout.as_millis().to_string(),
+                )
+                // keeps records with the same key in order when sends are retried
+                .set("enable.idempotence", "true");
+
+            let optional_settings = [
+                ("security.protocol", security_protocol),
+                ("sasl.mechanisms", sasl_mechanism),
+                ("sasl.username", sasl_username),
+                ("sasl.password", sasl_password),
+            ];
+            for (key, value) in optional_settings {
+                if let Some(value) = value.filter(|v| !v.is_empty()) {
+                    client_config.set(key, value);
+                }
+            }
+
+            Some(client_config.create()?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            topic: topic.to_string(),
+            delivery_timeout,
+            producer,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let enable: bool = env::var("KAFKA_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let brokers = env::var("KAFKA_BROKERS").unwrap_or_default();
+        let topic = env::var("KAFKA_TOPIC").unwrap_or_else(|_| "spot-prices".to_string());
+        let security_protocol = env::var("KAFKA_SECURITY_PROTOCOL").ok();
+        let sasl_mechanism = env::var("KAFKA_SASL_MECHANISM").ok();
+        let sasl_username = env::var("KAFKA_SASL_USERNAME").ok();
+        let sasl_password = env::var("KAFKA_SASL_PASSWORD").ok();
+        let delivery_timeout_seconds: u64 = env::var("KAFKA_DELIVERY_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        Self::new(
+            enable,
+            &brokers,
+            &topic,
+            security_protocol,
+            sasl_mechanism,
+            sasl_username,
+            sasl_password,
+            time::Duration::from_secs(delivery_timeout_seconds),
+        )
+    }
+}
+
+/// Produces new spot prices as json records to a Kafka topic.
+pub struct KafkaClient {
+    config: KafkaClientConfig,
+}
+
+impl KafkaClient {
+    pub fn new(config: KafkaClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(KafkaClientConfig::from_env()?))
+    }
+
+    /// Key and json payload per spot price; keying on source keeps the prices of a source in one
+    /// partition, and thus in order.
+    fn records(spot_prices: &[SpotPrice]) -> Result<Vec<(String, String)>, serde_json::Error> {
+        spot_prices
+            .iter()
+            .map(|spot_price| {
+                Ok((
+                    spot_price.source.clone().unwrap_or_default(),
+                    serde_json::to_string(spot_price)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Produces the spot prices newly written in this run and only returns once the brokers
+    /// acknowledged all of them, so nothing is lost when the exporter exits right after.
+    pub async fn produce_spot_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+    ) -> Result<(), Box<dyn Error>> {
+        let producer = match &self.config.producer {
+            Some(producer) => producer,
+            None => return Ok(()),
+        };
+        if spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        let records = Self::records(spot_prices)?;
+
+        // enqueue everything first so records are batched, then wait for the delivery reports
+        let mut deliveries = Vec::with_capacity(records.len());
+        for (key, payload) in &records {
+            let record = FutureRecord::to(&self.config.topic)
+                .key(key)
+                .payload(payload);
+            let delivery = producer.send_result(record).map_err(|(e, _)| e)?;
+            deliveries.push(delivery);
+        }
+
+        for delivery in deliveries {
+            match delivery.await {
+                Ok(Ok(_)) => {}
+                Ok(Err((e, _))) => return Err(Box::new(e)),
+                Err(_) => return Err(Box::<dyn Error>::from("Kafka delivery was canceled")),
+            }
+        }
+
+        producer.flush(self.config.delivery_timeout)?;
+
+        info!(
+            "Produced {} new spot prices to kafka topic {}",
+            spot_prices.len(),
+            &self.config.topic
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for KafkaClient {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.produce_spot_prices(spot_prices).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn records_are_keyed_by_source() -> Result<(), Box<dyn Error>> {
+        let from: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices = vec![SpotPrice {
+            source: Some("tibber".to_string()),
+            ..SpotPrice::test_at(from)
+        }];
+
+        // act
+        let records = KafkaClient::records(&spot_prices)?;
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, "tibber");
+        let spot_price: SpotPrice = serde_json::from_str(&records[0].1)?;
+        assert_eq!(spot_price.from, from);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn produce_spot_prices_when_disabled() -> Result<(), Box<dyn Error>> {
+        let kafka_client = KafkaClient::new(KafkaClientConfig::new(
+            false,
+            "",
+            "spot-prices",
+            None,
+            None,
+            None,
+            None,
+            time::Duration::from_secs(5),
+        )?);
+
+        // act
+        let result = kafka_client.produce_spot_prices(&[]).await;
+
+        assert!(result.is_ok());
+
+        Ok(())
+    }
+}
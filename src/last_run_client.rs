@@ -0,0 +1,164 @@
+use crate::error::ExporterError;
+use chrono::{DateTime, Utc};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::time;
+use tracing::info;
+
+pub struct LastRunClientConfig {
+    path: Option<String>,
+    max_staleness: Option<time::Duration>,
+}
+
+impl LastRunClientConfig {
+    pub fn new(
+        path: Option<String>,
+        max_staleness: Option<time::Duration>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            path,
+            max_staleness,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let path = env::var("LAST_RUN_FILE").ok().filter(|p| !p.is_empty());
+        let max_staleness: Option<time::Duration> = match env::var("MAX_STALENESS") {
+            Ok(seconds) if !seconds.is_empty() => Some(time::Duration::from_secs(seconds.parse()?)),
+            _ => None,
+        };
+
+        Self::new(path, max_staleness)
+    }
+}
+
+/// Keeps a file with the time of the last successful run, so the exporter can be monitored by the
+/// file's age without a metrics stack.
+pub struct LastRunClient {
+    config: LastRunClientConfig,
+}
+
+impl LastRunClient {
+    pub fn new(config: LastRunClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(LastRunClientConfig::from_env()?))
+    }
+
+    /// Whether the exporter is started to check the staleness of the file instead of exporting.
+    pub fn is_check_mode(&self) -> bool {
+        self.config.max_staleness.is_some()
+    }
+
+    /// Writes the time of a successful run; does nothing if no file is configured.
+    pub fn write_last_run(&self, at: DateTime<Utc>) -> Result<(), ExporterError> {
+        let path = match &self.config.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        fs::write(path, at.to_rfc3339())?;
+
+        Ok(())
+    }
+
+    /// Fails if the file wasn't modified within the max staleness, or doesn't exist at all.
+    pub fn check_staleness(&self, now: time::SystemTime) -> Result<(), ExporterError> {
+        let (path, max_staleness) = match (&self.config.path, self.config.max_staleness) {
+            (Some(path), Some(max_staleness)) => (path, max_staleness),
+            _ => {
+                return Err(ExporterError::Config(
+                    "Checking staleness requires LAST_RUN_FILE and MAX_STALENESS".to_string(),
+                ))
+            }
+        };
+
+        let modified = fs::metadata(path)?.modified()?;
+        let age = now.duration_since(modified).unwrap_or_default();
+
+        if age > max_staleness {
+            return Err(ExporterError::Config(format!(
+                "Last successful run in {} is {}s old, more than the allowed {}s",
+                path,
+                age.as_secs(),
+                max_staleness.as_secs()
+            )));
+        }
+
+        info!(
+            "Last successful run in {} is {}s old, within the allowed {}s",
+            path,
+            age.as_secs(),
+            max_staleness.as_secs()
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("{}-{}", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn check_staleness_of_fresh_file() -> Result<(), Box<dyn Error>> {
+        let path = temp_path("last-run-fresh");
+        let last_run_client = LastRunClient::new(LastRunClientConfig::new(
+            Some(path.clone()),
+            Some(time::Duration::from_secs(60)),
+        )?);
+        last_run_client.write_last_run(Utc::now())?;
+
+        // act
+        let result = last_run_client.check_staleness(time::SystemTime::now());
+
+        fs::remove_file(&path)?;
+        assert!(result.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_staleness_of_stale_file() -> Result<(), Box<dyn Error>> {
+        let path = temp_path("last-run-stale");
+        let last_run_client = LastRunClient::new(LastRunClientConfig::new(
+            Some(path.clone()),
+            Some(time::Duration::from_secs(60)),
+        )?);
+        last_run_client.write_last_run(Utc::now())?;
+
+        // act
+        let result = last_run_client
+            .check_staleness(time::SystemTime::now() + time::Duration::from_secs(120));
+
+        fs::remove_file(&path)?;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_staleness_of_missing_file() -> Result<(), Box<dyn Error>> {
+        let last_run_client = LastRunClient::new(LastRunClientConfig::new(
+            Some(temp_path("last-run-missing")),
+            Some(time::Duration::from_secs(60)),
+        )?);
+
+        // act
+        let result = last_run_client.check_staleness(time::SystemTime::now());
+
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
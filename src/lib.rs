@@ -0,0 +1,48 @@
+//! Retrieves day-ahead electricity prices from Tibber, ENTSO-E or Nord Pool and exports them to
+//! BigQuery and other sinks; the modules are public so the clients can be reused as a library.
+
+pub mod alert_client;
+pub mod bigquery_client;
+pub mod build_info;
+pub mod circuit_breaker;
+pub mod cli;
+pub mod config_file;
+pub mod csv_client;
+pub mod dedup;
+pub mod energy_tax;
+pub mod entsoe_client;
+pub mod env_validation;
+pub mod error;
+pub mod exchange_rate_client;
+pub mod exporter_service;
+pub mod gcs_client;
+pub mod google_auth;
+pub mod health_server;
+pub mod ical_client;
+pub mod influxdb_client;
+pub mod kafka_client;
+pub mod last_run_client;
+pub mod logging;
+pub mod metrics_server;
+pub mod mqtt_client;
+pub mod nordpool_client;
+pub mod parquet_client;
+pub mod postgres_client;
+pub mod price_source;
+pub mod price_window;
+pub mod proxy;
+pub mod pubsub_client;
+pub mod retry;
+pub mod sink;
+pub mod state_client;
+pub mod tibber_client;
+pub mod types;
+pub mod webhook_client;
+
+#[cfg(test)]
+#[ctor::ctor]
+fn init() {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Pretty,
+}
+
+impl FromStr for LogFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "pretty" => Ok(LogFormat::Pretty),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown log format {}, expected json or pretty",
+                s
+            ))),
+        }
+    }
+}
+
+/// Initializes tracing from LOG_FORMAT and LOG_LEVEL; logs go to stderr when stdout is reserved
+/// for json output. Records of libraries logging through the log crate end up in the same
+/// subscriber, so the filter applies to them as well.
+pub fn init_from_env(emit_json: bool) -> Result<(), Box<dyn Error>> {
+    let log_format: LogFormat = env::var("LOG_FORMAT")
+        .unwrap_or_else(|_| "json".to_string())
+        .parse()?;
+    let env_filter = env_filter(env::var("LOG_LEVEL").ok().as_deref())?;
+
+    let writer = if emit_json {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(env_filter)
+        .with_writer(writer);
+
+    match log_format {
+        LogFormat::Json => builder.json().init(),
+        LogFormat::Pretty => builder.pretty().init(),
+    }
+
+    Ok(())
+}
+
+/// Builds the filter from per-target directives like `info,reqwest=warn,kube=debug`, falling
+/// back to RUST_LOG when they're not set.
+fn env_filter(log_level: Option<&str>) -> Result<EnvFilter, Box<dyn Error>> {
+    match log_level.filter(|l| !l.trim().is_empty()) {
+        Some(directives) => Ok(EnvFilter::try_new(directives.trim())?),
+        None => Ok(EnvFilter::from_default_env()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::filter::LevelFilter;
+
+    #[test]
+    fn parse_log_format() -> Result<(), Box<dyn Error>> {
+        assert_eq!("json".parse::<LogFormat>()?, LogFormat::Json);
+        assert_eq!(" Pretty ".parse::<LogFormat>()?, LogFormat::Pretty);
+        assert!("text".parse::<LogFormat>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_filter_accepts_per_target_directives() -> Result<(), Box<dyn Error>> {
+        // act
+        let env_filter = env_filter(Some("info,reqwest=warn,jarvis_tibber_price_exporter=debug"))?;
+
+        assert_eq!(env_filter.max_level_hint(), Some(LevelFilter::DEBUG));
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_filter_rejects_invalid_directives() {
+        // act
+        let result = env_filter(Some("reqwest=loud"));
+
+        assert!(result.is_err());
+    }
+}
@@ -1,35 +1,92 @@
-mod bigquery_client;
-mod exporter_service;
-mod state_client;
-mod tibber_client;
-mod types;
-
-use bigquery_client::BigqueryClient;
-use exporter_service::ExporterService;
-use state_client::StateClient;
+use jarvis_tibber_price_exporter::alert_client::AlertClient;
+use jarvis_tibber_price_exporter::bigquery_client::BigqueryClient;
+use jarvis_tibber_price_exporter::build_info::BuildInfo;
+use jarvis_tibber_price_exporter::csv_client::CsvClient;
+use jarvis_tibber_price_exporter::exchange_rate_client::ExchangeRateClient;
+use jarvis_tibber_price_exporter::exporter_service::ExporterService;
+use jarvis_tibber_price_exporter::health_server::{Health, HealthServer};
+use jarvis_tibber_price_exporter::ical_client::IcalClient;
+use jarvis_tibber_price_exporter::influxdb_client::InfluxDbClient;
+use jarvis_tibber_price_exporter::kafka_client::KafkaClient;
+use jarvis_tibber_price_exporter::last_run_client::LastRunClient;
+use jarvis_tibber_price_exporter::metrics_server::{Metrics, MetricsServer};
+use jarvis_tibber_price_exporter::mqtt_client::MqttClient;
+use jarvis_tibber_price_exporter::parquet_client::ParquetClient;
+use jarvis_tibber_price_exporter::postgres_client::PostgresClient;
+use jarvis_tibber_price_exporter::pubsub_client::PubSubClient;
+use jarvis_tibber_price_exporter::sink::SpotPriceSink;
+use jarvis_tibber_price_exporter::state_client::StateClient;
+use jarvis_tibber_price_exporter::webhook_client::WebhookClient;
+use jarvis_tibber_price_exporter::{
+    cli, config_file, env_validation, logging, price_source, proxy,
+};
+use std::env;
 use std::error::Error;
-use tibber_client::TibberClient;
+use std::sync::Arc;
+use tracing::info;
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn Error>> {
-    tracing_subscriber::fmt()
-        .json()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    cli::apply_args_to_env();
+    config_file::apply_config_file_to_env()?;
+    proxy::apply_proxy_to_env();
 
-    let bigquery_client = BigqueryClient::from_env().await?;
-    let tibber_client = TibberClient::from_env()?;
+    // keep stdout machine-parseable when the forecast gets emitted as json
+    let emit_json: bool = env::var("EMIT_JSON")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .unwrap_or(false);
+    logging::init_from_env(emit_json)?;
+
+    let build_info = BuildInfo::current();
+    info!(
+        "Starting jarvis-tibber-price-exporter version {} ({})",
+        build_info.version, build_info.git_sha
+    );
+
+    // checking staleness is meant for a liveness probe, it doesn't need the export configuration
+    let last_run_client = LastRunClient::from_env()?;
+    if last_run_client.is_check_mode() {
+        last_run_client.check_staleness(std::time::SystemTime::now())?;
+        return Ok(());
+    }
+
+    env_validation::validate_env()?;
+
+    let price_source = price_source::from_env()?;
     let state_client = StateClient::from_env().await?;
+    let ical_client = IcalClient::from_env()?;
+    let alert_client = AlertClient::from_env()?;
+    let exchange_rate_client = ExchangeRateClient::from_env()?;
 
-    let exporter_service = ExporterService::from_env(bigquery_client, tibber_client, state_client)?;
+    let sinks: Vec<Box<dyn SpotPriceSink>> = vec![
+        Box::new(BigqueryClient::from_env().await?),
+        Box::new(PostgresClient::from_env()?),
+        Box::new(InfluxDbClient::from_env()?),
+        Box::new(WebhookClient::from_env()?),
+        Box::new(PubSubClient::from_env().await?),
+        Box::new(KafkaClient::from_env()?),
+        Box::new(CsvClient::from_env()?),
+        Box::new(ParquetClient::from_env()?),
+        Box::new(MqttClient::from_env()?),
+    ];
 
-    exporter_service.run().await
-}
+    let metrics = Arc::new(Metrics::default());
+    MetricsServer::from_env(metrics.clone())?.spawn();
+
+    let health = Arc::new(Health::default());
+    HealthServer::from_env(health.clone())?.spawn();
 
-#[cfg(test)]
-#[ctor::ctor]
-fn init() {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    let exporter_service = ExporterService::from_env(
+        price_source,
+        state_client,
+        ical_client,
+        alert_client,
+        exchange_rate_client,
+        sinks,
+        metrics,
+        health,
+    )?;
+
+    exporter_service.run().await
 }
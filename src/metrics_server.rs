@@ -0,0 +1,215 @@
+use crate::health_server::version_response;
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{error, info};
+
+#[derive(Default)]
+pub struct Metrics {
+    tibber_prices_fetched_total: AtomicU64,
+    spot_prices_written_total: AtomicU64,
+    spot_prices_skipped_total: AtomicU64,
+    last_successful_run_timestamp: AtomicI64,
+}
+
+impl Metrics {
+    pub fn inc_tibber_prices_fetched(&self, count: usize) {
+        self.tibber_prices_fetched_total
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn inc_spot_prices_written(&self) {
+        self.spot_prices_written_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_spot_prices_skipped(&self) {
+        self.spot_prices_skipped_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_last_successful_run(&self, timestamp: DateTime<Utc>) {
+        self.last_successful_run_timestamp
+            .store(timestamp.timestamp(), Ordering::Relaxed);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (name, kind, help, value) in [
+            (
+                "tibber_prices_fetched_total",
+                "counter",
+                "Number of spot prices retrieved from Tibber.",
+                self.tibber_prices_fetched_total.load(Ordering::Relaxed) as i64,
+            ),
+            (
+                "spot_prices_written_total",
+                "counter",
+                "Number of spot prices written to the sinks.",
+                self.spot_prices_written_total.load(Ordering::Relaxed) as i64,
+            ),
+            (
+                "spot_prices_skipped_total",
+                "counter",
+                "Number of spot prices skipped because they were already stored.",
+                self.spot_prices_skipped_total.load(Ordering::Relaxed) as i64,
+            ),
+            (
+                "last_successful_run_timestamp",
+                "gauge",
+                "Unix timestamp of the last successful run.",
+                self.last_successful_run_timestamp.load(Ordering::Relaxed),
+            ),
+        ] {
+            output.push_str(&format!("# HELP {} {}\n", name, help));
+            output.push_str(&format!("# TYPE {} {}\n", name, kind));
+            output.push_str(&format!("{} {}\n", name, value));
+        }
+
+        output
+    }
+}
+
+pub struct MetricsServerConfig {
+    enable: bool,
+    port: u16,
+}
+
+impl MetricsServerConfig {
+    pub fn new(enable: bool, port: u16) -> Result<Self, Box<dyn Error>> {
+        Ok(Self { enable, port })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let enable: bool = env::var("METRICS_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let port: u16 = env::var("METRICS_PORT")
+            .unwrap_or_else(|_| "9101".to_string())
+            .parse()
+            .unwrap_or(9101);
+
+        Self::new(enable, port)
+    }
+}
+
+pub struct MetricsServer {
+    config: MetricsServerConfig,
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsServer {
+    pub fn new(config: MetricsServerConfig, metrics: Arc<Metrics>) -> Self {
+        Self { config, metrics }
+    }
+
+    pub fn from_env(metrics: Arc<Metrics>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(MetricsServerConfig::from_env()?, metrics))
+    }
+
+    /// Serves /metrics and /version in the background, so they stay up between scheduled runs.
+    pub fn spawn(self) {
+        if !self.config.enable {
+            return;
+        }
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], self.config.port));
+        let metrics = self.metrics;
+
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let metrics = metrics.clone();
+                    async move { Ok::<_, Infallible>(Self::handle(&metrics, &request)) }
+                }))
+            }
+        });
+
+        info!("Serving metrics on {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = Server::bind(&addr).serve(make_service).await {
+                error!("Metrics server failed: {}", e);
+            }
+        });
+    }
+
+    fn handle(metrics: &Metrics, request: &Request<Body>) -> Response<Body> {
+        match request.uri().path() {
+            "/metrics" => Response::builder()
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(Body::from(metrics.render()))
+                .unwrap(),
+            "/version" => version_response(),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() -> Result<(), Box<dyn Error>> {
+        let metrics = Metrics::default();
+        metrics.inc_tibber_prices_fetched(24);
+        metrics.inc_spot_prices_written();
+        metrics.inc_spot_prices_skipped();
+        metrics.inc_spot_prices_skipped();
+        metrics.set_last_successful_run("2022-05-01T00:00:00Z".parse()?);
+
+        // act
+        let output = metrics.render();
+
+        assert!(output.contains("# TYPE tibber_prices_fetched_total counter\n"));
+        assert!(output.contains("\ntibber_prices_fetched_total 24\n"));
+        assert!(output.contains("\nspot_prices_written_total 1\n"));
+        assert!(output.contains("\nspot_prices_skipped_total 2\n"));
+        assert!(output.contains("# TYPE last_successful_run_timestamp gauge\n"));
+        assert!(output.contains("\nlast_successful_run_timestamp 1651363200\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn handle() -> Result<(), Box<dyn Error>> {
+        let metrics = Metrics::default();
+
+        // act
+        let response = MetricsServer::handle(
+            &metrics,
+            &Request::builder().uri("/metrics").body(Body::empty())?,
+        );
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = MetricsServer::handle(
+            &metrics,
+            &Request::builder().uri("/version").body(Body::empty())?,
+        );
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = MetricsServer::handle(
+            &metrics,
+            &Request::builder().uri("/other").body(Body::empty())?,
+        );
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use crate::price_window::PriceWindow;
+use crate::sink::{SpotPriceSink, SpotPriceSnapshot};
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+use tracing::{debug, info};
+
+pub struct MqttClientConfig {
+    host: String,
+    port: u16,
+    username: String,
+    password: String,
+    topic: String,
+    forecast_topic: String,
+    cheapest_window_topic: String,
+}
+
+impl MqttClientConfig {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: &str,
+        password: &str,
+        topic: &str,
+        forecast_topic: &str,
+        cheapest_window_topic: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            username: username.to_string(),
+            password: password.to_string(),
+            topic: topic.to_string(),
+            forecast_topic: forecast_topic.to_string(),
+            cheapest_window_topic: cheapest_window_topic.to_string(),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let host = env::var("MQTT_HOST").unwrap_or_default();
+        let port: u16 = env::var("MQTT_PORT")
+            .unwrap_or_else(|_| "1883".to_string())
+            .parse()
+            .unwrap_or(1883);
+        let username = env::var("MQTT_USERNAME").unwrap_or_default();
+        let password = env::var("MQTT_PASSWORD").unwrap_or_default();
+        let topic =
+            env::var("MQTT_TOPIC").unwrap_or_else(|_| "jarvis/tibber/spot-price".to_string());
+        let forecast_topic =
+            env::var("MQTT_FORECAST_TOPIC").unwrap_or_else(|_| format!("{}/forecast", topic));
+        let cheapest_window_topic = env::var("MQTT_CHEAPEST_WINDOW_TOPIC")
+            .unwrap_or_else(|_| format!("{}/cheapest-window", topic));
+
+        Self::new(
+            &host,
+            port,
+            &username,
+            &password,
+            &topic,
+            &forecast_topic,
+            &cheapest_window_topic,
+        )
+    }
+}
+
+pub struct MqttClient {
+    config: MqttClientConfig,
+}
+
+impl MqttClient {
+    pub fn new(config: MqttClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(MqttClientConfig::from_env()?))
+    }
+
+    /// Returns topic and json payload for the current hour, the next hour, the full forecast and
+    /// the cheapest upcoming window.
+    fn messages(
+        &self,
+        spot_prices: &[SpotPrice],
+        cheapest_window: Option<&PriceWindow>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let future_spot_prices: Vec<&SpotPrice> =
+            spot_prices.iter().filter(|sp| sp.till > now).collect();
+
+        let mut messages = vec![];
+        if let Some(current) = future_spot_prices.iter().find(|sp| sp.from <= now) {
+            messages.push((self.config.topic.clone(), serde_json::to_string(current)?));
+        }
+        if let Some(next) = future_spot_prices.iter().find(|sp| sp.from > now) {
+            messages.push((
+                format!("{}/next", self.config.topic),
+                serde_json::to_string(next)?,
+            ));
+        }
+        messages.push((
+            self.config.forecast_topic.clone(),
+            serde_json::to_string(&future_spot_prices)?,
+        ));
+        if let Some(cheapest_window) = cheapest_window {
+            messages.push((
+                self.config.cheapest_window_topic.clone(),
+                serde_json::to_string(cheapest_window)?,
+            ));
+        }
+
+        Ok(messages)
+    }
+
+    /// Publishes retained messages so subscribers like Home Assistant get a value on restart.
+    pub async fn publish(
+        &self,
+        spot_prices: &[SpotPrice],
+        cheapest_window: Option<&PriceWindow>,
+        now: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.config.host.is_empty() {
+            return Ok(());
+        }
+
+        let mut options = MqttOptions::new(
+            "jarvis-tibber-price-exporter",
+            &self.config.host,
+            self.config.port,
+        );
+        options.set_keep_alive(Duration::from_secs(30));
+        if !self.config.username.is_empty() {
+            options.set_credentials(&self.config.username, &self.config.password);
+        }
+
+        let messages = self.messages(spot_prices, cheapest_window, now)?;
+        let (client, mut event_loop) = AsyncClient::new(options, messages.len() + 1);
+
+        for (topic, payload) in &messages {
+            client
+                .publish(topic, QoS::AtLeastOnce, true, payload.as_bytes())
+                .await?;
+        }
+
+        // drive the event loop until the broker acknowledged every message
+        let mut acknowledged = 0;
+        while acknowledged < messages.len() {
+            if let Event::Incoming(Packet::PubAck(ack)) = event_loop.poll().await? {
+                debug!("Mqtt broker acknowledged message {}", ack.pkid);
+                acknowledged += 1;
+            }
+        }
+
+        client.disconnect().await?;
+
+        info!(
+            "Published {} messages to mqtt broker {}",
+            messages.len(),
+            &self.config.host
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for MqttClient {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    async fn write_snapshot(&self, snapshot: &SpotPriceSnapshot<'_>) -> Result<(), Box<dyn Error>> {
+        self.publish(snapshot.spot_prices, snapshot.cheapest_window, snapshot.now)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn messages() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices: Vec<SpotPrice> = (0..4)
+            .map(|i| SpotPrice {
+                market_price: i as f64,
+                market_price_tax: 0.0,
+                ..SpotPrice::test_at(start + Duration::hours(i))
+            })
+            .collect();
+        let mqtt_client = MqttClient::new(MqttClientConfig::new(
+            "localhost",
+            1883,
+            "",
+            "",
+            "home/spot-price",
+            "home/spot-price/forecast",
+            "home/spot-price/cheapest-window",
+        )?);
+        let cheapest_window = PriceWindow {
+            source: None,
+            home_id: None,
+            from: start + Duration::hours(2),
+            till: start + Duration::hours(4),
+            average_price: 2.5,
+        };
+
+        // act
+        let messages = mqtt_client.messages(
+            &spot_prices,
+            Some(&cheapest_window),
+            start + Duration::minutes(90),
+        )?;
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].0, "home/spot-price");
+        assert!(messages[0].1.contains("\"marketPrice\":1.0"));
+        assert_eq!(messages[1].0, "home/spot-price/next");
+        assert!(messages[1].1.contains("\"marketPrice\":2.0"));
+        assert_eq!(messages[2].0, "home/spot-price/forecast");
+        assert_eq!(
+            serde_json::from_str::<Vec<SpotPrice>>(&messages[2].1)?.len(),
+            3
+        );
+        assert_eq!(messages[3].0, "home/spot-price/cheapest-window");
+        assert!(messages[3].1.contains("\"averagePrice\":2.5"));
+
+        Ok(())
+    }
+}
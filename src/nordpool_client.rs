@@ -0,0 +1,314 @@
+use crate::error::ExporterError;
+use crate::price_source::{SpotPriceSource, SpotPricesResult};
+use crate::types::{RecordType, SpotPrice};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::time;
+use tracing::info;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DayAheadPrices {
+    currency: String,
+    #[serde(default)]
+    multi_area_entries: Vec<MultiAreaEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MultiAreaEntry {
+    delivery_start: DateTime<Utc>,
+    delivery_end: DateTime<Utc>,
+    entry_per_area: HashMap<String, f64>,
+}
+
+pub struct NordPoolClientConfig {
+    api_url: String,
+    area: String,
+    currency: String,
+    client: reqwest::Client,
+}
+
+impl NordPoolClientConfig {
+    pub fn new(
+        api_url: &str,
+        area: &str,
+        currency: &str,
+        request_timeout: time::Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()?;
+
+        Ok(Self {
+            api_url: api_url.to_string(),
+            area: area.to_string(),
+            currency: currency.to_string(),
+            client,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let api_url = env::var("NORDPOOL_API_URL").unwrap_or_else(|_| {
+            "https://dataportal-api.nordpoolgroup.com/api/DayAheadPrices".to_string()
+        });
+        let area = env::var("NORDPOOL_AREA")?;
+        let currency = env::var("NORDPOOL_CURRENCY").unwrap_or_else(|_| "EUR".to_string());
+        let request_timeout_seconds: u64 = env::var("NORDPOOL_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        Self::new(
+            &api_url,
+            &area,
+            &currency,
+            time::Duration::from_secs(request_timeout_seconds),
+        )
+    }
+}
+
+/// Retrieves hourly day-ahead prices for a delivery area from Nord Pool's public data portal.
+pub struct NordPoolClient {
+    config: NordPoolClientConfig,
+}
+
+impl NordPoolClient {
+    pub fn new(config: NordPoolClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(NordPoolClientConfig::from_env()?))
+    }
+
+    /// Retrieves the day-ahead prices for a delivery date; empty if they're not published yet.
+    async fn query(&self, delivery_date: NaiveDate) -> Result<Vec<SpotPrice>, ExporterError> {
+        let response = self
+            .config
+            .client
+            .get(&self.config.api_url)
+            .query(&[
+                (
+                    "date",
+                    delivery_date.format("%Y-%m-%d").to_string().as_str(),
+                ),
+                ("market", "DayAhead"),
+                ("deliveryArea", self.config.area.as_str()),
+                ("currency", self.config.currency.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| ExporterError::NordPoolHttp(e.to_string()))?;
+
+        let status_code = response.status();
+        // nord pool answers without content until the auction results are published
+        if status_code == reqwest::StatusCode::NO_CONTENT {
+            return Ok(vec![]);
+        }
+        if !status_code.is_success() {
+            return Err(ExporterError::NordPoolHttp(format!(
+                "Nord Pool status code {} indicates failure",
+                status_code
+            )));
+        }
+
+        let day_ahead_prices: DayAheadPrices = response.json().await.map_err(|e| {
+            ExporterError::NordPoolResponse(format!("Nord Pool response is invalid: {}", e))
+        })?;
+
+        Self::spot_prices_from_response(&day_ahead_prices, &self.config.area)
+    }
+
+    /// Maps the prices of `area` into spot prices; prices are published per MWh so they're
+    /// converted to per kWh like Tibber's.
+    fn spot_prices_from_response(
+        day_ahead_prices: &DayAheadPrices,
+        area: &str,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        day_ahead_prices
+            .multi_area_entries
+            .iter()
+            .map(|entry| {
+                let price = entry.entry_per_area.get(area).ok_or_else(|| {
+                    ExporterError::NordPoolResponse(format!(
+                        "Nord Pool response has no price for area {}",
+                        area
+                    ))
+                })?;
+
+                Ok(SpotPrice {
+                    id: None,
+                    source: None,
+                    home_id: None,
+                    record_type: RecordType::Spot,
+                    currency: day_ahead_prices.currency.clone(),
+                    price_level: None,
+                    computed_price_level: None,
+                    ingested_at: Utc::now(),
+                    from: entry.delivery_start,
+                    till: entry.delivery_end,
+                    market_price: price / 1000.0,
+                    market_price_tax: 0.0,
+                    sourcing_markup_price: 0.0,
+                    energy_tax_price: 0.0,
+                    appliance_cycle_cost: None,
+                    original_currency: None,
+                    exchange_rate: None,
+                    time_zone: None,
+                    local_date: None,
+                    resolution: Some(
+                        (entry.delivery_end - entry.delivery_start).num_minutes() as u32
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Nord Pool delivery dates follow central european time.
+    fn delivery_date(at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&Tz::CET).date_naive()
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSource for NordPoolClient {
+    fn name(&self) -> &str {
+        "nordpool"
+    }
+
+    async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError> {
+        let today = Self::delivery_date(Utc::now());
+
+        let mut spot_prices = self.query(today).await?;
+        let tomorrow_spot_prices = self.query(today + Duration::days(1)).await?;
+
+        let tomorrow_count = tomorrow_spot_prices.len();
+        if tomorrow_count > 0 {
+            info!(
+                "Tomorrow's prices are published with {} entries",
+                tomorrow_count
+            );
+        } else {
+            info!("Tomorrow's prices are not published yet");
+        }
+
+        spot_prices.extend(tomorrow_spot_prices);
+
+        Ok(SpotPricesResult {
+            spot_prices,
+            tomorrow_available: tomorrow_count > 0,
+        })
+    }
+
+    async fn get_historical_prices(
+        &self,
+        hours_back: u32,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let now = Utc::now();
+        let since = now - Duration::hours(hours_back as i64);
+        let today = Self::delivery_date(now);
+
+        let mut spot_prices = vec![];
+        let mut delivery_date = Self::delivery_date(since);
+        while delivery_date < today {
+            spot_prices.extend(self.query(delivery_date).await?);
+            delivery_date += Duration::days(1);
+        }
+
+        Ok(spot_prices
+            .into_iter()
+            .filter(|spot_price| spot_price.from >= since)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use wiremock::matchers::{method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn spot_prices_from_response() -> Result<(), Box<dyn Error>> {
+        let day_ahead_prices: DayAheadPrices =
+            serde_json::from_str(&fs::read_to_string("nordpool_day_ahead_prices.json")?)?;
+
+        // act
+        let spot_prices = NordPoolClient::spot_prices_from_response(&day_ahead_prices, "NO2")?;
+
+        assert_eq!(spot_prices.len(), 24);
+        let start: DateTime<Utc> = "2024-10-14T22:00:00Z".parse()?;
+        assert_eq!(spot_prices[0].from, start);
+        assert_eq!(spot_prices[0].till, start + Duration::hours(1));
+        assert_eq!(spot_prices[0].currency, "EUR");
+        assert!((spot_prices[0].market_price - 0.0405).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_fails_for_unknown_area() -> Result<(), Box<dyn Error>> {
+        let day_ahead_prices: DayAheadPrices =
+            serde_json::from_str(&fs::read_to_string("nordpool_day_ahead_prices.json")?)?;
+
+        // act
+        let result = NordPoolClient::spot_prices_from_response(&day_ahead_prices, "SE3");
+
+        assert!(matches!(result, Err(ExporterError::NordPoolResponse(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn delivery_date_follows_central_european_time() -> Result<(), Box<dyn Error>> {
+        // act
+        let delivery_date = NordPoolClient::delivery_date("2024-10-14T22:30:00Z".parse()?);
+
+        assert_eq!(
+            delivery_date,
+            NaiveDate::from_ymd_opt(2024, 10, 15).unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_without_tomorrow() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        let today = NordPoolClient::delivery_date(Utc::now());
+        Mock::given(method("GET"))
+            .and(query_param("date", today.format("%Y-%m-%d").to_string()))
+            .and(query_param("deliveryArea", "NO1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(fs::read_to_string("nordpool_day_ahead_prices.json")?),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&mock_server)
+            .await;
+        let nordpool_client = NordPoolClient::new(NordPoolClientConfig::new(
+            &mock_server.uri(),
+            "NO1",
+            "EUR",
+            time::Duration::from_secs(5),
+        )?);
+
+        // act
+        let result = nordpool_client.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 24);
+        assert!(!result.tomorrow_available);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+use crate::sink::{SpotPriceSink, SpotPriceSnapshot};
+use crate::types::SpotPrice;
+use arrow::array::{
+    ArrayRef, Date32Array, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+pub struct ParquetClientConfig {
+    dir: String,
+    enable: bool,
+}
+
+impl ParquetClientConfig {
+    pub fn new(dir: &str, enable: bool) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            dir: dir.to_string(),
+            enable,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let enable: bool = env::var("PARQUET_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        let dir = env::var("PARQUET_DIR").unwrap_or_else(|_| "/data".to_string());
+
+        Self::new(&dir, enable)
+    }
+}
+
+pub struct ParquetClient {
+    config: ParquetClientConfig,
+}
+
+impl ParquetClient {
+    pub fn new(config: ParquetClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(ParquetClientConfig::from_env()?))
+    }
+
+    /// Writes spot prices as parquet files, partitioned hive-style by source and date of `from`.
+    pub fn write_spot_prices(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if !self.config.enable {
+            return Ok(());
+        }
+
+        let mut partitions: BTreeMap<(String, NaiveDate), Vec<SpotPrice>> = BTreeMap::new();
+        for spot_price in spot_prices {
+            partitions
+                .entry((
+                    spot_price.source.clone().unwrap_or_default(),
+                    spot_price.from.date_naive(),
+                ))
+                .or_default()
+                .push(spot_price.clone());
+        }
+
+        for ((source, date), partition_spot_prices) in &partitions {
+            let path = Self::partition_path(Path::new(&self.config.dir), source, date);
+            Self::write_file(&path, partition_spot_prices)?;
+
+            info!(
+                "Wrote {} spot prices to parquet file {}",
+                partition_spot_prices.len(),
+                path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn partition_path(dir: &Path, source: &str, date: &NaiveDate) -> PathBuf {
+        dir.join(format!("source={}", source))
+            .join(format!("date={}", date.format("%Y-%m-%d")))
+            .join("spot_prices.parquet")
+    }
+
+    fn schema() -> Schema {
+        let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+
+        Schema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("source", DataType::Utf8, true),
+            Field::new("homeId", DataType::Utf8, true),
+            Field::new("recordType", DataType::Utf8, false),
+            Field::new("currency", DataType::Utf8, false),
+            Field::new("from", timestamp.clone(), false),
+            Field::new("till", timestamp.clone(), false),
+            Field::new("marketPrice", DataType::Float64, false),
+            Field::new("marketPriceTax", DataType::Float64, false),
+            Field::new("sourcingMarkupPrice", DataType::Float64, false),
+            Field::new("energyTaxPrice", DataType::Float64, false),
+            Field::new("priceLevel", DataType::Utf8, true),
+            Field::new("applianceCycleCost", DataType::Float64, true),
+            Field::new("totalPrice", DataType::Float64, false),
+            Field::new("originalCurrency", DataType::Utf8, true),
+            Field::new("exchangeRate", DataType::Float64, true),
+            Field::new("timeZone", DataType::Utf8, true),
+            Field::new("localDate", DataType::Date32, true),
+            Field::new("resolution", DataType::Int64, true),
+            Field::new("computedPriceLevel", DataType::Utf8, true),
+            Field::new("ingestedAt", timestamp, false),
+        ])
+    }
+
+    /// Parquet stores dates as the number of days since the unix epoch.
+    fn days_since_epoch(date: NaiveDate) -> i32 {
+        (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days() as i32
+    }
+
+    fn write_file(path: &Path, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let schema = Arc::new(Self::schema());
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.id.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.source.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.home_id.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.record_type.to_string())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.currency.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(
+                TimestampMicrosecondArray::from(
+                    spot_prices
+                        .iter()
+                        .map(|sp| sp.from.timestamp_micros())
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(
+                TimestampMicrosecondArray::from(
+                    spot_prices
+                        .iter()
+                        .map(|sp| sp.till.timestamp_micros())
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.market_price)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.market_price_tax)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.sourcing_markup_price)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.energy_tax_price)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.price_level.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.appliance_cycle_cost)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(SpotPrice::total_price)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.original_currency.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.exchange_rate)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.time_zone.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Date32Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.local_date.map(Self::days_since_epoch))
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Int64Array::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.resolution.map(i64::from))
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                spot_prices
+                    .iter()
+                    .map(|sp| sp.computed_price_level.map(|l| l.to_string()))
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(
+                TimestampMicrosecondArray::from(
+                    spot_prices
+                        .iter()
+                        .map(|sp| sp.ingested_at.timestamp_micros())
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+        ];
+
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let file = fs::File::create(path)?;
+        let mut writer = ArrowWriter::try_new(file, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        Ok(())
+    }
+}
+
+/// Rewrites the partitions of all stored prices of the run, so files don't only hold the new ones.
+#[async_trait(?Send)]
+impl SpotPriceSink for ParquetClient {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn write_snapshot(&self, snapshot: &SpotPriceSnapshot<'_>) -> Result<(), Box<dyn Error>> {
+        self.write_spot_prices(snapshot.stored_spot_prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigquery_client::BigqueryClient;
+    use chrono::{DateTime, Duration, Utc};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::collections::BTreeSet;
+    use uuid::Uuid;
+
+    #[test]
+    fn write_spot_prices() -> Result<(), Box<dyn Error>> {
+        let dir = env::temp_dir().join(Uuid::new_v4().to_string());
+        let parquet_client =
+            ParquetClient::new(ParquetClientConfig::new(dir.to_str().unwrap(), true)?);
+
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices: Vec<SpotPrice> = (0..24)
+            .map(|hour| SpotPrice {
+                id: Some(Uuid::new_v4().to_string()),
+                source: Some("tibber".to_string()),
+                ..SpotPrice::test_at(start + Duration::hours(hour))
+            })
+            .collect();
+
+        // act
+        parquet_client.write_spot_prices(&spot_prices)?;
+
+        let path = ParquetClient::partition_path(
+            &dir,
+            "tibber",
+            &NaiveDate::from_ymd_opt(2022, 5, 1).unwrap(),
+        );
+        let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&path)?)?.build()?;
+
+        let mut row_count = 0;
+        for batch in reader {
+            let batch = batch?;
+            let field_names: Vec<String> = batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect();
+            assert_eq!(
+                field_names,
+                vec![
+                    "id",
+                    "source",
+                    "homeId",
+                    "recordType",
+                    "currency",
+                    "from",
+                    "till",
+                    "marketPrice",
+                    "marketPriceTax",
+                    "sourcingMarkupPrice",
+                    "energyTaxPrice",
+                    "priceLevel",
+                    "applianceCycleCost",
+                    "totalPrice",
+                    "originalCurrency",
+                    "exchangeRate",
+                    "timeZone",
+                    "localDate",
+                    "resolution",
+                    "computedPriceLevel",
+                    "ingestedAt"
+                ]
+            );
+            row_count += batch.num_rows();
+        }
+        assert_eq!(row_count, 24);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn schema_has_the_columns_of_the_bigquery_table() {
+        // act
+        let columns: BTreeSet<String> = ParquetClient::schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+
+        let bigquery_columns: BTreeSet<String> = BigqueryClient::table_fields()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(columns, bigquery_columns);
+    }
+
+    #[test]
+    fn days_since_epoch() {
+        assert_eq!(
+            ParquetClient::days_since_epoch(NaiveDate::from_ymd_opt(2022, 5, 1).unwrap()),
+            19113
+        );
+    }
+}
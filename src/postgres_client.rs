@@ -0,0 +1,240 @@
+use crate::sink::SpotPriceSink;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::env;
+use std::error::Error;
+use tracing::info;
+
+pub struct PostgresClientConfig {
+    table: String,
+    init: bool,
+    hypertable_enable: bool,
+    pool: Option<PgPool>,
+}
+
+impl PostgresClientConfig {
+    pub fn new(
+        database_url: &str,
+        table: &str,
+        init: bool,
+        hypertable_enable: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !Self::is_valid_identifier(table) {
+            return Err(Box::<dyn Error>::from(format!(
+                "Postgres table name {} may only contain letters, digits and underscores",
+                table
+            )));
+        }
+
+        // connect lazily so an unreachable database only fails the run that needs it
+        let pool = if database_url.is_empty() {
+            None
+        } else {
+            Some(
+                PgPoolOptions::new()
+                    .max_connections(2)
+                    .connect_lazy(database_url)?,
+            )
+        };
+
+        Ok(Self {
+            table: table.to_string(),
+            init,
+            hypertable_enable,
+            pool,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let database_url = env::var("DATABASE_URL").unwrap_or_default();
+        let table = env::var("POSTGRES_TABLE").unwrap_or_else(|_| "spot_prices".to_string());
+        let init: bool = env::var("POSTGRES_INIT")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .unwrap_or(true);
+        let hypertable_enable: bool = env::var("POSTGRES_HYPERTABLE_ENABLE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        Self::new(&database_url, &table, init, hypertable_enable)
+    }
+
+    fn is_valid_identifier(identifier: &str) -> bool {
+        !identifier.is_empty()
+            && identifier
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !identifier.starts_with(|c: char| c.is_ascii_digit())
+    }
+}
+
+pub struct PostgresClient {
+    config: PostgresClientConfig,
+}
+
+impl PostgresClient {
+    pub fn new(config: PostgresClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(PostgresClientConfig::from_env()?))
+    }
+
+    /// Home id is part of the key next to source and from, so prices for multiple homes don't
+    /// overwrite each other; it's stored as an empty string when absent to keep the key non-null.
+    fn create_table_statement(table: &str) -> String {
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS {} (
+    id TEXT,
+    source TEXT NOT NULL,
+    home_id TEXT NOT NULL DEFAULT '',
+    record_type TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    price_level TEXT,
+    "from" TIMESTAMPTZ NOT NULL,
+    till TIMESTAMPTZ NOT NULL,
+    market_price DOUBLE PRECISION NOT NULL,
+    market_price_tax DOUBLE PRECISION NOT NULL,
+    sourcing_markup_price DOUBLE PRECISION NOT NULL,
+    energy_tax_price DOUBLE PRECISION NOT NULL,
+    appliance_cycle_cost DOUBLE PRECISION,
+    PRIMARY KEY (source, home_id, "from")
+)"#,
+            table
+        )
+    }
+
+    fn upsert_statement(table: &str) -> String {
+        format!(
+            r#"INSERT INTO {} (id, source, home_id, record_type, currency, price_level, "from", till, market_price, market_price_tax, sourcing_markup_price, energy_tax_price, appliance_cycle_cost)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+ON CONFLICT (source, home_id, "from") DO UPDATE SET
+    id = EXCLUDED.id,
+    record_type = EXCLUDED.record_type,
+    currency = EXCLUDED.currency,
+    price_level = EXCLUDED.price_level,
+    till = EXCLUDED.till,
+    market_price = EXCLUDED.market_price,
+    market_price_tax = EXCLUDED.market_price_tax,
+    sourcing_markup_price = EXCLUDED.sourcing_markup_price,
+    energy_tax_price = EXCLUDED.energy_tax_price,
+    appliance_cycle_cost = EXCLUDED.appliance_cycle_cost"#,
+            table
+        )
+    }
+
+    pub async fn init_table(&self) -> Result<(), Box<dyn Error>> {
+        let pool = match &self.config.pool {
+            Some(pool) if self.config.init => pool,
+            _ => return Ok(()),
+        };
+
+        sqlx::query(&Self::create_table_statement(&self.config.table))
+            .execute(pool)
+            .await?;
+
+        if self.config.hypertable_enable {
+            sqlx::query("SELECT create_hypertable($1, 'from', if_not_exists => TRUE)")
+                .bind(&self.config.table)
+                .execute(pool)
+                .await?;
+        }
+
+        info!("Initialized postgres table {}", &self.config.table);
+
+        Ok(())
+    }
+
+    /// Upserts so reruns overwrite rows for the same source, home and hour instead of duplicating
+    /// them.
+    pub async fn insert_spot_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+    ) -> Result<(), Box<dyn Error>> {
+        let pool = match &self.config.pool {
+            Some(pool) => pool,
+            None => return Ok(()),
+        };
+
+        if spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        let statement = Self::upsert_statement(&self.config.table);
+        let mut transaction = pool.begin().await?;
+
+        for spot_price in spot_prices {
+            sqlx::query(&statement)
+                .bind(&spot_price.id)
+                .bind(spot_price.source.as_deref().unwrap_or_default())
+                .bind(spot_price.home_id.as_deref().unwrap_or_default())
+                .bind(spot_price.record_type.to_string())
+                .bind(&spot_price.currency)
+                .bind(&spot_price.price_level)
+                .bind(spot_price.from)
+                .bind(spot_price.till)
+                .bind(spot_price.market_price)
+                .bind(spot_price.market_price_tax)
+                .bind(spot_price.sourcing_markup_price)
+                .bind(spot_price.energy_tax_price)
+                .bind(spot_price.appliance_cycle_cost)
+                .execute(&mut transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        info!(
+            "Upserted {} spot prices into postgres table {}",
+            spot_prices.len(),
+            &self.config.table
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for PostgresClient {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn init(&self) -> Result<(), Box<dyn Error>> {
+        self.init_table().await
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.insert_spot_prices(spot_prices).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_valid_identifier() {
+        assert!(PostgresClientConfig::is_valid_identifier("spot_prices"));
+        assert!(PostgresClientConfig::is_valid_identifier("SpotPrices2"));
+        assert!(!PostgresClientConfig::is_valid_identifier(""));
+        assert!(!PostgresClientConfig::is_valid_identifier("2spot_prices"));
+        assert!(!PostgresClientConfig::is_valid_identifier(
+            "spot_prices; DROP TABLE users"
+        ));
+    }
+
+    #[test]
+    fn upsert_statement_updates_on_conflict() {
+        // act
+        let statement = PostgresClient::upsert_statement("spot_prices");
+
+        assert!(statement.starts_with("INSERT INTO spot_prices ("));
+        assert!(statement.contains(r#"ON CONFLICT (source, home_id, "from") DO UPDATE SET"#));
+        assert!(PostgresClient::create_table_statement("spot_prices")
+            .contains(r#"PRIMARY KEY (source, home_id, "from")"#));
+    }
+}
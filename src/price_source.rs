@@ -0,0 +1,80 @@
+use crate::entsoe_client::EntsoeClient;
+use crate::error::ExporterError;
+use crate::nordpool_client::NordPoolClient;
+use crate::tibber_client::TibberClient;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+
+/// Spot prices for today and tomorrow, along with whether tomorrow's prices were published yet.
+pub struct SpotPricesResult {
+    pub spot_prices: Vec<SpotPrice>,
+    pub tomorrow_available: bool,
+}
+
+/// Origin of the day-ahead prices fed into the rest of the pipeline.
+#[async_trait(?Send)]
+pub trait SpotPriceSource {
+    /// Name used in log lines.
+    fn name(&self) -> &str;
+
+    /// Retrieves the prices for today and, once published, tomorrow.
+    async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError>;
+
+    /// Retrieves hourly prices for the past `hours_back` hours, to backfill after a gap in exports.
+    async fn get_historical_prices(&self, hours_back: u32)
+        -> Result<Vec<SpotPrice>, ExporterError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSource {
+    Tibber,
+    Entsoe,
+    NordPool,
+}
+
+impl FromStr for PriceSource {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "tibber" => Ok(PriceSource::Tibber),
+            "entsoe" => Ok(PriceSource::Entsoe),
+            "nordpool" => Ok(PriceSource::NordPool),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown price source {}, expected tibber, entsoe or nordpool",
+                s
+            ))),
+        }
+    }
+}
+
+/// Creates the price source selected with `PRICE_SOURCE`, defaulting to Tibber.
+pub fn from_env() -> Result<Box<dyn SpotPriceSource>, Box<dyn Error>> {
+    let price_source: PriceSource = env::var("PRICE_SOURCE")
+        .unwrap_or_else(|_| "tibber".to_string())
+        .parse()?;
+
+    Ok(match price_source {
+        PriceSource::Tibber => Box::new(TibberClient::from_env()?),
+        PriceSource::Entsoe => Box::new(EntsoeClient::from_env()?),
+        PriceSource::NordPool => Box::new(NordPoolClient::from_env()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_price_source() -> Result<(), Box<dyn Error>> {
+        assert_eq!("tibber".parse::<PriceSource>()?, PriceSource::Tibber);
+        assert_eq!(" ENTSOE ".parse::<PriceSource>()?, PriceSource::Entsoe);
+        assert_eq!("NordPool".parse::<PriceSource>()?, PriceSource::NordPool);
+        assert!("epex".parse::<PriceSource>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use crate::types::SpotPrice;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceWindow {
+    pub source: Option<String>,
+    pub home_id: Option<String>,
+    pub from: DateTime<Utc>,
+    pub till: DateTime<Utc>,
+    pub average_price: f64,
+}
+
+/// Sums the total prices over the `hours` starting at the price at `start`, weighted by how many
+/// of those hours each price covers, so it works for any resolution. Returns None if the prices
+/// don't cover the whole window without gaps.
+fn window_price_hours(spot_prices: &[SpotPrice], start: usize, hours: usize) -> Option<f64> {
+    let from = spot_prices.get(start)?.from;
+    let till = from + Duration::hours(hours as i64);
+
+    let mut covered_till = from;
+    let mut price_hours = 0.0;
+    for spot_price in &spot_prices[start..] {
+        if spot_price.from != covered_till || spot_price.till <= spot_price.from {
+            return None;
+        }
+
+        let end = spot_price.till.min(till);
+        price_hours +=
+            spot_price.total_price() * (end - covered_till).num_seconds() as f64 / 3600.0;
+        covered_till = end;
+
+        if covered_till >= till {
+            return Some(price_hours);
+        }
+    }
+
+    None
+}
+
+/// Returns the contiguous `hours` with the lowest average price, or None if `spot_prices` don't
+/// cover that many hours without gaps. Expects `spot_prices` to be sorted by `from`.
+pub fn cheapest_window(spot_prices: &[SpotPrice], hours: usize) -> Option<PriceWindow> {
+    if hours == 0 {
+        return None;
+    }
+
+    let mut cheapest: Option<PriceWindow> = None;
+    for (i, spot_price) in spot_prices.iter().enumerate() {
+        let average_price = match window_price_hours(spot_prices, i, hours) {
+            Some(price_hours) => price_hours / hours as f64,
+            None => continue,
+        };
+
+        if cheapest
+            .as_ref()
+            .map_or(true, |c| average_price < c.average_price)
+        {
+            cheapest = Some(PriceWindow {
+                source: spot_price.source.clone(),
+                home_id: spot_price.home_id.clone(),
+                from: spot_price.from,
+                till: spot_price.from + Duration::hours(hours as i64),
+                average_price,
+            });
+        }
+    }
+
+    cheapest
+}
+
+/// Returns the cheapest window per day of each source and home, the day being the local date when
+/// known and the utc date otherwise.
+pub fn cheapest_window_per_day(spot_prices: &[SpotPrice], hours: usize) -> Vec<PriceWindow> {
+    let mut days: BTreeMap<(Option<String>, Option<String>, NaiveDate), Vec<SpotPrice>> =
+        BTreeMap::new();
+    for spot_price in spot_prices {
+        days.entry((
+            spot_price.source.clone(),
+            spot_price.home_id.clone(),
+            spot_price
+                .local_date
+                .unwrap_or_else(|| spot_price.from.date_naive()),
+        ))
+        .or_default()
+        .push(spot_price.clone());
+    }
+
+    days.values()
+        .filter_map(|day_spot_prices| cheapest_window(day_spot_prices, hours))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplianceProfile {
+    pub kwh: f64,
+    pub duration_hours: usize,
+}
+
+/// Returns for each spot price the cost of running the appliance starting at its `from`,
+/// assuming the appliance consumes its energy evenly over its duration. Returns None for starts
+/// where the cycle would extend past the available prices.
+pub fn appliance_cycle_costs(
+    spot_prices: &[SpotPrice],
+    appliance_profile: &ApplianceProfile,
+) -> Vec<Option<f64>> {
+    let hours = appliance_profile.duration_hours;
+    if hours == 0 {
+        return vec![None; spot_prices.len()];
+    }
+
+    let kwh_per_hour = appliance_profile.kwh / hours as f64;
+
+    (0..spot_prices.len())
+        .map(|i| {
+            window_price_hours(spot_prices, i, hours).map(|price_hours| price_hours * kwh_per_hour)
+        })
+        .collect()
+}
+
+const PRICE_EPSILON: f64 = 1e-9;
+
+fn equal_prices(a: &SpotPrice, b: &SpotPrice) -> bool {
+    a.source == b.source
+        && a.home_id == b.home_id
+        && a.record_type == b.record_type
+        && a.currency == b.currency
+        && (a.market_price - b.market_price).abs() < PRICE_EPSILON
+        && (a.market_price_tax - b.market_price_tax).abs() < PRICE_EPSILON
+        && (a.sourcing_markup_price - b.sourcing_markup_price).abs() < PRICE_EPSILON
+        && (a.energy_tax_price - b.energy_tax_price).abs() < PRICE_EPSILON
+}
+
+/// Merges runs of adjacent spot prices with equal price components into a single spot price
+/// spanning their combined interval. Expects `spot_prices` to be sorted by `from`.
+pub fn coalesce_equal_prices(spot_prices: &[SpotPrice]) -> Vec<SpotPrice> {
+    let mut coalesced: Vec<SpotPrice> = vec![];
+
+    for spot_price in spot_prices {
+        if let Some(last) = coalesced.last_mut() {
+            if last.till == spot_price.from && equal_prices(last, spot_price) {
+                last.till = spot_price.till;
+                continue;
+            }
+        }
+
+        coalesced.push(spot_price.clone());
+    }
+
+    coalesced
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotPriceSummary {
+    pub count: usize,
+    pub min_total_price: Option<f64>,
+    pub max_total_price: Option<f64>,
+    pub average_total_price: Option<f64>,
+    pub cheapest_from: Option<DateTime<Utc>>,
+    pub most_expensive_from: Option<DateTime<Utc>>,
+    pub cheapest_window: Option<PriceWindow>,
+}
+
+impl SpotPriceSummary {
+    pub fn from_spot_prices(
+        spot_prices: &[SpotPrice],
+        cheapest_window: Option<PriceWindow>,
+    ) -> Self {
+        let cheapest = spot_prices
+            .iter()
+            .min_by(|a, b| a.total_price().total_cmp(&b.total_price()));
+        let most_expensive = spot_prices
+            .iter()
+            .max_by(|a, b| a.total_price().total_cmp(&b.total_price()));
+        let average_total_price = if spot_prices.is_empty() {
+            None
+        } else {
+            Some(
+                spot_prices.iter().map(SpotPrice::total_price).sum::<f64>()
+                    / spot_prices.len() as f64,
+            )
+        };
+
+        Self {
+            count: spot_prices.len(),
+            min_total_price: cheapest.map(SpotPrice::total_price),
+            max_total_price: most_expensive.map(SpotPrice::total_price),
+            average_total_price,
+            cheapest_from: cheapest.map(|sp| sp.from),
+            most_expensive_from: most_expensive.map(|sp| sp.from),
+            cheapest_window,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot_prices(start: DateTime<Utc>, market_prices: &[f64]) -> Vec<SpotPrice> {
+        spot_prices_with_resolution(start, Duration::hours(1), market_prices)
+    }
+
+    fn spot_prices_with_resolution(
+        start: DateTime<Utc>,
+        resolution: Duration,
+        market_prices: &[f64],
+    ) -> Vec<SpotPrice> {
+        market_prices
+            .iter()
+            .enumerate()
+            .map(|(i, market_price)| SpotPrice {
+                till: start + resolution * (i as i32 + 1),
+                market_price: *market_price,
+                market_price_tax: 0.0,
+                ..SpotPrice::test_at(start + resolution * i as i32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cheapest_window_finds_lowest_average() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[0.3, 0.2, 0.1, 0.15, 0.4]);
+
+        let window = cheapest_window(&spot_prices, 2).unwrap();
+
+        assert_eq!(window.from, start + Duration::hours(2));
+        assert_eq!(window.till, start + Duration::hours(4));
+        assert!((window.average_price - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cheapest_window_returns_none_when_too_few_prices() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[0.3, 0.2]);
+
+        assert_eq!(cheapest_window(&spot_prices, 3), None);
+    }
+
+    #[test]
+    fn cheapest_window_spans_hours_of_quarter_hour_prices() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices_with_resolution(
+            start,
+            Duration::minutes(15),
+            &[0.4, 0.4, 0.4, 0.4, 0.1, 0.1, 0.1, 0.1, 0.2, 0.2, 0.2, 0.2],
+        );
+
+        // act
+        let window = cheapest_window(&spot_prices, 2).unwrap();
+
+        assert_eq!(window.from, start + Duration::hours(1));
+        assert_eq!(window.till, start + Duration::hours(3));
+        assert!((window.average_price - 0.15).abs() < 1e-9);
+        assert_eq!(cheapest_window(&spot_prices, 4), None);
+    }
+
+    #[test]
+    fn cheapest_window_per_day_groups_by_local_day_and_home() {
+        let start: DateTime<Utc> = "2022-04-30T22:00:00Z".parse().unwrap();
+        let local_date = NaiveDate::from_ymd_opt(2022, 5, 1);
+        let spot_prices: Vec<SpotPrice> = ["home-1", "home-2"]
+            .iter()
+            .flat_map(|home_id| {
+                spot_prices(start, &[0.3, 0.1, 0.1, 0.3])
+                    .into_iter()
+                    .map(|spot_price| SpotPrice {
+                        home_id: Some(home_id.to_string()),
+                        local_date,
+                        ..spot_price
+                    })
+            })
+            .collect();
+
+        // act
+        let windows = cheapest_window_per_day(&spot_prices, 2);
+
+        assert_eq!(windows.len(), 2);
+        for (window, home_id) in windows.iter().zip(["home-1", "home-2"]) {
+            assert_eq!(window.home_id.as_deref(), Some(home_id));
+            assert_eq!(window.from, start + Duration::hours(1));
+            assert!((window.average_price - 0.1).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn coalesce_equal_prices_merges_adjacent_equal_hours() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[0.1, 0.2, 0.2, 0.2, 0.1, 0.1]);
+
+        let coalesced = coalesce_equal_prices(&spot_prices);
+
+        assert_eq!(coalesced.len(), 3);
+        assert_eq!(coalesced[0].from, start);
+        assert_eq!(coalesced[0].till, start + Duration::hours(1));
+        assert_eq!(coalesced[1].from, start + Duration::hours(1));
+        assert_eq!(coalesced[1].till, start + Duration::hours(4));
+        assert_eq!(coalesced[2].from, start + Duration::hours(4));
+        assert_eq!(coalesced[2].till, start + Duration::hours(6));
+    }
+
+    #[test]
+    fn coalesce_equal_prices_does_not_merge_across_gaps_or_sources() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let mut spot_prices = spot_prices(start, &[0.2, 0.2, 0.2]);
+        spot_prices[1].source = Some("other".to_string());
+        spot_prices.push(SpotPrice {
+            from: start + Duration::hours(4),
+            till: start + Duration::hours(5),
+            ..spot_prices[2].clone()
+        });
+
+        let coalesced = coalesce_equal_prices(&spot_prices);
+
+        assert_eq!(coalesced.len(), 4);
+    }
+
+    #[test]
+    fn appliance_cycle_costs_for_two_hour_appliance() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices = spot_prices(start, &[0.1, 0.3, 0.2, 0.4]);
+
+        let costs = appliance_cycle_costs(
+            &spot_prices,
+            &ApplianceProfile {
+                kwh: 2.0,
+                duration_hours: 2,
+            },
+        );
+
+        assert_eq!(costs.len(), 4);
+        assert!((costs[0].unwrap() - 0.4).abs() < 1e-9);
+        assert!((costs[1].unwrap() - 0.5).abs() < 1e-9);
+        assert!((costs[2].unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(costs[3], None);
+    }
+
+    #[test]
+    fn appliance_cycle_costs_for_quarter_hour_prices() {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse().unwrap();
+        let spot_prices =
+            spot_prices_with_resolution(start, Duration::minutes(15), &[0.1, 0.2, 0.3, 0.4, 0.5]);
+
+        // act
+        let costs = appliance_cycle_costs(
+            &spot_prices,
+            &ApplianceProfile {
+                kwh: 2.0,
+                duration_hours: 1,
+            },
+        );
+
+        assert_eq!(costs.len(), 5);
+        assert!((costs[0].unwrap() - 0.5).abs() < 1e-9);
+        assert!((costs[1].unwrap() - 0.7).abs() < 1e-9);
+        assert_eq!(costs[2], None);
+    }
+
+    #[test]
+    fn spot_price_summary() -> Result<(), Box<dyn std::error::Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+
+        // act
+        let spot_prices = spot_prices(start, &[0.5, 0.25, 1.0, 0.25]);
+        let summary =
+            SpotPriceSummary::from_spot_prices(&spot_prices, cheapest_window(&spot_prices, 2));
+
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.min_total_price, Some(0.25));
+        assert_eq!(summary.max_total_price, Some(1.0));
+        assert_eq!(summary.average_total_price, Some(0.5));
+        assert_eq!(summary.cheapest_from, Some(start + Duration::hours(1)));
+        assert_eq!(
+            summary.most_expensive_from,
+            Some(start + Duration::hours(2))
+        );
+        assert_eq!(
+            summary.cheapest_window,
+            Some(PriceWindow {
+                source: None,
+                home_id: None,
+                from: start,
+                till: start + Duration::hours(2),
+                average_price: 0.375,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_price_summary_of_no_prices() {
+        // act
+        let summary = SpotPriceSummary::from_spot_prices(&[], None);
+
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.average_total_price, None);
+        assert_eq!(summary.cheapest_from, None);
+    }
+}
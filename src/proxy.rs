@@ -0,0 +1,36 @@
+use std::env;
+
+/// Env vars reqwest reads its proxy from when a client isn't configured with one explicitly.
+const STANDARD_PROXY_ENV_VARS: [&str; 2] = ["HTTPS_PROXY", "HTTP_PROXY"];
+
+/// Exports PROXY_URL as the standard proxy env vars, so http clients created inside libraries,
+/// like the one of the bigquery client, send their requests through the proxy as well. Standard
+/// proxy env vars that are already set take precedence.
+pub fn apply_proxy_to_env() {
+    let proxy_url = match proxy_url_from_env() {
+        Some(proxy_url) => proxy_url,
+        None => return,
+    };
+
+    for env_var in STANDARD_PROXY_ENV_VARS {
+        if env::var(env_var).is_err() && env::var(env_var.to_lowercase()).is_err() {
+            env::set_var(env_var, &proxy_url);
+        }
+    }
+}
+
+pub fn proxy_url_from_env() -> Option<String> {
+    env::var("PROXY_URL").ok().filter(|p| !p.is_empty())
+}
+
+/// Routes all requests of the client through `proxy_url` if set; without it reqwest falls back to
+/// the standard proxy env vars.
+pub fn apply_proxy(
+    client_builder: reqwest::ClientBuilder,
+    proxy_url: Option<&str>,
+) -> Result<reqwest::ClientBuilder, reqwest::Error> {
+    match proxy_url {
+        Some(proxy_url) => Ok(client_builder.proxy(reqwest::Proxy::all(proxy_url)?)),
+        None => Ok(client_builder),
+    }
+}
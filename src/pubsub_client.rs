@@ -0,0 +1,292 @@
+use crate::google_auth;
+use crate::retry::RetryPolicy;
+use crate::sink::SpotPriceSink;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::time;
+use tracing::info;
+use yup_oauth2::authenticator::DefaultAuthenticator;
+
+const PUBSUB_SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+
+/// Pub/Sub accepts at most 1000 messages per publish request.
+const MAX_MESSAGES_PER_REQUEST: usize = 1000;
+
+#[derive(Serialize, Debug)]
+struct PublishRequest {
+    messages: Vec<PubsubMessage>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PubsubMessage {
+    data: String,
+    attributes: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ordering_key: Option<String>,
+}
+
+pub struct PubSubClientConfig {
+    api_url: String,
+    project_id: String,
+    topic: String,
+    ordering_enable: bool,
+    /// Without an authenticator requests are sent anonymously, e.g. to the emulator.
+    authenticator: Option<DefaultAuthenticator>,
+    retry_policy: RetryPolicy,
+    client: reqwest::Client,
+}
+
+impl PubSubClientConfig {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        api_url: &str,
+        project_id: &str,
+        topic: &str,
+        ordering_enable: bool,
+        authenticator: Option<DefaultAuthenticator>,
+        timeout: time::Duration,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+        Ok(Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            project_id: project_id.to_string(),
+            topic: topic.to_string(),
+            ordering_enable,
+            authenticator,
+            retry_policy,
+            client,
+        })
+    }
+
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        let api_url = env::var("PUBSUB_API_URL")
+            .unwrap_or_else(|_| "https://pubsub.googleapis.com".to_string());
+        let project_id = env::var("PUBSUB_PROJECT_ID")
+            .or_else(|_| env::var("BQ_PROJECT_ID"))
+            .unwrap_or_default();
+        let topic = env::var("PUBSUB_TOPIC").unwrap_or_default();
+        let ordering_enable: bool = env::var("PUBSUB_ORDERING_ENABLE")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .unwrap_or(true);
+        let timeout_seconds: u64 = env::var("PUBSUB_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .unwrap_or(10);
+
+        // only authenticate when publishing, so the credentials aren't needed otherwise
+        let authenticator = if topic.is_empty() {
+            None
+        } else {
+            Some(google_auth::authenticator_from_env("pubsub").await?)
+        };
+
+        Self::new(
+            &api_url,
+            &project_id,
+            &topic,
+            ordering_enable,
+            authenticator,
+            time::Duration::from_secs(timeout_seconds),
+            RetryPolicy::from_env(),
+        )
+    }
+}
+
+/// Publishes new spot prices as json messages to a Google Pub/Sub topic.
+pub struct PubSubClient {
+    config: PubSubClientConfig,
+}
+
+impl PubSubClient {
+    pub fn new(config: PubSubClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(PubSubClientConfig::from_env().await?))
+    }
+
+    /// One message per spot price; with ordering enabled they're keyed on source, so subscribers
+    /// with message ordering receive the prices of a source in order.
+    fn messages(
+        spot_prices: &[SpotPrice],
+        ordering_enable: bool,
+    ) -> Result<Vec<PubsubMessage>, serde_json::Error> {
+        spot_prices
+            .iter()
+            .map(|spot_price| {
+                let source = spot_price.source.clone().unwrap_or_default();
+
+                Ok(PubsubMessage {
+                    data: STANDARD.encode(serde_json::to_vec(spot_price)?),
+                    attributes: HashMap::from([
+                        ("source".to_string(), source.clone()),
+                        ("from".to_string(), spot_price.from.to_rfc3339()),
+                    ]),
+                    ordering_key: if ordering_enable && !source.is_empty() {
+                        Some(source)
+                    } else {
+                        None
+                    },
+                })
+            })
+            .collect()
+    }
+
+    async fn publish(&self, publish_request: &PublishRequest) -> Result<(), String> {
+        let url = format!(
+            "{}/v1/projects/{}/topics/{}:publish",
+            self.config.api_url, self.config.project_id, self.config.topic
+        );
+
+        let mut request = self.config.client.post(url).json(publish_request);
+        if let Some(authenticator) = &self.config.authenticator {
+            let token = authenticator
+                .token(&[PUBSUB_SCOPE])
+                .await
+                .map_err(|e| format!("Retrieving pubsub token failed: {}", e))?;
+            request = request.bearer_auth(token.as_str());
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(format!(
+                "Pubsub status code {} indicates failure",
+                status_code
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Publishes the spot prices newly written in this run; does nothing if there are none.
+    pub async fn publish_spot_prices(
+        &self,
+        spot_prices: &[SpotPrice],
+    ) -> Result<(), Box<dyn Error>> {
+        if self.config.topic.is_empty() || spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        let messages = Self::messages(spot_prices, self.config.ordering_enable)?;
+        let mut messages = messages.into_iter().peekable();
+        while messages.peek().is_some() {
+            let publish_request = PublishRequest {
+                messages: messages.by_ref().take(MAX_MESSAGES_PER_REQUEST).collect(),
+            };
+
+            self.config
+                .retry_policy
+                .retry(
+                    "Publishing to pubsub",
+                    || self.publish(&publish_request),
+                    |_: &String| true,
+                )
+                .await?;
+        }
+
+        info!(
+            "Published {} new spot prices to pubsub topic {}",
+            spot_prices.len(),
+            &self.config.topic
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for PubSubClient {
+    fn name(&self) -> &str {
+        "pubsub"
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.publish_spot_prices(spot_prices).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn spot_prices(source: Option<&str>) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+
+        Ok((0..2)
+            .map(|i| SpotPrice {
+                source: source.map(|s| s.to_string()),
+                ..SpotPrice::test_at(start + chrono::Duration::hours(i))
+            })
+            .collect())
+    }
+
+    #[test]
+    fn messages_are_ordered_by_source() -> Result<(), Box<dyn Error>> {
+        // act
+        let messages = PubSubClient::messages(&spot_prices(Some("tibber"))?, true)?;
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].ordering_key, Some("tibber".to_string()));
+        assert_eq!(messages[1].attributes["from"], "2022-05-01T01:00:00+00:00");
+        let spot_price: SpotPrice = serde_json::from_slice(&STANDARD.decode(&messages[0].data)?)?;
+        assert_eq!(
+            spot_price.from,
+            "2022-05-01T00:00:00Z".parse::<DateTime<Utc>>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn messages_without_ordering() -> Result<(), Box<dyn Error>> {
+        // act
+        let messages = PubSubClient::messages(&spot_prices(Some("tibber"))?, false)?;
+
+        assert!(messages.iter().all(|m| m.ordering_key.is_none()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn publish_spot_prices() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/projects/my-project/topics/prices:publish"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"messageIds":["1","2"]}"#))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let pubsub_client = PubSubClient::new(PubSubClientConfig::new(
+            &mock_server.uri(),
+            "my-project",
+            "prices",
+            true,
+            None,
+            time::Duration::from_secs(5),
+            RetryPolicy::new(1, 1, time::Duration::from_secs(1), None),
+        )?);
+
+        // act
+        pubsub_client
+            .publish_spot_prices(&spot_prices(Some("tibber"))?)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+use std::cell::Cell;
+use std::env;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use tokio_retry::{Condition, RetryIf};
+use tracing::{info, warn};
+
+/// Retries with exponentially growing delays capped at `max_delay`, until attempts run out or the
+/// optional deadline passes, whichever comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    base_millis: u64,
+    max_delay: Duration,
+    deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    pub fn new(
+        max_attempts: usize,
+        base_millis: u64,
+        max_delay: Duration,
+        deadline: Option<Duration>,
+    ) -> Self {
+        Self {
+            max_attempts,
+            base_millis,
+            max_delay,
+            deadline,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let max_attempts: usize = env::var("RETRY_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse()
+            .unwrap_or(4);
+        let base_millis: u64 = env::var("RETRY_BASE_MILLIS")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse()
+            .unwrap_or(100);
+        let max_delay_millis: u64 = env::var("RETRY_MAX_DELAY_MILLIS")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse()
+            .unwrap_or(10000);
+        let deadline: Option<Duration> = env::var("RETRY_DEADLINE_SECONDS")
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .map(Duration::from_secs);
+
+        Self::new(
+            max_attempts,
+            base_millis,
+            Duration::from_millis(max_delay_millis),
+            deadline,
+        )
+    }
+
+    pub fn with_max_attempts(self, max_attempts: usize) -> Self {
+        Self {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Delays between attempts; the first attempt isn't delayed so it's one shorter than the number
+    /// of attempts. A delay requested by the failed attempt is waited out when it's longer than the
+    /// backoff, and no delay sleeps past the deadline.
+    fn strategy<'a>(
+        &self,
+        started: Instant,
+        requested_delay: &'a Cell<Option<Duration>>,
+    ) -> impl Iterator<Item = Duration> + 'a {
+        let deadline = self.deadline;
+
+        ExponentialBackoff::from_millis(self.base_millis)
+            .max_delay(self.max_delay)
+            .map(jitter)
+            .map(move |delay| {
+                requested_delay
+                    .take()
+                    .map_or(delay, |requested| requested.max(delay))
+            })
+            .map(move |delay| match deadline {
+                Some(deadline) => delay.min(deadline.saturating_sub(started.elapsed())),
+                None => delay,
+            })
+            .take(self.max_attempts.saturating_sub(1))
+    }
+
+    fn deadline_passed(&self, started: Instant) -> bool {
+        self.deadline
+            .map_or(false, |deadline| started.elapsed() >= deadline)
+    }
+
+    /// Runs the action until it succeeds, the condition deems the error permanent, attempts run
+    /// out or the deadline passes, logging how many attempts it took. Returns the last error when
+    /// giving up.
+    pub async fn retry<T, E, A, F, C>(
+        &self,
+        operation: &str,
+        action: A,
+        condition: C,
+    ) -> Result<T, E>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = Result<T, E>>,
+        C: Condition<E>,
+        E: Display,
+    {
+        self.retry_with_requested_delay(operation, action, condition, |_: &E| None)
+            .await
+    }
+
+    /// Like `retry`, but waits at least the delay an error asks for, like a Retry-After, before the
+    /// next attempt. Gives up right away when that delay would run past the deadline, and doesn't
+    /// wait at all when no attempts are left.
+    pub async fn retry_with_requested_delay<T, E, A, F, C, D>(
+        &self,
+        operation: &str,
+        mut action: A,
+        mut condition: C,
+        requested_delay: D,
+    ) -> Result<T, E>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = Result<T, E>>,
+        C: Condition<E>,
+        D: Fn(&E) -> Option<Duration>,
+        E: Display,
+    {
+        let attempts = AtomicUsize::new(0);
+        let started = Instant::now();
+        let next_delay = Cell::new(None);
+
+        let result = RetryIf::spawn(
+            self.strategy(started, &next_delay),
+            || {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                action()
+            },
+            |e: &E| {
+                if !condition.should_retry(e) {
+                    return false;
+                }
+                if self.deadline_passed(started) {
+                    warn!("{} reached its retry deadline", operation);
+                    return false;
+                }
+
+                let delay = requested_delay(e);
+                if let (Some(delay), Some(deadline)) = (delay, self.deadline) {
+                    if delay >= deadline.saturating_sub(started.elapsed()) {
+                        warn!(
+                            "{} can't wait {}s before retrying within its retry deadline",
+                            operation,
+                            delay.as_secs()
+                        );
+                        return false;
+                    }
+                }
+                next_delay.set(delay);
+
+                true
+            },
+        )
+        .await;
+
+        let attempts = attempts.into_inner();
+        match &result {
+            Ok(_) if attempts > 1 => info!("{} succeeded after {} attempts", operation, attempts),
+            Ok(_) => {}
+            Err(e) => warn!("{} failed after {} attempts: {}", operation, attempts, e),
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retry_stops_after_max_attempts() {
+        let policy = RetryPolicy::new(3, 1, Duration::from_secs(10), None);
+        let calls = AtomicUsize::new(0);
+
+        // act
+        let result: Result<(), String> = policy
+            .retry(
+                "test",
+                || {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    async { Err("failed".to_string()) }
+                },
+                |_: &String| true,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_stops_on_permanent_error() {
+        let policy = RetryPolicy::new(3, 1, Duration::from_secs(10), None);
+        let calls = AtomicUsize::new(0);
+
+        // act
+        let result: Result<(), String> = policy
+            .retry(
+                "test",
+                || {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    async { Err("permanent".to_string()) }
+                },
+                |_: &String| false,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_returns_first_success() {
+        let policy = RetryPolicy::new(3, 1, Duration::from_secs(10), None);
+        let calls = AtomicUsize::new(0);
+
+        // act
+        let result: Result<usize, String> = policy
+            .retry(
+                "test",
+                || {
+                    let call = calls.fetch_add(1, Ordering::Relaxed) + 1;
+                    async move {
+                        if call < 2 {
+                            Err("failed".to_string())
+                        } else {
+                            Ok(call)
+                        }
+                    }
+                },
+                |_: &String| true,
+            )
+            .await;
+
+        assert_eq!(result, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn retry_stops_at_deadline() {
+        let policy = RetryPolicy::new(
+            100,
+            10,
+            Duration::from_millis(10),
+            Some(Duration::from_millis(50)),
+        );
+        let calls = AtomicUsize::new(0);
+
+        // act
+        let result: Result<(), String> = policy
+            .retry(
+                "test",
+                || {
+                    let call = calls.fetch_add(1, Ordering::Relaxed) + 1;
+                    async move { Err(format!("failed {}", call)) }
+                },
+                |_: &String| true,
+            )
+            .await;
+
+        let calls = calls.load(Ordering::Relaxed);
+        assert!(calls > 1 && calls < 100);
+        assert_eq!(result, Err(format!("failed {}", calls)));
+    }
+
+    #[test]
+    fn strategy_caps_delays() {
+        let policy = RetryPolicy::new(6, 10, Duration::from_millis(500), None);
+
+        // act
+        let delays: Vec<Duration> = policy.strategy(Instant::now(), &Cell::new(None)).collect();
+
+        assert_eq!(delays.len(), 5);
+        assert!(delays.iter().all(|d| *d <= Duration::from_millis(500)));
+    }
+
+    #[tokio::test]
+    async fn retry_waits_requested_delay() {
+        let policy = RetryPolicy::new(2, 1, Duration::from_millis(1), None);
+        let started = Instant::now();
+
+        // act
+        let result: Result<(), String> = policy
+            .retry_with_requested_delay(
+                "test",
+                || async { Err("rate limited".to_string()) },
+                |_: &String| true,
+                |_: &String| Some(Duration::from_millis(50)),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn retry_doesnt_wait_requested_delay_after_last_attempt() {
+        let policy = RetryPolicy::new(1, 1, Duration::from_millis(1), None);
+        let started = Instant::now();
+
+        // act
+        let result: Result<(), String> = policy
+            .retry_with_requested_delay(
+                "test",
+                || async { Err("rate limited".to_string()) },
+                |_: &String| true,
+                |_: &String| Some(Duration::from_secs(60)),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_when_requested_delay_passes_deadline() {
+        let policy = RetryPolicy::new(3, 1, Duration::from_millis(1), Some(Duration::from_secs(1)));
+        let calls = AtomicUsize::new(0);
+        let started = Instant::now();
+
+        // act
+        let result: Result<(), String> = policy
+            .retry_with_requested_delay(
+                "test",
+                || {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    async { Err("rate limited".to_string()) }
+                },
+                |_: &String| true,
+                |_: &String| Some(Duration::from_secs(60)),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
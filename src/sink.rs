@@ -0,0 +1,58 @@
+use crate::price_window::PriceWindow;
+use crate::types::{Consumption, SpotPrice, StoredSpotPriceKey};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::error::Error;
+
+/// All prices of a single run, whether or not they were written in a previous run.
+pub struct SpotPriceSnapshot<'a> {
+    /// Prices as stored, so including backfilled prices and coalesced if configured.
+    pub stored_spot_prices: &'a [SpotPrice],
+    /// Day-ahead prices as retrieved.
+    pub spot_prices: &'a [SpotPrice],
+    pub cheapest_window: Option<&'a PriceWindow>,
+    pub now: DateTime<Utc>,
+}
+
+/// Destination for newly retrieved spot prices; each sink decides from its own configuration
+/// whether it's enabled and turns into a no-op otherwise.
+#[async_trait(?Send)]
+pub trait SpotPriceSink {
+    /// Name used in log lines and as metrics label.
+    fn name(&self) -> &str;
+
+    /// Prepares the sink before anything is inserted, e.g. by creating or migrating a table.
+    async fn init(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Returns the keys of prices of the source the sink already holds since `since`, for sinks
+    /// that can be queried; used to avoid writing duplicates when the state got lost.
+    async fn existing_spot_price_keys(
+        &self,
+        _source: &str,
+        _since: DateTime<Utc>,
+    ) -> Result<HashSet<StoredSpotPriceKey>, Box<dyn Error>> {
+        Ok(HashSet::new())
+    }
+
+    /// Stores measured consumption, for sinks that keep it next to the prices.
+    async fn insert_consumption(&self, _consumption: &[Consumption]) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Stores the spot prices that haven't been written in a previous run.
+    async fn insert(&self, _spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Receives all prices of the run instead of only the new ones, for sinks that rewrite or
+    /// publish their output as a whole.
+    async fn write_snapshot(
+        &self,
+        _snapshot: &SpotPriceSnapshot<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
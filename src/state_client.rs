@@ -1,38 +1,127 @@
+use crate::bigquery_client::BigqueryClient;
+use crate::error::ExporterError;
+use crate::gcs_client::GcsClient;
 use crate::types::*;
-use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::{ByteString, NamespaceResourceScope};
 use kube::{
-    api::{Api, PostParams},
-    Client,
+    api::{Api, ObjectMeta, PostParams},
+    Client, Resource,
 };
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::env;
 use std::error::Error;
+use std::fmt;
+use std::fmt::Debug;
 use std::fs;
 use std::path::Path;
-use tracing::info;
+use std::str::FromStr;
+use std::time;
+use tracing::{error, info, warn};
+
+const NAMESPACE_FILE_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateBackend {
+    ConfigMap,
+    File,
+    Bigquery,
+    Gcs,
+}
+
+impl FromStr for StateBackend {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "configmap" => Ok(StateBackend::ConfigMap),
+            "file" => Ok(StateBackend::File),
+            "bigquery" => Ok(StateBackend::Bigquery),
+            "gcs" => Ok(StateBackend::Gcs),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown state backend {}, expected configmap, file, bigquery or gcs",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateResourceKind {
+    ConfigMap,
+    Secret,
+}
+
+impl FromStr for StateResourceKind {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "configmap" => Ok(StateResourceKind::ConfigMap),
+            "secret" => Ok(StateResourceKind::Secret),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown state resource kind {}, expected configmap or secret",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StateResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateResourceKind::ConfigMap => write!(f, "configmap"),
+            StateResourceKind::Secret => write!(f, "secret"),
+        }
+    }
+}
 
 pub struct StateClientConfig {
     kube_client: Option<kube::Client>,
+    backend: StateBackend,
+    resource_kind: StateResourceKind,
     state_file_path: String,
     state_file_configmap_name: String,
     current_namespace: String,
+    fallback_file_path: String,
+    conflict_retries: usize,
     enable: bool,
+    bigquery_client: Option<BigqueryClient>,
+    source: String,
+    gcs_client: Option<GcsClient>,
 }
 
 impl StateClientConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         kube_client: Option<kube::Client>,
+        backend: StateBackend,
+        resource_kind: StateResourceKind,
         state_file_path: &str,
         state_file_configmap_name: &str,
         current_namespace: &str,
+        fallback_file_path: &str,
+        conflict_retries: usize,
         enable: bool,
+        bigquery_client: Option<BigqueryClient>,
+        source: &str,
+        gcs_client: Option<GcsClient>,
     ) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
             kube_client,
+            backend,
+            resource_kind,
             state_file_path: state_file_path.into(),
             state_file_configmap_name: state_file_configmap_name.into(),
             current_namespace: current_namespace.into(),
+            fallback_file_path: fallback_file_path.into(),
+            conflict_retries,
             enable,
+            bigquery_client,
+            source: source.into(),
+            gcs_client,
         })
     }
 
@@ -41,32 +130,80 @@ impl StateClientConfig {
             .unwrap_or_else(|_| "false".to_string())
             .parse()
             .unwrap_or(false);
+        let backend: StateBackend = env::var("STATE_BACKEND")
+            .unwrap_or_else(|_| "configmap".to_string())
+            .parse()?;
+        let resource_kind: StateResourceKind = env::var("STATE_RESOURCE_KIND")
+            .unwrap_or_else(|_| "configmap".to_string())
+            .parse()?;
 
         let state_file_path =
             env::var("STATE_FILE_PATH").unwrap_or_else(|_| "/configs/state.yaml".to_string());
         let state_file_configmap_name = env::var("STATE_FILE_CONFIG_MAP_NAME")
             .unwrap_or_else(|_| "jarvis-tibber-price-exporter".to_string());
+        let fallback_file_path = env::var("STATE_FALLBACK_FILE_PATH").unwrap_or_default();
+        let conflict_retries: usize = env::var("STATE_CONFLICT_RETRIES")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()
+            .unwrap_or(3);
+
+        let source = env::var("SOURCE").unwrap_or_default();
 
-        if enable {
+        let (kube_client, current_namespace) = if enable && backend == StateBackend::ConfigMap {
             let kube_client: kube::Client = Client::try_default().await?;
             let current_namespace =
-                fs::read_to_string("/var/run/secrets/kubernetes.io/serviceaccount/namespace")?;
-
-            Self::new(
-                Some(kube_client),
-                &state_file_path,
-                &state_file_configmap_name,
-                &current_namespace,
-                enable,
-            )
+                Self::resolve_namespace(env::var("STATE_NAMESPACE").ok(), NAMESPACE_FILE_PATH)?;
+
+            (Some(kube_client), current_namespace)
         } else {
-            Self::new(
-                None,
-                &state_file_path,
-                &state_file_configmap_name,
-                "",
-                enable,
-            )
+            (None, "".to_string())
+        };
+
+        // the prices table doubles as state, so it needs its own client to query it
+        let bigquery_client = if enable && backend == StateBackend::Bigquery {
+            Some(BigqueryClient::from_env().await?)
+        } else {
+            None
+        };
+
+        let gcs_client = if enable && backend == StateBackend::Gcs {
+            Some(GcsClient::from_env().await?)
+        } else {
+            None
+        };
+
+        Self::new(
+            kube_client,
+            backend,
+            resource_kind,
+            &state_file_path,
+            &state_file_configmap_name,
+            &current_namespace,
+            &fallback_file_path,
+            conflict_retries,
+            enable,
+            bigquery_client,
+            &source,
+            gcs_client,
+        )
+    }
+
+    fn resolve_namespace(
+        namespace_override: Option<String>,
+        namespace_file_path: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        if let Some(namespace) = namespace_override {
+            if !namespace.trim().is_empty() {
+                return Ok(namespace.trim().to_string());
+            }
+        }
+
+        match fs::read_to_string(namespace_file_path) {
+            Ok(namespace) => Ok(namespace.trim().to_string()),
+            Err(e) => Err(Box::<dyn Error>::from(format!(
+                "STATE_NAMESPACE is not set and reading namespace from {} failed: {}",
+                namespace_file_path, e
+            ))),
         }
     }
 }
@@ -84,11 +221,19 @@ impl StateClient {
         Ok(Self::new(StateClientConfig::from_env().await?))
     }
 
-    pub fn read_state(&self) -> Result<Option<State>, Box<dyn std::error::Error>> {
+    pub async fn read_state(&self) -> Result<Option<State>, Box<dyn std::error::Error>> {
         if !self.config.enable {
             return Ok(None);
         }
 
+        if let Some(bigquery_client) = &self.config.bigquery_client {
+            return self.read_state_from_bigquery(bigquery_client).await;
+        }
+
+        if let Some(gcs_client) = &self.config.gcs_client {
+            return self.read_state_from_gcs(gcs_client).await;
+        }
+
         let state_file_contents = match fs::read_to_string(&self.config.state_file_path) {
             Ok(c) => c,
             Err(_) => return Ok(Option::None),
@@ -104,78 +249,674 @@ impl StateClient {
         Ok(last_state)
     }
 
-    async fn get_state_configmap(&self) -> Result<ConfigMap, Box<dyn std::error::Error>> {
-        let configmaps_api: Api<ConfigMap> = Api::namespaced(
-            self.config.kube_client.as_ref().unwrap().clone(),
-            &self.config.current_namespace,
-        );
+    /// Derives the state from the latest price stored for the source; written prices aren't
+    /// tracked, the sinks' existing rows keep those from being written twice.
+    async fn read_state_from_bigquery(
+        &self,
+        bigquery_client: &BigqueryClient,
+    ) -> Result<Option<State>, Box<dyn std::error::Error>> {
+        let last_state = bigquery_client
+            .last_from(&self.config.source)
+            .await?
+            .map(|last_from| State {
+                future_spot_prices: vec![],
+                last_from,
+                written_spot_prices: vec![],
+            });
 
-        let config_map = configmaps_api
-            .get(&self.config.state_file_configmap_name)
-            .await?;
+        info!(
+            "Read state from bigquery for source {}",
+            &self.config.source
+        );
 
-        Ok(config_map)
+        Ok(last_state)
     }
 
-    async fn update_state_configmap(
+    async fn read_state_from_gcs(
         &self,
-        config_map: &ConfigMap,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let configmaps_api: Api<ConfigMap> = Api::namespaced(
+        gcs_client: &GcsClient,
+    ) -> Result<Option<State>, Box<dyn std::error::Error>> {
+        let state_file_contents = match gcs_client.download().await? {
+            Some(c) => c,
+            None => return Ok(Option::None),
+        };
+
+        let last_state: Option<State> = match serde_yaml::from_str(&state_file_contents) {
+            Ok(lm) => Some(lm),
+            Err(_) => return Ok(Option::None),
+        };
+
+        info!("Read state from {}", gcs_client.location());
+
+        Ok(last_state)
+    }
+
+    fn state_api<K>(&self) -> Api<K>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>,
+    {
+        Api::namespaced(
             self.config.kube_client.as_ref().unwrap().clone(),
             &self.config.current_namespace,
-        );
+        )
+    }
+
+    async fn get_state_resource<K>(&self) -> Result<Option<K>, kube::Error>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + DeserializeOwned
+            + Debug,
+    {
+        self.state_api::<K>()
+            .get_opt(&self.config.state_file_configmap_name)
+            .await
+    }
+
+    async fn create_state_resource<K>(&self, resource: &K) -> Result<(), kube::Error>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + DeserializeOwned
+            + Serialize
+            + Debug,
+    {
+        self.state_api::<K>()
+            .create(&PostParams::default(), resource)
+            .await?;
+
+        Ok(())
+    }
 
-        configmaps_api
+    async fn update_state_resource<K>(&self, resource: &K) -> Result<(), kube::Error>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + DeserializeOwned
+            + Serialize
+            + Debug,
+    {
+        self.state_api::<K>()
             .replace(
                 &self.config.state_file_configmap_name,
                 &PostParams::default(),
-                config_map,
+                resource,
             )
             .await?;
 
         Ok(())
     }
 
-    pub async fn store_state(&self, state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    fn state_resource_metadata(&self) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(self.config.state_file_configmap_name.clone()),
+            namespace: Some(self.config.current_namespace.clone()),
+            ..ObjectMeta::default()
+        }
+    }
+
+    async fn upsert_state_configmap(
+        &self,
+        state_file_name: &str,
+        yaml_data: &str,
+    ) -> Result<(), kube::Error> {
+        match self.get_state_resource::<ConfigMap>().await? {
+            Some(mut config_map) => {
+                // update data in configmap
+                let mut data = config_map.data.unwrap_or_default();
+                data.insert(state_file_name.to_string(), yaml_data.to_string());
+                config_map.data = Some(data);
+
+                // update configmap to have state available when the application runs the next time and for other applications
+                self.update_state_resource(&config_map).await
+            }
+            None => {
+                // first run, create the configmap holding the state
+                let config_map = ConfigMap {
+                    metadata: self.state_resource_metadata(),
+                    data: Some(BTreeMap::from([(
+                        state_file_name.to_string(),
+                        yaml_data.to_string(),
+                    )])),
+                    ..ConfigMap::default()
+                };
+
+                info!(
+                    "Configmap {} does not exist yet, creating it",
+                    &self.config.state_file_configmap_name
+                );
+                self.create_state_resource(&config_map).await
+            }
+        }
+    }
+
+    /// Secret data is base64 encoded on the wire by ByteString; when mounted as a volume kubelet
+    /// decodes it again, so read_state can keep reading plain yaml from the state file path.
+    async fn upsert_state_secret(
+        &self,
+        state_file_name: &str,
+        yaml_data: &str,
+    ) -> Result<(), kube::Error> {
+        match self.get_state_resource::<Secret>().await? {
+            Some(mut secret) => {
+                let mut data = secret.data.unwrap_or_default();
+                data.insert(
+                    state_file_name.to_string(),
+                    ByteString(yaml_data.as_bytes().to_vec()),
+                );
+                secret.data = Some(data);
+
+                self.update_state_resource(&secret).await
+            }
+            None => {
+                let secret = Secret {
+                    metadata: self.state_resource_metadata(),
+                    data: Some(BTreeMap::from([(
+                        state_file_name.to_string(),
+                        ByteString(yaml_data.as_bytes().to_vec()),
+                    )])),
+                    ..Secret::default()
+                };
+
+                info!(
+                    "Secret {} does not exist yet, creating it",
+                    &self.config.state_file_configmap_name
+                );
+                self.create_state_resource(&secret).await
+            }
+        }
+    }
+
+    /// Serializes state to yaml; on failure logs the error and spools the state to the fallback
+    /// file (if configured) so it can be restored by hand, since the prices it describes are
+    /// already stored.
+    fn serialize_state<T: Serialize + Debug>(&self, state: &T) -> Result<String, ExporterError> {
+        let err = match serde_yaml::to_string(state) {
+            Ok(yd) => return Ok(yd),
+            Err(e) => e,
+        };
+
+        // the state can hold a whole day of prices, so it only goes into the fallback file
+        error!("Failed serializing state to yaml: {}", err);
+
+        if !self.config.fallback_file_path.is_empty() {
+            let fallback_data = match serde_json::to_string_pretty(state) {
+                Ok(jd) => jd,
+                Err(_) => format!("{:#?}", state),
+            };
+
+            match fs::write(&self.config.fallback_file_path, fallback_data) {
+                Ok(()) => warn!(
+                    "Spooled state to fallback file {}",
+                    &self.config.fallback_file_path
+                ),
+                Err(e) => error!(
+                    "Failed spooling state to fallback file {}: {}",
+                    &self.config.fallback_file_path, e
+                ),
+            }
+        }
+
+        Err(ExporterError::Yaml(err))
+    }
+
+    pub async fn store_state(&self, state: &State) -> Result<(), ExporterError> {
         if !self.config.enable {
             return Ok(());
         }
 
-        // retrieve configmap
-        let mut config_map = self.get_state_configmap().await?;
+        if self.config.backend == StateBackend::Bigquery {
+            info!("State is derived from the bigquery table, nothing to store");
+
+            return Ok(());
+        }
 
         // marshal state to yaml
-        let yaml_data = match serde_yaml::to_string(state) {
-            Ok(yd) => yd,
-            Err(e) => return Err(Box::new(e)),
-        };
+        let yaml_data = self.serialize_state(state)?;
+
+        if self.config.backend == StateBackend::File {
+            fs::write(&self.config.state_file_path, yaml_data)?;
+
+            info!("Stored last state in file {}", &self.config.state_file_path);
+
+            return Ok(());
+        }
+
+        if let Some(gcs_client) = &self.config.gcs_client {
+            gcs_client.upload(&yaml_data).await?;
+
+            info!("Stored last state in {}", gcs_client.location());
+
+            return Ok(());
+        }
 
         // extract filename from config file path
         let state_file_path = Path::new(&self.config.state_file_path);
         let state_file_name = match state_file_path.file_name() {
             Some(filename) => match filename.to_str() {
                 Some(filename) => String::from(filename),
-                None => return Err(Box::<dyn Error>::from("No filename found in path")),
+                None => {
+                    return Err(ExporterError::Config(
+                        "No filename found in path".to_string(),
+                    ))
+                }
             },
-            None => return Err(Box::<dyn Error>::from("No filename found in path")),
+            None => {
+                return Err(ExporterError::Config(
+                    "No filename found in path".to_string(),
+                ))
+            }
         };
 
-        // update data in configmap
-        let mut data: std::collections::BTreeMap<String, String> = match config_map.data {
-            Some(d) => d,
-            None => BTreeMap::new(),
-        };
-        data.insert(state_file_name, yaml_data);
-        config_map.data = Some(data);
+        // read-modify-write, re-reading the configmap or secret whenever someone else updated it in between
+        let mut attempt = 0;
+        loop {
+            let result = match self.config.resource_kind {
+                StateResourceKind::ConfigMap => {
+                    self.upsert_state_configmap(&state_file_name, &yaml_data)
+                        .await
+                }
+                StateResourceKind::Secret => {
+                    self.upsert_state_secret(&state_file_name, &yaml_data).await
+                }
+            };
 
-        // update configmap to have state available when the application runs the next time and for other applications
-        self.update_state_configmap(&config_map).await?;
+            match result {
+                Ok(()) => break,
+                Err(kube::Error::Api(e))
+                    if e.code == 409 && attempt < self.config.conflict_retries =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "Conflict updating {} {}, retrying ({}/{})",
+                        self.config.resource_kind,
+                        &self.config.state_file_configmap_name,
+                        attempt,
+                        self.config.conflict_retries
+                    );
+                    // back off a little so an overlapping instance can finish its update first
+                    tokio::time::sleep(time::Duration::from_millis(100 * attempt as u64)).await;
+                }
+                Err(kube::Error::Api(e)) if e.code == 409 => {
+                    return Err(ExporterError::StateConflict(format!(
+                        "Updating {} {} kept conflicting after {} retries",
+                        self.config.resource_kind,
+                        &self.config.state_file_configmap_name,
+                        self.config.conflict_retries
+                    )))
+                }
+                Err(e) => return Err(ExporterError::Kube(e)),
+            }
+        }
 
         info!(
-            "Stored last state in configmap {}",
-            &self.config.state_file_configmap_name
+            "Stored last state in {} {}",
+            self.config.resource_kind, &self.config.state_file_configmap_name
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, Request, Response, StatusCode};
+    use hyper::Body;
+
+    #[test]
+    fn resolve_namespace_prefers_override() -> Result<(), Box<dyn Error>> {
+        let namespace = StateClientConfig::resolve_namespace(
+            Some("jarvis".to_string()),
+            "/path/that/does/not/exist",
+        )?;
+
+        assert_eq!(namespace, "jarvis");
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_namespace_fails_without_override_or_file() {
+        let result = StateClientConfig::resolve_namespace(None, "/path/that/does/not/exist");
+
+        assert!(result.is_err());
+    }
+
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("cannot serialize"))
+        }
+    }
+
+    impl Debug for Unserializable {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "Unserializable")
+        }
+    }
+
+    #[test]
+    fn serialize_state_spools_to_fallback_file_on_failure() -> Result<(), Box<dyn Error>> {
+        let fallback_file_path = env::temp_dir().join(format!("{}.state", uuid::Uuid::new_v4()));
+        let state_client = StateClient::new(StateClientConfig::new(
+            None,
+            StateBackend::ConfigMap,
+            StateResourceKind::ConfigMap,
+            "/configs/state.yaml",
+            "jarvis-tibber-price-exporter",
+            "",
+            fallback_file_path.to_str().unwrap(),
+            3,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+
+        // act
+        let result = state_client.serialize_state(&Unserializable);
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&fallback_file_path)?, "Unserializable");
+
+        fs::remove_file(&fallback_file_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_state_backend() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            "configmap".parse::<StateBackend>()?,
+            StateBackend::ConfigMap
         );
+        assert_eq!("File".parse::<StateBackend>()?, StateBackend::File);
+        assert_eq!("bigquery".parse::<StateBackend>()?, StateBackend::Bigquery);
+        assert_eq!("GCS".parse::<StateBackend>()?, StateBackend::Gcs);
+        assert!("s3".parse::<StateBackend>().is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_state_writes_file_backend() -> Result<(), Box<dyn Error>> {
+        let state_file_path = env::temp_dir().join(format!("{}.yaml", uuid::Uuid::new_v4()));
+        let state_client = StateClient::new(StateClientConfig::new(
+            None,
+            StateBackend::File,
+            StateResourceKind::ConfigMap,
+            state_file_path.to_str().unwrap(),
+            "jarvis-tibber-price-exporter",
+            "",
+            "",
+            3,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+        let last_from = "2022-05-01T00:00:00Z".parse()?;
+
+        // act
+        state_client
+            .store_state(&State {
+                future_spot_prices: vec![],
+                last_from,
+                written_spot_prices: vec![],
+            })
+            .await?;
+
+        let state = state_client.read_state().await?;
+        assert_eq!(state.map(|s| s.last_from), Some(last_from));
+
+        fs::remove_file(&state_file_path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_state_fails_when_conflict_retries_are_exhausted() -> Result<(), Box<dyn Error>> {
+        let (mock_service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+
+        let config_map_body = r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"jarvis-tibber-price-exporter","namespace":"jarvis","resourceVersion":"1"},"data":{}}"#;
+        let conflict_body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"the object has been modified","reason":"Conflict","code":409}"#;
+
+        let server = tokio::spawn(async move {
+            let responses = vec![
+                (Method::GET, StatusCode::OK, config_map_body),
+                (Method::PUT, StatusCode::CONFLICT, conflict_body),
+                (Method::GET, StatusCode::OK, config_map_body),
+                (Method::PUT, StatusCode::CONFLICT, conflict_body),
+            ];
+
+            for (method, status, body) in responses {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.method(), method);
+                send.send_response(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::from(body))
+                        .unwrap(),
+                );
+            }
+        });
+
+        let state_client = StateClient::new(StateClientConfig::new(
+            Some(kube::Client::new(mock_service, "jarvis")),
+            StateBackend::ConfigMap,
+            StateResourceKind::ConfigMap,
+            "/configs/state.yaml",
+            "jarvis-tibber-price-exporter",
+            "jarvis",
+            "",
+            1,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+
+        // act
+        let result = state_client
+            .store_state(&State {
+                future_spot_prices: vec![],
+                last_from: "2022-05-01T00:00:00Z".parse()?,
+                written_spot_prices: vec![],
+            })
+            .await;
+
+        assert!(matches!(result, Err(ExporterError::StateConflict(_))));
+
+        server.await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_state_creates_missing_configmap() -> Result<(), Box<dyn Error>> {
+        let (mock_service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+
+        let not_found_body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"configmaps \"jarvis-tibber-price-exporter\" not found","reason":"NotFound","code":404}"#;
+        let config_map_body = r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"jarvis-tibber-price-exporter","namespace":"jarvis","resourceVersion":"1"},"data":{}}"#;
+
+        let server = tokio::spawn(async move {
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.method(), Method::GET);
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from(not_found_body))
+                    .unwrap(),
+            );
+
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.method(), Method::POST);
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let config_map: ConfigMap = serde_json::from_slice(&body).unwrap();
+            assert_eq!(
+                config_map.metadata.name.as_deref(),
+                Some("jarvis-tibber-price-exporter")
+            );
+            assert!(config_map.data.unwrap().contains_key("state.yaml"));
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::from(config_map_body))
+                    .unwrap(),
+            );
+        });
+
+        let state_client = StateClient::new(StateClientConfig::new(
+            Some(kube::Client::new(mock_service, "jarvis")),
+            StateBackend::ConfigMap,
+            StateResourceKind::ConfigMap,
+            "/configs/state.yaml",
+            "jarvis-tibber-price-exporter",
+            "jarvis",
+            "",
+            3,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+
+        // act
+        state_client
+            .store_state(&State {
+                future_spot_prices: vec![],
+                last_from: "2022-05-01T00:00:00Z".parse()?,
+                written_spot_prices: vec![],
+            })
+            .await?;
+
+        server.await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_state_creates_missing_secret() -> Result<(), Box<dyn Error>> {
+        let (mock_service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+
+        let not_found_body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"secrets \"jarvis-tibber-price-exporter\" not found","reason":"NotFound","code":404}"#;
+        let secret_body = r#"{"apiVersion":"v1","kind":"Secret","metadata":{"name":"jarvis-tibber-price-exporter","namespace":"jarvis","resourceVersion":"1"},"data":{}}"#;
+
+        let server = tokio::spawn(async move {
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.method(), Method::GET);
+            assert!(request.uri().path().contains("/secrets/"));
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from(not_found_body))
+                    .unwrap(),
+            );
+
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.method(), Method::POST);
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let raw: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(!raw["data"]["state.yaml"]
+                .as_str()
+                .unwrap()
+                .contains("lastFrom"));
+            let secret: Secret = serde_json::from_slice(&body).unwrap();
+            let state_data = secret.data.unwrap().remove("state.yaml").unwrap();
+            assert!(String::from_utf8(state_data.0)
+                .unwrap()
+                .contains("lastFrom"));
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Body::from(secret_body))
+                    .unwrap(),
+            );
+        });
+
+        let state_client = StateClient::new(StateClientConfig::new(
+            Some(kube::Client::new(mock_service, "jarvis")),
+            StateBackend::ConfigMap,
+            StateResourceKind::Secret,
+            "/configs/state.yaml",
+            "jarvis-tibber-price-exporter",
+            "jarvis",
+            "",
+            3,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+
+        // act
+        state_client
+            .store_state(&State {
+                future_spot_prices: vec![],
+                last_from: "2022-05-01T00:00:00Z".parse()?,
+                written_spot_prices: vec![],
+            })
+            .await?;
+
+        server.await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_state_retries_on_conflict() -> Result<(), Box<dyn Error>> {
+        let (mock_service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+
+        let config_map_body = r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"jarvis-tibber-price-exporter","namespace":"jarvis","resourceVersion":"1"},"data":{}}"#;
+        let conflict_body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"the object has been modified","reason":"Conflict","code":409}"#;
+
+        let server = tokio::spawn(async move {
+            let responses = vec![
+                (Method::GET, StatusCode::OK, config_map_body),
+                (Method::PUT, StatusCode::CONFLICT, conflict_body),
+                (Method::GET, StatusCode::OK, config_map_body),
+                (Method::PUT, StatusCode::OK, config_map_body),
+            ];
+
+            for (method, status, body) in responses {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.method(), method);
+                send.send_response(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::from(body))
+                        .unwrap(),
+                );
+            }
+        });
+
+        let state_client = StateClient::new(StateClientConfig::new(
+            Some(kube::Client::new(mock_service, "jarvis")),
+            StateBackend::ConfigMap,
+            StateResourceKind::ConfigMap,
+            "/configs/state.yaml",
+            "jarvis-tibber-price-exporter",
+            "jarvis",
+            "",
+            3,
+            true,
+            None,
+            "tibber",
+            None,
+        )?);
+
+        // act
+        state_client
+            .store_state(&State {
+                future_spot_prices: vec![],
+                last_from: "2022-05-01T00:00:00Z".parse()?,
+                written_spot_prices: vec![],
+            })
+            .await?;
+
+        server.await?;
 
         Ok(())
     }
@@ -1,24 +1,194 @@
-use crate::types::{SpotPrice, SpotPriceResponse};
-use chrono::Duration;
+use crate::error::ExporterError;
+use crate::price_source::{SpotPriceSource, SpotPricesResult};
+use crate::proxy;
+use crate::types::{
+    Consumption, RecordType, SpotPrice, SpotPriceHome, SpotPricePrice, SpotPriceResponse,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::collections::HashSet;
 use std::env;
 use std::error::Error;
-use tracing::debug;
+use std::fs;
+use std::str::FromStr;
+use std::time;
+use tracing::{debug, info, warn};
 
-pub struct TibberClientConfig {
+const DEFAULT_RETRY_AFTER: time::Duration = time::Duration::from_secs(60);
+
+/// Which of the day-ahead price arrays are turned into spot prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchWindow {
+    TodayAndTomorrow,
+    TomorrowOnly,
+    TodayOnly,
+}
+
+impl FetchWindow {
+    fn includes_today(&self) -> bool {
+        *self != FetchWindow::TomorrowOnly
+    }
+
+    fn includes_tomorrow(&self) -> bool {
+        *self != FetchWindow::TodayOnly
+    }
+}
+
+impl FromStr for FetchWindow {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "today_and_tomorrow" => Ok(FetchWindow::TodayAndTomorrow),
+            "tomorrow_only" => Ok(FetchWindow::TomorrowOnly),
+            "today_only" => Ok(FetchWindow::TodayOnly),
+            _ => Err(Box::<dyn Error>::from(format!(
+                "Unknown fetch window {}, expected today_and_tomorrow, tomorrow_only or today_only",
+                s
+            ))),
+        }
+    }
+}
+
+/// Tibber account to retrieve prices for; its name tags the prices when configured.
+#[derive(Debug, Clone, PartialEq)]
+struct TibberAccount {
+    name: Option<String>,
     access_token: String,
 }
 
+impl TibberAccount {
+    /// Parses a comma-separated list of access tokens, each optionally prefixed with `name:`;
+    /// unnamed tokens get numbered when there's more than one, so every account stays apart.
+    fn parse_list(access_tokens: &str) -> Result<Vec<TibberAccount>, Box<dyn Error>> {
+        let entries: Vec<&str> = access_tokens
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        if entries.is_empty() {
+            return Err(Box::<dyn Error>::from("No tibber access token configured"));
+        }
+
+        Ok(entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| match entry.split_once(':') {
+                Some((name, access_token)) => TibberAccount {
+                    name: Some(name.trim().to_string()),
+                    access_token: access_token.trim().to_string(),
+                },
+                None => TibberAccount {
+                    name: if entries.len() > 1 {
+                        Some(format!("account{}", i + 1))
+                    } else {
+                        None
+                    },
+                    access_token: entry.to_string(),
+                },
+            })
+            .collect())
+    }
+}
+
+pub struct TibberClientConfig {
+    api_url: String,
+    accounts: Vec<TibberAccount>,
+    home_id: Option<String>,
+    request_timeout: time::Duration,
+    fetch_window: FetchWindow,
+    client: reqwest::Client,
+}
+
 impl TibberClientConfig {
-    pub fn new(access_token: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        api_url: &str,
+        access_token: &str,
+        home_id: Option<String>,
+        request_timeout: time::Duration,
+        pool_idle_timeout: time::Duration,
+        pool_max_idle_per_host: usize,
+        proxy_url: Option<String>,
+        fetch_window: FetchWindow,
+    ) -> Result<Self, Box<dyn Error>> {
+        // built once so the connection pool and tls sessions are reused across requests
+        let client = proxy::apply_proxy(
+            reqwest::Client::builder()
+                .timeout(request_timeout)
+                .pool_idle_timeout(pool_idle_timeout)
+                .pool_max_idle_per_host(pool_max_idle_per_host),
+            proxy_url.as_deref(),
+        )?
+        .build()?;
+
         Ok(Self {
-            access_token: access_token.to_string(),
+            api_url: api_url.to_string(),
+            accounts: TibberAccount::parse_list(access_token)?,
+            home_id,
+            request_timeout,
+            fetch_window,
+            client,
         })
     }
 
     pub fn from_env() -> Result<Self, Box<dyn Error>> {
-        let access_token = env::var("TIBBER_ACCESS_TOKEN")?;
+        let api_url = env::var("TIBBER_API_URL")
+            .unwrap_or_else(|_| "https://api.tibber.com/v1-beta/gql".to_string());
+        let access_token = Self::access_token(
+            env::var("TIBBER_ACCESS_TOKEN_FILE").ok(),
+            env::var("TIBBER_ACCESS_TOKEN").ok(),
+        )?;
+        let home_id = env::var("TIBBER_HOME_ID").ok().filter(|h| !h.is_empty());
+        let request_timeout_seconds: u64 = env::var("TIBBER_REQUEST_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+        let pool_idle_timeout_seconds: u64 = env::var("TIBBER_POOL_IDLE_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "90".to_string())
+            .parse()
+            .unwrap_or(90);
+        let pool_max_idle_per_host: usize = env::var("TIBBER_POOL_MAX_IDLE_PER_HOST")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse()
+            .unwrap_or(1);
+        let fetch_window: FetchWindow = env::var("FETCH_WINDOW")
+            .unwrap_or_else(|_| "today_and_tomorrow".to_string())
+            .parse()?;
 
-        Self::new(&access_token)
+        Self::new(
+            &api_url,
+            &access_token,
+            home_id,
+            time::Duration::from_secs(request_timeout_seconds),
+            time::Duration::from_secs(pool_idle_timeout_seconds),
+            pool_max_idle_per_host,
+            proxy::proxy_url_from_env(),
+            fetch_window,
+        )
+    }
+
+    /// A token mounted as a file takes precedence over the inline env var, which leaks into
+    /// /proc; trailing newlines added by kubernetes secret mounts are trimmed.
+    fn access_token(
+        token_file: Option<String>,
+        token: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        match token_file.filter(|f| !f.is_empty()) {
+            Some(token_file) => {
+                let token = fs::read_to_string(&token_file).map_err(|e| {
+                    format!(
+                        "Reading tibber access token from {} failed: {}",
+                        token_file, e
+                    )
+                })?;
+
+                Ok(token.trim_end_matches(['\r', '\n']).to_string())
+            }
+            None => token.ok_or_else(|| {
+                "Either TIBBER_ACCESS_TOKEN or TIBBER_ACCESS_TOKEN_FILE needs to be set".into()
+            }),
+        }
     }
 }
 
@@ -35,80 +205,1064 @@ impl TibberClient {
         Ok(Self::new(TibberClientConfig::from_env()?))
     }
 
-    pub async fn get_spot_prices(&self) -> Result<Vec<SpotPrice>, Box<dyn Error>> {
-        let request_body = r#"{"query":"{\n  viewer {\n    homes {\n      currentSubscription{\n        priceInfo{\n          today {\n            energy\n            tax\n            currency\n            startsAt\n          }\n          tomorrow {\n            energy\n            tax\n            currency\n            startsAt\n          }\n        }\n      }\n    }\n  }\n}\n"}"#;
+    /// Retrieves the prices of all accounts; tomorrow only counts as available once it is for
+    /// every account.
+    pub async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError> {
+        let mut spot_prices = vec![];
+        let mut tomorrow_available = true;
+        for account in &self.config.accounts {
+            let result = self.get_account_spot_prices(account).await?;
+            spot_prices.extend(Self::tag_account(result.spot_prices, account));
+            tomorrow_available &= result.tomorrow_available;
+        }
+
+        Ok(SpotPricesResult {
+            spot_prices,
+            tomorrow_available,
+        })
+    }
+
+    /// Sets the account name as source, which the exporter prefixes with the configured source.
+    fn tag_account(spot_prices: Vec<SpotPrice>, account: &TibberAccount) -> Vec<SpotPrice> {
+        spot_prices
+            .into_iter()
+            .map(|spot_price| SpotPrice {
+                source: account.name.clone(),
+                ..spot_price
+            })
+            .collect()
+    }
+
+    async fn get_account_spot_prices(
+        &self,
+        account: &TibberAccount,
+    ) -> Result<SpotPricesResult, ExporterError> {
+        let request_body = r#"{"query":"{\n  viewer {\n    homes {\n      id\n      timeZone\n      currentSubscription{\n        priceInfo{\n          today {\n            energy\n            tax\n            currency\n            startsAt\n            level\n          }\n          tomorrow {\n            energy\n            tax\n            currency\n            startsAt\n            level\n          }\n        }\n      }\n    }\n  }\n}\n"}"#;
+
+        let spot_price_response = self.query(account, request_body).await?;
+
+        let home_id = self.config.home_id.as_deref();
+        let spot_prices = Self::dedup_spot_prices(Self::spot_prices_from_response(
+            &spot_price_response,
+            home_id,
+            self.config.fetch_window,
+        )?);
+
+        // tibber publishes tomorrow's prices around 13:00 CET, an empty array before that is
+        // expected
+        let tomorrow_count = Self::tomorrow_count(&spot_price_response, home_id)?;
+        if tomorrow_count > 0 {
+            info!(
+                "Tomorrow's prices are published with {} entries",
+                tomorrow_count
+            );
+        } else if self.config.fetch_window == FetchWindow::TomorrowOnly {
+            info!("Tomorrow's prices are not published yet, nothing to export when only fetching tomorrow");
+        } else {
+            info!("Tomorrow's prices are not published yet");
+        }
+
+        Ok(SpotPricesResult {
+            spot_prices,
+            tomorrow_available: tomorrow_count > 0,
+        })
+    }
+
+    /// Retrieves hourly prices for the past `hours_back` hours, to backfill after a gap in exports.
+    pub async fn get_historical_prices(
+        &self,
+        hours_back: u32,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let request_body = Self::historical_request_body(hours_back);
+
+        let mut spot_prices = vec![];
+        for account in &self.config.accounts {
+            let spot_price_response = self.query(account, &request_body).await?;
+
+            spot_prices.extend(Self::tag_account(
+                Self::dedup_spot_prices(Self::spot_prices_from_response(
+                    &spot_price_response,
+                    self.config.home_id.as_deref(),
+                    FetchWindow::TodayAndTomorrow,
+                )?),
+                account,
+            ));
+        }
+
+        Ok(spot_prices)
+    }
+
+    fn historical_request_body(hours_back: u32) -> String {
+        format!(
+            r#"{{"query":"{{\n  viewer {{\n    homes {{\n      id\n      timeZone\n      currentSubscription{{\n        priceInfo{{\n          range(resolution: HOURLY, last: {}) {{\n            nodes {{\n              energy\n              tax\n              currency\n              startsAt\n              level\n            }}\n          }}\n        }}\n      }}\n    }}\n  }}\n}}\n"}}"#,
+            hours_back
+        )
+    }
+
+    /// Retrieves the hourly consumption of the past `hours` hours; hours the meter hasn't
+    /// reported yet are left out.
+    pub async fn get_consumption(&self, hours: u32) -> Result<Vec<Consumption>, ExporterError> {
+        let request_body = Self::consumption_request_body(hours);
+
+        let mut consumption = vec![];
+        for account in &self.config.accounts {
+            let consumption_response = self.query(account, &request_body).await?;
+
+            consumption.extend(
+                Self::consumption_from_response(
+                    &consumption_response,
+                    self.config.home_id.as_deref(),
+                )?
+                .into_iter()
+                .map(|c| Consumption {
+                    source: account.name.clone(),
+                    ..c
+                }),
+            );
+        }
 
+        Ok(consumption)
+    }
+
+    fn consumption_request_body(hours: u32) -> String {
+        format!(
+            r#"{{"query":"{{\n  viewer {{\n    homes {{\n      id\n      consumption(resolution: HOURLY, last: {}) {{\n        nodes {{\n          from\n          to\n          consumption\n          consumptionUnit\n          cost\n          unitPrice\n          currency\n        }}\n      }}\n    }}\n  }}\n}}\n"}}"#,
+            hours
+        )
+    }
+
+    fn consumption_from_response(
+        consumption_response: &SpotPriceResponse,
+        home_id: Option<&str>,
+    ) -> Result<Vec<Consumption>, ExporterError> {
+        Ok(Self::homes_from_response(consumption_response, home_id)?
+            .into_iter()
+            .flat_map(|home| {
+                home.consumption
+                    .iter()
+                    .flat_map(|consumption| consumption.nodes.iter())
+                    .filter_map(move |node| {
+                        Some(Consumption {
+                            id: None,
+                            source: None,
+                            home_id: home.id.clone(),
+                            record_type: RecordType::Consumption,
+                            from: node.from,
+                            till: node.to,
+                            consumption: node.consumption?,
+                            consumption_unit: node.consumption_unit.clone(),
+                            cost: node.cost,
+                            unit_price: node.unit_price,
+                            currency: node.currency.clone(),
+                        })
+                    })
+            })
+            .collect())
+    }
+
+    async fn query(
+        &self,
+        account: &TibberAccount,
+        request_body: &str,
+    ) -> Result<SpotPriceResponse, ExporterError> {
         debug!("request body:\n{}", request_body);
 
-        let response = reqwest::Client::new()
-            .post("https://api.tibber.com/v1-beta/gql")
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.config.access_token),
-            )
+        let response = self
+            .config
+            .client
+            .post(&self.config.api_url)
+            .header("Authorization", format!("Bearer {}", account.access_token))
             .header("content-type", "application/json")
-            .body(request_body)
+            .body(request_body.to_string())
             .send()
-            .await?;
+            .await
+            .map_err(|e| self.map_request_error(e))?;
 
         let status_code = response.status();
         debug!("response status: {}", status_code);
 
-        let response_body = response.text().await?;
+        if status_code == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = Self::parse_retry_after(
+                response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok()),
+                Utc::now(),
+            );
+
+            return Err(ExporterError::TibberRateLimited { retry_after });
+        }
+
+        if status_code == reqwest::StatusCode::UNAUTHORIZED
+            || status_code == reqwest::StatusCode::FORBIDDEN
+        {
+            return Err(ExporterError::TibberAuth(status_code.as_u16()));
+        }
+
+        let response_body = response
+            .text()
+            .await
+            .map_err(|e| self.map_request_error(e))?;
         debug!("response body:\n{}", response_body);
 
         if !status_code.is_success() {
-            return Err(Box::<dyn Error>::from(format!(
+            return Err(ExporterError::TibberHttp(format!(
                 "Status code {} indicates failure",
                 status_code
             )));
         }
 
-        let spot_price_response = serde_json::from_str::<SpotPriceResponse>(&response_body)?;
+        serde_json::from_str::<SpotPriceResponse>(&response_body).map_err(|e| {
+            ExporterError::TibberResponse(format!("Tibber response is invalid: {}", e))
+        })
+    }
 
-        let mut spot_prices: Vec<SpotPrice> = vec![];
+    /// Parses a Retry-After header value, which is either a number of seconds or an http date;
+    /// falls back to a default when it's missing or can't be parsed.
+    fn parse_retry_after(retry_after: Option<&str>, now: DateTime<Utc>) -> time::Duration {
+        let retry_after = match retry_after {
+            Some(ra) => ra.trim(),
+            None => return DEFAULT_RETRY_AFTER,
+        };
 
-        for spot_price in &spot_price_response.data.viewer.homes[0]
-            .current_subscription
-            .price_info
-            .today
-        {
-            spot_prices.push(SpotPrice {
-                id: None,
-                source: None,
-                from: spot_price.starts_at,
-                till: spot_price.starts_at + Duration::hours(1),
-                market_price: spot_price.energy,
-                market_price_tax: spot_price.tax,
-                sourcing_markup_price: 0.0,
-                energy_tax_price: 0.0,
-            })
+        if let Ok(seconds) = retry_after.parse::<u64>() {
+            return time::Duration::from_secs(seconds);
         }
 
-        for spot_price in &spot_price_response.data.viewer.homes[0]
-            .current_subscription
-            .price_info
-            .tomorrow
+        match DateTime::parse_from_rfc2822(retry_after) {
+            Ok(date) => (date.with_timezone(&Utc) - now)
+                .to_std()
+                .unwrap_or(time::Duration::ZERO),
+            Err(_) => DEFAULT_RETRY_AFTER,
+        }
+    }
+
+    fn map_request_error(&self, e: reqwest::Error) -> ExporterError {
+        if e.is_timeout() {
+            ExporterError::TibberHttp(format!(
+                "Tibber request to {} timed out after {}s",
+                self.config.api_url,
+                self.config.request_timeout.as_secs()
+            ))
+        } else {
+            ExporterError::TibberHttp(e.to_string())
+        }
+    }
+
+    fn tomorrow_count(
+        spot_price_response: &SpotPriceResponse,
+        home_id: Option<&str>,
+    ) -> Result<usize, ExporterError> {
+        Ok(Self::homes_from_response(spot_price_response, home_id)?
+            .iter()
+            .filter_map(|home| home.current_subscription.as_ref())
+            .map(|subscription| subscription.price_info.tomorrow.len())
+            .sum())
+    }
+
+    /// Returns the homes in the response, limited to the configured home if any.
+    fn homes_from_response<'a>(
+        spot_price_response: &'a SpotPriceResponse,
+        home_id: Option<&str>,
+    ) -> Result<Vec<&'a SpotPriceHome>, ExporterError> {
+        if let Some(error) = spot_price_response
+            .errors
+            .as_ref()
+            .and_then(|errors| errors.first())
         {
-            spot_prices.push(SpotPrice {
-                id: None,
-                source: None,
-                from: spot_price.starts_at,
-                till: spot_price.starts_at + Duration::hours(1),
-                market_price: spot_price.energy,
-                market_price_tax: spot_price.tax,
-                sourcing_markup_price: 0.0,
-                energy_tax_price: 0.0,
+            return Err(ExporterError::TibberResponse(format!(
+                "Tibber GraphQL error: {}",
+                error.message
+            )));
+        }
+
+        let viewer = match &spot_price_response.data {
+            Some(data) => &data.viewer,
+            None => {
+                return Err(ExporterError::TibberResponse(
+                    "Tibber response contains no data".to_string(),
+                ))
+            }
+        };
+
+        if viewer.homes.is_empty() {
+            return Err(ExporterError::TibberResponse(
+                "Tibber account has no homes associated with the provided token".to_string(),
+            ));
+        }
+
+        let homes: Vec<&SpotPriceHome> = match home_id {
+            Some(home_id) => {
+                let homes: Vec<&SpotPriceHome> = viewer
+                    .homes
+                    .iter()
+                    .filter(|h| h.id.as_deref() == Some(home_id))
+                    .collect();
+
+                if homes.is_empty() {
+                    return Err(ExporterError::TibberResponse(format!(
+                        "No Tibber home found with id {}",
+                        home_id
+                    )));
+                }
+
+                homes
+            }
+            None => viewer.homes.iter().collect(),
+        };
+
+        Ok(homes)
+    }
+
+    /// Keeps the first of identical prices, which homes in the same bidding zone all return.
+    fn dedup_spot_prices(spot_prices: Vec<SpotPrice>) -> Vec<SpotPrice> {
+        let count = spot_prices.len();
+        let mut seen: HashSet<SpotPrice> = HashSet::new();
+        let spot_prices: Vec<SpotPrice> = spot_prices
+            .into_iter()
+            .filter(|spot_price| seen.insert(spot_price.clone()))
+            .collect();
+
+        if spot_prices.len() < count {
+            debug!(
+                "Dropped {} duplicate prices of homes in the same bidding zone",
+                count - spot_prices.len()
+            );
+        }
+
+        spot_prices
+    }
+
+    /// Ends each interval at the start of the next one, so both hourly and quarter-hourly prices
+    /// line up without gaps or overlaps. The last interval is as long as the one before it, or an
+    /// hour if there's only one.
+    fn interval_ends(starts_at: &[DateTime<Utc>]) -> Vec<DateTime<Utc>> {
+        let mut previous_length = Duration::hours(1);
+
+        starts_at
+            .iter()
+            .enumerate()
+            .map(|(i, start)| match starts_at.get(i + 1) {
+                Some(next_start) if next_start > start => {
+                    previous_length = *next_start - *start;
+                    *next_start
+                }
+                _ => *start + previous_length,
             })
+            .collect()
+    }
+
+    fn spot_prices_from_response(
+        spot_price_response: &SpotPriceResponse,
+        home_id: Option<&str>,
+        fetch_window: FetchWindow,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        let mut spot_prices: Vec<SpotPrice> = vec![];
+
+        for home in Self::homes_from_response(spot_price_response, home_id)? {
+            let price_info = match &home.current_subscription {
+                Some(subscription) => &subscription.price_info,
+                None => {
+                    warn!(
+                        "Skipping Tibber home {} without a current subscription",
+                        home.id.as_deref().unwrap_or("unknown")
+                    );
+                    continue;
+                }
+            };
+
+            let time_zone: Option<Tz> = home.time_zone.as_deref().and_then(|tz| match tz.parse() {
+                Ok(tz) => Some(tz),
+                Err(_) => {
+                    warn!("Ignoring unknown timezone {} of Tibber home", tz);
+                    None
+                }
+            });
+
+            // interval ends are derived from all nodes, so the last price of today still ends at
+            // the first of tomorrow when only today is exported
+            let range_nodes = price_info
+                .range
+                .iter()
+                .flat_map(|range| range.nodes.iter())
+                .map(|node| (node, true));
+            let nodes: Vec<(&SpotPricePrice, bool)> = price_info
+                .today
+                .iter()
+                .map(|node| (node, fetch_window.includes_today()))
+                .chain(
+                    price_info
+                        .tomorrow
+                        .iter()
+                        .map(|node| (node, fetch_window.includes_tomorrow())),
+                )
+                .chain(range_nodes)
+                .collect();
+            let tills = Self::interval_ends(
+                &nodes
+                    .iter()
+                    .map(|(node, _)| node.starts_at)
+                    .collect::<Vec<DateTime<Utc>>>(),
+            );
+
+            for ((spot_price, included), till) in nodes.into_iter().zip(tills) {
+                if !included {
+                    continue;
+                }
+
+                spot_prices.push(SpotPrice {
+                    id: None,
+                    source: None,
+                    home_id: home.id.clone(),
+                    record_type: RecordType::Spot,
+                    currency: spot_price.currency.clone(),
+                    price_level: spot_price.level.clone(),
+                    computed_price_level: None,
+                    ingested_at: Utc::now(),
+                    from: spot_price.starts_at,
+                    till,
+                    market_price: spot_price.energy,
+                    market_price_tax: spot_price.tax,
+                    sourcing_markup_price: 0.0,
+                    energy_tax_price: 0.0,
+                    appliance_cycle_cost: None,
+                    original_currency: None,
+                    exchange_rate: None,
+                    time_zone: time_zone.map(|tz| tz.name().to_string()),
+                    local_date: time_zone
+                        .map(|tz| spot_price.starts_at.with_timezone(&tz).date_naive()),
+                    resolution: Some((till - spot_price.starts_at).num_minutes() as u32),
+                })
+            }
         }
 
         Ok(spot_prices)
     }
 }
 
+#[async_trait(?Send)]
+impl SpotPriceSource for TibberClient {
+    fn name(&self) -> &str {
+        "tibber"
+    }
+
+    async fn get_spot_prices(&self) -> Result<SpotPricesResult, ExporterError> {
+        TibberClient::get_spot_prices(self).await
+    }
+
+    async fn get_historical_prices(
+        &self,
+        hours_back: u32,
+    ) -> Result<Vec<SpotPrice>, ExporterError> {
+        TibberClient::get_historical_prices(self, hours_back).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::NaiveDate;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn spot_prices_from_response_includes_all_homes() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let mut spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        let homes = &mut spot_price_response.data.as_mut().unwrap().viewer.homes;
+        let mut second_home = homes[0].clone();
+        second_home.id = Some("second-home".to_string());
+        homes.push(second_home);
+
+        // act
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?;
+
+        assert_eq!(spot_prices.len(), 48);
+        assert_eq!(
+            spot_prices
+                .iter()
+                .filter(|sp| sp.home_id == Some("second-home".to_string()))
+                .count(),
+            24
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_spot_prices_collapses_homes_in_same_bidding_zone() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let mut spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        let homes = &mut spot_price_response.data.as_mut().unwrap().viewer.homes;
+        let mut second_home = homes[0].clone();
+        second_home.id = Some("second-home".to_string());
+        homes.push(second_home);
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?;
+
+        // act
+        let spot_prices = TibberClient::dedup_spot_prices(spot_prices);
+
+        assert_eq!(spot_prices.len(), 24);
+        assert!(spot_prices
+            .iter()
+            .all(|sp| sp.home_id != Some("second-home".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_filters_on_home_id() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let mut spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        let homes = &mut spot_price_response.data.as_mut().unwrap().viewer.homes;
+        homes[0].id = Some("first-home".to_string());
+        let mut second_home = homes[0].clone();
+        second_home.id = Some("second-home".to_string());
+        homes.push(second_home);
+
+        // act
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            Some("second-home"),
+            FetchWindow::TodayAndTomorrow,
+        )?;
+
+        assert_eq!(spot_prices.len(), 24);
+        assert!(spot_prices
+            .iter()
+            .all(|sp| sp.home_id == Some("second-home".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_fails_for_unknown_home_id() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        // act
+        let result = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            Some("unknown-home"),
+            FetchWindow::TodayAndTomorrow,
+        );
+
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_fails_without_homes() -> Result<(), Box<dyn Error>> {
+        let spot_price_response: SpotPriceResponse =
+            serde_json::from_str(r#"{"data":{"viewer":{"homes":[]}}}"#)?;
+
+        // act
+        let result = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Tibber account has no homes associated with the provided token"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_surfaces_graphql_errors() -> Result<(), Box<dyn Error>> {
+        let spot_price_response: SpotPriceResponse = serde_json::from_str(
+            r#"{"errors":[{"message":"invalid token","locations":[{"line":1,"column":3}],"path":["viewer"]}],"data":null}"#,
+        )?;
+
+        // act
+        let result = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Tibber GraphQL error: invalid token"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_skips_homes_without_subscription() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let mut spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        let homes = &mut spot_price_response.data.as_mut().unwrap().viewer.homes;
+        homes[0].id = Some("first-home".to_string());
+        homes.push(serde_json::from_str(
+            r#"{"id":"second-home","currentSubscription":null}"#,
+        )?);
+
+        // act
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?;
+
+        assert_eq!(spot_prices.len(), 24);
+        assert!(spot_prices
+            .iter()
+            .all(|sp| sp.home_id == Some("first-home".to_string())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_maps_range_nodes() -> Result<(), Box<dyn Error>> {
+        let spot_price_response: SpotPriceResponse = serde_json::from_str(
+            r#"{"data":{"viewer":{"homes":[{"id":"first-home","currentSubscription":{"priceInfo":{"range":{"nodes":[{"energy":0.2,"tax":0.042,"currency":"EUR","startsAt":"2022-05-01T00:00:00Z","level":"CHEAP"},{"energy":0.3,"tax":0.063,"currency":"EUR","startsAt":"2022-05-01T01:00:00Z","level":"NORMAL"}]}}}}]}}}"#,
+        )?;
+
+        // act
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?;
+
+        assert_eq!(spot_prices.len(), 2);
+        assert_eq!(
+            spot_prices[1].from,
+            "2022-05-01T01:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(
+            spot_prices[1].till,
+            "2022-05-01T02:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(spot_prices[1].market_price, 0.3);
+        assert_eq!(spot_prices[1].home_id.as_deref(), Some("first-home"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn interval_ends_follow_next_start() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let starts_at: Vec<DateTime<Utc>> =
+            (0..4).map(|i| start + Duration::minutes(15 * i)).collect();
+
+        // act
+        let tills = TibberClient::interval_ends(&starts_at);
+
+        assert_eq!(
+            tills,
+            vec![
+                start + Duration::minutes(15),
+                start + Duration::minutes(30),
+                start + Duration::minutes(45),
+                start + Duration::minutes(60),
+            ]
+        );
+        assert_eq!(
+            TibberClient::interval_ends(&[start]),
+            vec![start + Duration::hours(1)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_from_response_without_tomorrow_for_tomorrow_only() -> Result<(), Box<dyn Error>>
+    {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        // act
+        let spot_prices = TibberClient::spot_prices_from_response(
+            &spot_price_response,
+            None,
+            FetchWindow::TomorrowOnly,
+        )?;
+
+        assert!(spot_prices.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_fetch_window() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            "today_and_tomorrow".parse::<FetchWindow>()?,
+            FetchWindow::TodayAndTomorrow
+        );
+        assert_eq!(
+            "Tomorrow_Only".parse::<FetchWindow>()?,
+            FetchWindow::TomorrowOnly
+        );
+        assert_eq!("today_only".parse::<FetchWindow>()?, FetchWindow::TodayOnly);
+        assert!("yesterday".parse::<FetchWindow>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tomorrow_count() -> Result<(), Box<dyn Error>> {
+        let spot_price_predictions_content = fs::read_to_string("spot_price_predictions.json")?;
+        let mut spot_price_response: SpotPriceResponse =
+            serde_json::from_str(&spot_price_predictions_content)?;
+
+        // act
+        let tomorrow_count = TibberClient::tomorrow_count(&spot_price_response, None)?;
+
+        assert_eq!(tomorrow_count, 0);
+
+        let price_info = &mut spot_price_response.data.as_mut().unwrap().viewer.homes[0]
+            .current_subscription
+            .as_mut()
+            .unwrap()
+            .price_info;
+        price_info.tomorrow = price_info.today.clone();
+
+        let tomorrow_count = TibberClient::tomorrow_count(&spot_price_response, None)?;
+
+        assert_eq!(tomorrow_count, 24);
+
+        Ok(())
+    }
+
+    #[test]
+    fn historical_request_body_is_valid_json() -> Result<(), Box<dyn Error>> {
+        // act
+        let request_body = TibberClient::historical_request_body(48);
+
+        let request: serde_json::Value = serde_json::from_str(&request_body)?;
+        assert!(request["query"]
+            .as_str()
+            .unwrap()
+            .contains("range(resolution: HOURLY, last: 48)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn consumption_request_body_is_valid_json() -> Result<(), Box<dyn Error>> {
+        // act
+        let request_body = TibberClient::consumption_request_body(24);
+
+        let request: serde_json::Value = serde_json::from_str(&request_body)?;
+        assert!(request["query"]
+            .as_str()
+            .unwrap()
+            .contains("consumption(resolution: HOURLY, last: 24)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn consumption_from_response_skips_unreported_hours() -> Result<(), Box<dyn Error>> {
+        let consumption_response: SpotPriceResponse = serde_json::from_value(serde_json::json!({
+            "data": {
+                "viewer": {
+                    "homes": [{
+                        "id": "home-1",
+                        "consumption": {
+                            "nodes": [
+                                {
+                                    "from": "2022-05-01T00:00:00+02:00",
+                                    "to": "2022-05-01T01:00:00+02:00",
+                                    "consumption": 0.5,
+                                    "consumptionUnit": "kWh",
+                                    "cost": 0.125,
+                                    "unitPrice": 0.25,
+                                    "currency": "EUR"
+                                },
+                                {
+                                    "from": "2022-05-01T01:00:00+02:00",
+                                    "to": "2022-05-01T02:00:00+02:00",
+                                    "consumption": null,
+                                    "consumptionUnit": "kWh",
+                                    "cost": null,
+                                    "unitPrice": null,
+                                    "currency": "EUR"
+                                }
+                            ]
+                        }
+                    }]
+                }
+            }
+        }))?;
+
+        // act
+        let consumption = TibberClient::consumption_from_response(&consumption_response, None)?;
+
+        assert_eq!(consumption.len(), 1);
+        assert_eq!(consumption[0].home_id, Some("home-1".to_string()));
+        assert_eq!(
+            consumption[0].from,
+            "2022-04-30T22:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(
+            consumption[0].till,
+            "2022-04-30T23:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(consumption[0].consumption, 0.5);
+        assert_eq!(consumption[0].cost, Some(0.125));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        let now: DateTime<Utc> = "2022-05-01T11:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            TibberClient::parse_retry_after(Some("120"), now),
+            time::Duration::from_secs(120)
+        );
+        assert_eq!(
+            TibberClient::parse_retry_after(Some("Sun, 01 May 2022 11:00:30 GMT"), now),
+            time::Duration::from_secs(30)
+        );
+        assert_eq!(
+            TibberClient::parse_retry_after(Some("Sun, 01 May 2022 10:59:30 GMT"), now),
+            time::Duration::ZERO
+        );
+        assert_eq!(
+            TibberClient::parse_retry_after(None, now),
+            DEFAULT_RETRY_AFTER
+        );
+        assert_eq!(
+            TibberClient::parse_retry_after(Some("soon"), now),
+            DEFAULT_RETRY_AFTER
+        );
+    }
+
+    fn tibber_client(mock_server: &MockServer) -> Result<TibberClient, Box<dyn Error>> {
+        Ok(TibberClient::new(TibberClientConfig::new(
+            &mock_server.uri(),
+            "token",
+            None,
+            time::Duration::from_secs(5),
+            time::Duration::from_secs(90),
+            1,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?))
+    }
+
+    async fn mock_response(mock_server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(response)
+            .mount(mock_server)
+            .await;
+    }
+
+    fn price_nodes(start: DateTime<Utc>, count: i64) -> Vec<serde_json::Value> {
+        (0..count)
+            .map(|i| {
+                serde_json::json!({
+                    "energy": 0.25 + i as f64,
+                    "tax": 0.0625,
+                    "currency": "EUR",
+                    "startsAt": (start + Duration::hours(i)).to_rfc3339(),
+                    "level": "NORMAL",
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_maps_today() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        mock_response(
+            &mock_server,
+            ResponseTemplate::new(200)
+                .set_body_string(fs::read_to_string("spot_price_predictions.json")?),
+        )
+        .await;
+
+        // act
+        let result = tibber_client(&mock_server)?.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 24);
+        assert!(!result.tomorrow_available);
+
+        let spot_price = &result.spot_prices[0];
+        assert_eq!(spot_price.market_price, 0.5522);
+        assert_eq!(spot_price.market_price_tax, 0.2125);
+        assert_eq!(spot_price.currency, "SEK");
+        assert_eq!(
+            spot_price.from,
+            "2022-09-28T22:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(
+            spot_price.till,
+            "2022-09-28T23:00:00Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(spot_price.record_type, RecordType::Spot);
+        assert_eq!(spot_price.time_zone, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_maps_today_and_tomorrow() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let mock_server = MockServer::start().await;
+        mock_response(
+            &mock_server,
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {"viewer": {"homes": [{
+                    "id": "home-1",
+                    "timeZone": "Europe/Amsterdam",
+                    "currentSubscription": {"priceInfo": {
+                        "today": price_nodes(start, 24),
+                        "tomorrow": price_nodes(start + Duration::hours(24), 24),
+                    }},
+                }]}}
+            })),
+        )
+        .await;
+
+        // act
+        let result = tibber_client(&mock_server)?.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 48);
+        assert!(result.tomorrow_available);
+
+        let spot_price = &result.spot_prices[25];
+        assert_eq!(spot_price.home_id.as_deref(), Some("home-1"));
+        assert_eq!(spot_price.market_price, 1.25);
+        assert_eq!(spot_price.market_price_tax, 0.0625);
+        assert_eq!(spot_price.currency, "EUR");
+        assert_eq!(spot_price.price_level.as_deref(), Some("NORMAL"));
+        assert_eq!(spot_price.from, start + Duration::hours(25));
+        assert_eq!(spot_price.till, start + Duration::hours(26));
+        assert_eq!(spot_price.time_zone.as_deref(), Some("Europe/Amsterdam"));
+        assert_eq!(spot_price.local_date, NaiveDate::from_ymd_opt(2022, 5, 2));
+
+        // 23:00 UTC is already the next day in Amsterdam
+        assert_eq!(
+            result.spot_prices[23].local_date,
+            NaiveDate::from_ymd_opt(2022, 5, 2)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_fails_without_homes() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        mock_response(
+            &mock_server,
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"data": {"viewer": {"homes": []}}})),
+        )
+        .await;
+
+        // act
+        let result = tibber_client(&mock_server)?.get_spot_prices().await;
+
+        assert!(matches!(result, Err(ExporterError::TibberResponse(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_surfaces_graphql_errors() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        mock_response(
+            &mock_server,
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "errors": [{"message": "invalid query"}],
+                "data": null,
+            })),
+        )
+        .await;
+
+        // act
+        let result = tibber_client(&mock_server)?.get_spot_prices().await;
+
+        assert_eq!(
+            result.err().map(|e| e.to_string()),
+            Some("Tibber GraphQL error: invalid query".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_is_rate_limited() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        mock_response(
+            &mock_server,
+            ResponseTemplate::new(429).insert_header("Retry-After", "120"),
+        )
+        .await;
+
+        // act
+        let result = tibber_client(&mock_server)?.get_spot_prices().await;
+
+        assert!(matches!(
+            result,
+            Err(ExporterError::TibberRateLimited { retry_after }) if retry_after == time::Duration::from_secs(120)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_through_proxy() -> Result<(), Box<dyn Error>> {
+        let proxy_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1-beta/gql"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(fs::read_to_string("spot_price_predictions.json")?),
+            )
+            .mount(&proxy_server)
+            .await;
+        // the api url doesn't resolve, so the request can only succeed through the proxy
+        let tibber_client = TibberClient::new(TibberClientConfig::new(
+            "http://api.tibber.invalid/v1-beta/gql",
+            "token",
+            None,
+            time::Duration::from_secs(5),
+            time::Duration::from_secs(90),
+            1,
+            Some(proxy_server.uri()),
+            FetchWindow::TodayAndTomorrow,
+        )?);
+
+        // act
+        let result = tibber_client.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 24);
+
+        Ok(())
+    }
 
     #[tokio::test]
     #[ignore]
@@ -119,8 +1273,102 @@ mod tests {
         let spot_prices = spot_price_client
             .get_spot_prices()
             .await
-            .expect("Failed retrieving spot prices");
+            .expect("Failed retrieving spot prices")
+            .spot_prices;
 
         assert_eq!(spot_prices.len(), 24);
     }
+
+    #[test]
+    fn access_token_from_file_takes_precedence() -> Result<(), Box<dyn Error>> {
+        let token_file = env::temp_dir().join(format!("{}.token", uuid::Uuid::new_v4()));
+        fs::write(&token_file, "file-token\n")?;
+
+        // act
+        let access_token = TibberClientConfig::access_token(
+            Some(token_file.to_string_lossy().to_string()),
+            Some("env-token".to_string()),
+        );
+
+        fs::remove_file(&token_file)?;
+        assert_eq!(access_token?, "file-token");
+
+        Ok(())
+    }
+
+    #[test]
+    fn access_token_from_env() -> Result<(), Box<dyn Error>> {
+        // act
+        let access_token = TibberClientConfig::access_token(None, Some("env-token".to_string()))?;
+
+        assert_eq!(access_token, "env-token");
+        assert!(TibberClientConfig::access_token(None, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_accounts() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            TibberAccount::parse_list("token")?,
+            vec![TibberAccount {
+                name: None,
+                access_token: "token".to_string(),
+            }]
+        );
+        assert_eq!(
+            TibberAccount::parse_list("alice:token-a, token-b")?,
+            vec![
+                TibberAccount {
+                    name: Some("alice".to_string()),
+                    access_token: "token-a".to_string(),
+                },
+                TibberAccount {
+                    name: Some("account2".to_string()),
+                    access_token: "token-b".to_string(),
+                },
+            ]
+        );
+        assert!(TibberAccount::parse_list(" , ").is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_spot_prices_for_multiple_accounts() -> Result<(), Box<dyn Error>> {
+        let mock_server = MockServer::start().await;
+        for token in ["token-a", "token-b"] {
+            Mock::given(method("POST"))
+                .and(header(
+                    "Authorization",
+                    format!("Bearer {}", token).as_str(),
+                ))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string(fs::read_to_string("spot_price_predictions.json")?),
+                )
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+        let tibber_client = TibberClient::new(TibberClientConfig::new(
+            &mock_server.uri(),
+            "alice:token-a,bob:token-b",
+            None,
+            time::Duration::from_secs(5),
+            time::Duration::from_secs(90),
+            1,
+            None,
+            FetchWindow::TodayAndTomorrow,
+        )?);
+
+        // act
+        let result = tibber_client.get_spot_prices().await?;
+
+        assert_eq!(result.spot_prices.len(), 48);
+        assert_eq!(result.spot_prices[0].source.as_deref(), Some("alice"));
+        assert_eq!(result.spot_prices[24].source.as_deref(), Some("bob"));
+
+        Ok(())
+    }
 }
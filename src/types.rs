@@ -1,10 +1,22 @@
-use chrono::{DateTime, Utc};
+use crate::error::ValidationError;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotPriceResponse {
-    pub data: SpotPriceData,
+    pub data: Option<SpotPriceData>,
+    pub errors: Option<Vec<GraphQLError>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLError {
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,23 +31,58 @@ pub struct SpotPriceViewer {
     pub homes: Vec<SpotPriceHome>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotPriceHome {
-    pub current_subscription: SpotPriceSubscription,
+    pub id: Option<String>,
+    pub time_zone: Option<String>,
+    /// Null for homes without an active subscription, e.g. while switching providers.
+    pub current_subscription: Option<SpotPriceSubscription>,
+    /// Only present when querying consumption.
+    pub consumption: Option<HomeConsumption>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HomeConsumption {
+    #[serde(default)]
+    pub nodes: Vec<ConsumptionNode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumptionNode {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Null for hours the meter hasn't reported yet.
+    pub consumption: Option<f64>,
+    pub consumption_unit: Option<String>,
+    pub cost: Option<f64>,
+    pub unit_price: Option<f64>,
+    pub currency: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotPriceSubscription {
     pub price_info: SpotPriceInfo,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SpotPriceInfo {
+    #[serde(default)]
     pub today: Vec<SpotPricePrice>,
+    #[serde(default)]
     pub tomorrow: Vec<SpotPricePrice>,
+    /// Only present when querying past prices with the range connection.
+    pub range: Option<SpotPriceRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotPriceRange {
+    pub nodes: Vec<SpotPricePrice>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,6 +92,26 @@ pub struct SpotPricePrice {
     pub tax: f64,
     pub currency: String,
     pub starts_at: DateTime<Utc>,
+    pub level: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordType {
+    #[default]
+    Spot,
+    Production,
+    Consumption,
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordType::Spot => write!(f, "spot"),
+            RecordType::Production => write!(f, "production"),
+            RecordType::Consumption => write!(f, "consumption"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -52,12 +119,333 @@ pub struct SpotPricePrice {
 pub struct SpotPrice {
     pub id: Option<String>,
     pub source: Option<String>,
+    pub home_id: Option<String>,
+    #[serde(default)]
+    pub record_type: RecordType,
+    #[serde(default)]
+    pub currency: String,
+    pub price_level: Option<String>,
+    /// Level derived from where the price falls within its day, consistent across sources.
+    pub computed_price_level: Option<PriceLevel>,
     pub from: DateTime<Utc>,
     pub till: DateTime<Utc>,
     pub market_price: f64,
     pub market_price_tax: f64,
     pub sourcing_markup_price: f64,
     pub energy_tax_price: f64,
+    pub appliance_cycle_cost: Option<f64>,
+    /// Currency Tibber returned the price in, when converted into another currency.
+    pub original_currency: Option<String>,
+    pub exchange_rate: Option<f64>,
+    /// Timezone of the home, so prices can be grouped by the local day they belong to.
+    pub time_zone: Option<String>,
+    pub local_date: Option<NaiveDate>,
+    /// Length in minutes of the intervals the source publishes prices for, which stays the same
+    /// when equal prices get coalesced into a longer interval.
+    pub resolution: Option<u32>,
+    /// When the exporter retrieved the price, the same for all prices of a run.
+    #[serde(default)]
+    pub ingested_at: DateTime<Utc>,
+}
+
+/// Quintiles of the total prices within a day, named like the levels Tibber returns.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PriceLevel {
+    VeryCheap,
+    Cheap,
+    Normal,
+    Expensive,
+    VeryExpensive,
+}
+
+impl fmt::Display for PriceLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PriceLevel::VeryCheap => write!(f, "VERY_CHEAP"),
+            PriceLevel::Cheap => write!(f, "CHEAP"),
+            PriceLevel::Normal => write!(f, "NORMAL"),
+            PriceLevel::Expensive => write!(f, "EXPENSIVE"),
+            PriceLevel::VeryExpensive => write!(f, "VERY_EXPENSIVE"),
+        }
+    }
+}
+
+impl SpotPrice {
+    pub fn total_price(&self) -> f64 {
+        self.market_price
+            + self.market_price_tax
+            + self.sourcing_markup_price
+            + self.energy_tax_price
+    }
+
+    /// Checks for values upstream bugs have produced before: prices that aren't finite numbers
+    /// and intervals that don't end after they start.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let fields = [
+            ("marketPrice", Some(self.market_price)),
+            ("marketPriceTax", Some(self.market_price_tax)),
+            ("sourcingMarkupPrice", Some(self.sourcing_markup_price)),
+            ("energyTaxPrice", Some(self.energy_tax_price)),
+            ("applianceCycleCost", self.appliance_cycle_cost),
+            ("exchangeRate", self.exchange_rate),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value.filter(|v| !v.is_finite()) {
+                return Err(ValidationError::NotFinite { field, value });
+            }
+        }
+
+        if self.till <= self.from {
+            return Err(ValidationError::EmptyInterval {
+                from: self.from,
+                till: self.till,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Classifies every price by the quintile of its day it falls in, the day being the local date
+    /// when known and the utc date otherwise; equal prices get the same level. Levels are returned
+    /// in the order of the prices.
+    pub fn classify_levels(prices: &[SpotPrice]) -> Vec<PriceLevel> {
+        let day_key = |spot_price: &SpotPrice| {
+            (
+                spot_price.source.clone(),
+                spot_price.home_id.clone(),
+                spot_price
+                    .local_date
+                    .unwrap_or_else(|| spot_price.from.date_naive()),
+            )
+        };
+
+        let mut days: BTreeMap<(Option<String>, Option<String>, NaiveDate), Vec<f64>> =
+            BTreeMap::new();
+        for spot_price in prices {
+            days.entry(day_key(spot_price))
+                .or_default()
+                .push(spot_price.total_price());
+        }
+
+        prices
+            .iter()
+            .map(|spot_price| {
+                let day_prices = &days[&day_key(spot_price)];
+                let total_price = spot_price.total_price();
+                let cheaper = day_prices.iter().filter(|p| **p < total_price).count();
+
+                match cheaper * 5 / day_prices.len() {
+                    0 => PriceLevel::VeryCheap,
+                    1 => PriceLevel::Cheap,
+                    2 => PriceLevel::Normal,
+                    3 => PriceLevel::Expensive,
+                    _ => PriceLevel::VeryExpensive,
+                }
+            })
+            .collect()
+    }
+
+    pub fn key(&self) -> SpotPriceKey {
+        SpotPriceKey {
+            home_id: self.home_id.clone(),
+            from: self.from,
+        }
+    }
+
+    pub fn stored_key(&self) -> StoredSpotPriceKey {
+        StoredSpotPriceKey {
+            source: self.source.clone().unwrap_or_default(),
+            home_id: self.home_id.clone().unwrap_or_default(),
+            from: self.from,
+        }
+    }
+
+    /// Fields that make up the identity of a price, leaving out the `id` and `home_id` so the same
+    /// price retrieved for multiple homes in one bidding zone compares equal, and `ingested_at` so
+    /// it compares equal when retrieved again in a later run. Prices are compared
+    /// by their bits so equality is consistent with hashing.
+    #[allow(clippy::type_complexity)]
+    fn identity(
+        &self,
+    ) -> (
+        (
+            &Option<String>,
+            RecordType,
+            &str,
+            &Option<String>,
+            Option<PriceLevel>,
+        ),
+        (DateTime<Utc>, DateTime<Utc>),
+        (u64, u64, u64, u64, Option<u64>),
+        (
+            &Option<String>,
+            Option<u64>,
+            &Option<String>,
+            Option<NaiveDate>,
+            Option<u32>,
+        ),
+    ) {
+        (
+            (
+                &self.source,
+                self.record_type,
+                &self.currency,
+                &self.price_level,
+                self.computed_price_level,
+            ),
+            (self.from, self.till),
+            (
+                self.market_price.to_bits(),
+                self.market_price_tax.to_bits(),
+                self.sourcing_markup_price.to_bits(),
+                self.energy_tax_price.to_bits(),
+                self.appliance_cycle_cost.map(f64::to_bits),
+            ),
+            (
+                &self.original_currency,
+                self.exchange_rate.map(f64::to_bits),
+                &self.time_zone,
+                self.local_date,
+                self.resolution,
+            ),
+        )
+    }
+
+    /// Summarizes the total price per source, home and calendar day in `time_zone`, leaving out
+    /// days the prices don't cover completely. The average is weighted by duration, so coalesced
+    /// prices count for every hour they span.
+    pub fn aggregate_daily(spot_prices: &[SpotPrice], time_zone: Tz) -> Vec<DailyPriceSummary> {
+        let mut days: BTreeMap<(Option<String>, Option<String>, NaiveDate), Vec<&SpotPrice>> =
+            BTreeMap::new();
+        for spot_price in spot_prices {
+            let date = spot_price.from.with_timezone(&time_zone).date_naive();
+            days.entry((spot_price.source.clone(), spot_price.home_id.clone(), date))
+                .or_default()
+                .push(spot_price);
+        }
+
+        days.into_iter()
+            .filter(|((_, _, date), day_spot_prices)| {
+                Self::covers_day(day_spot_prices, *date, time_zone)
+            })
+            .map(|((source, home_id, date), day_spot_prices)| {
+                let total_seconds: i64 = day_spot_prices
+                    .iter()
+                    .map(|sp| (sp.till - sp.from).num_seconds())
+                    .sum();
+                let weighted_total: f64 = day_spot_prices
+                    .iter()
+                    .map(|sp| sp.total_price() * (sp.till - sp.from).num_seconds() as f64)
+                    .sum();
+
+                DailyPriceSummary {
+                    source,
+                    home_id,
+                    date,
+                    time_zone: time_zone.name().to_string(),
+                    currency: day_spot_prices[0].currency.clone(),
+                    count: day_spot_prices.len(),
+                    min_total_price: day_spot_prices
+                        .iter()
+                        .map(|sp| sp.total_price())
+                        .fold(f64::INFINITY, f64::min),
+                    max_total_price: day_spot_prices
+                        .iter()
+                        .map(|sp| sp.total_price())
+                        .fold(f64::NEG_INFINITY, f64::max),
+                    average_total_price: if total_seconds > 0 {
+                        weighted_total / total_seconds as f64
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the prices cover the whole of `date` in `time_zone`, which lasts 23 or 25 hours
+    /// when daylight saving time starts or ends.
+    fn covers_day(spot_prices: &[&SpotPrice], date: NaiveDate, time_zone: Tz) -> bool {
+        let start_of = |date: NaiveDate| -> Option<DateTime<Utc>> {
+            time_zone
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .earliest()
+                .map(|start| start.with_timezone(&Utc))
+        };
+        let (start, end) = match (start_of(date), date.succ_opt().and_then(start_of)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return false,
+        };
+
+        let covered_seconds: i64 = spot_prices
+            .iter()
+            .map(|sp| (sp.till.min(end) - sp.from.max(start)).num_seconds())
+            .sum();
+
+        covered_seconds >= (end - start).num_seconds()
+    }
+}
+
+impl PartialEq for SpotPrice {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for SpotPrice {}
+
+impl Hash for SpotPrice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyPriceSummary {
+    pub source: Option<String>,
+    pub home_id: Option<String>,
+    pub date: NaiveDate,
+    pub time_zone: String,
+    pub currency: String,
+    pub count: usize,
+    pub min_total_price: f64,
+    pub max_total_price: f64,
+    pub average_total_price: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct SpotPriceKey {
+    pub home_id: Option<String>,
+    pub from: DateTime<Utc>,
+}
+
+/// Identifies a row in a sink that's shared by multiple sources and homes; a missing source or
+/// home is stored as an empty string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StoredSpotPriceKey {
+    pub source: String,
+    pub home_id: String,
+    pub from: DateTime<Utc>,
+}
+
+/// Measured consumption of a home over an interval, along with what it cost.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Consumption {
+    pub id: Option<String>,
+    pub source: Option<String>,
+    pub home_id: Option<String>,
+    pub record_type: RecordType,
+    pub from: DateTime<Utc>,
+    pub till: DateTime<Utc>,
+    pub consumption: f64,
+    pub consumption_unit: Option<String>,
+    pub cost: Option<f64>,
+    pub unit_price: Option<f64>,
+    pub currency: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -65,6 +453,37 @@ pub struct SpotPrice {
 pub struct State {
     pub future_spot_prices: Vec<SpotPrice>,
     pub last_from: DateTime<Utc>,
+    #[serde(default)]
+    pub written_spot_prices: Vec<SpotPriceKey>,
+}
+
+#[cfg(test)]
+impl SpotPrice {
+    /// Hourly spot price starting at `from`, for tests to override the fields they care about.
+    pub fn test_at(from: DateTime<Utc>) -> Self {
+        SpotPrice {
+            id: None,
+            source: None,
+            home_id: None,
+            record_type: RecordType::Spot,
+            currency: "EUR".to_string(),
+            price_level: None,
+            computed_price_level: None,
+            from,
+            till: from + chrono::Duration::hours(1),
+            market_price: 0.2,
+            market_price_tax: 0.042,
+            sourcing_markup_price: 0.0,
+            energy_tax_price: 0.0,
+            appliance_cycle_cost: None,
+            original_currency: None,
+            exchange_rate: None,
+            time_zone: None,
+            local_date: None,
+            resolution: None,
+            ingested_at: Utc::now(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -81,16 +500,20 @@ mod tests {
             serde_json::from_str(&spot_price_predictions_content)?;
 
         assert_eq!(
-            spot_price_response.data.viewer.homes[0]
+            spot_price_response.data.as_ref().unwrap().viewer.homes[0]
                 .current_subscription
+                .as_ref()
+                .unwrap()
                 .price_info
                 .today
                 .len(),
             24
         );
         assert_eq!(
-            spot_price_response.data.viewer.homes[0]
+            spot_price_response.data.as_ref().unwrap().viewer.homes[0]
                 .current_subscription
+                .as_ref()
+                .unwrap()
                 .price_info
                 .tomorrow
                 .len(),
@@ -98,4 +521,245 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn serialize_spot_price_includes_record_type() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?);
+
+        let json = serde_json::to_value(&spot_price)?;
+        assert_eq!(json["recordType"], "spot");
+
+        let yaml = serde_yaml::to_string(&spot_price)?;
+        assert!(yaml.contains("recordType: spot"));
+
+        Ok(())
+    }
+
+    fn valid_spot_price() -> Result<SpotPrice, Box<dyn Error>> {
+        Ok(SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?))
+    }
+
+    #[test]
+    fn validate_accepts_valid_spot_price() -> Result<(), Box<dyn Error>> {
+        // act
+        let result = valid_spot_price()?.validate();
+
+        assert_eq!(result, Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_rejects_nan_market_price() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            market_price: f64::NAN,
+            ..valid_spot_price()?
+        };
+
+        // act
+        let result = spot_price.validate();
+
+        assert!(matches!(
+            result,
+            Err(ValidationError::NotFinite {
+                field: "marketPrice",
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_rejects_reversed_interval() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            till: "2022-04-30T23:00:00Z".parse()?,
+            ..valid_spot_price()?
+        };
+
+        // act
+        let result = spot_price.validate();
+
+        assert_eq!(
+            result,
+            Err(ValidationError::EmptyInterval {
+                from: spot_price.from,
+                till: spot_price.till,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn total_price() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            market_price: 0.25,
+            market_price_tax: 0.05,
+            sourcing_markup_price: 0.02,
+            energy_tax_price: 0.125,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        assert!((spot_price.total_price() - 0.445).abs() < 1e-9);
+
+        // negative market prices can push the total below zero
+        let negative_spot_price = SpotPrice {
+            market_price: -0.5,
+            market_price_tax: -0.105,
+            ..spot_price
+        };
+
+        assert!((negative_spot_price.total_price() - -0.46).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_spot_price_without_record_type_defaults_to_spot() -> Result<(), Box<dyn Error>> {
+        let spot_price: SpotPrice = serde_yaml::from_str(
+            "from: 2022-05-01T00:00:00Z\ntill: 2022-05-01T01:00:00Z\nmarketPrice: 0.2\nmarketPriceTax: 0.042\nsourcingMarkupPrice: 0.0\nenergyTaxPrice: 0.0\n",
+        )?;
+
+        assert_eq!(spot_price.record_type, RecordType::Spot);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_spot_price_info_without_tomorrow() -> Result<(), Box<dyn Error>> {
+        // act
+        let spot_price_info: SpotPriceInfo = serde_json::from_str(
+            r#"{"today":[{"energy":0.2,"tax":0.042,"currency":"EUR","startsAt":"2022-05-01T00:00:00+02:00","level":"NORMAL"}]}"#,
+        )?;
+
+        assert_eq!(spot_price_info.today.len(), 1);
+        assert!(spot_price_info.tomorrow.is_empty());
+        assert!(spot_price_info.range.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn classify_levels_by_quintile_within_day() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-05-01T00:00:00Z".parse()?;
+        let spot_prices: Vec<SpotPrice> = [0.3, 0.1, 0.5, 0.2, 0.4, 0.1, 0.9]
+            .iter()
+            .enumerate()
+            .map(|(i, market_price)| SpotPrice {
+                source: Some("entsoe".to_string()),
+                till: start + chrono::Duration::hours(if i == 6 { 25 } else { i as i64 + 1 }),
+                market_price: *market_price,
+                market_price_tax: 0.0,
+                // the last price falls on the next day, where it's the only one
+                ..SpotPrice::test_at(
+                    start + chrono::Duration::hours(if i == 6 { 24 } else { i as i64 }),
+                )
+            })
+            .collect();
+
+        // act
+        let levels = SpotPrice::classify_levels(&spot_prices);
+
+        assert_eq!(
+            levels,
+            vec![
+                PriceLevel::Normal,
+                PriceLevel::VeryCheap,
+                PriceLevel::VeryExpensive,
+                PriceLevel::Cheap,
+                PriceLevel::Expensive,
+                PriceLevel::VeryCheap,
+                PriceLevel::VeryCheap,
+            ]
+        );
+        assert_eq!(PriceLevel::VeryExpensive.to_string(), "VERY_EXPENSIVE");
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_daily_groups_by_local_day() -> Result<(), Box<dyn Error>> {
+        let start: DateTime<Utc> = "2022-04-30T21:00:00Z".parse()?;
+        let market_prices: Vec<f64> = [0.5, 0.25, 0.75, 1.0]
+            .into_iter()
+            .chain([0.5; 21])
+            .collect();
+        let spot_prices: Vec<SpotPrice> = market_prices
+            .iter()
+            .enumerate()
+            .map(|(i, market_price)| SpotPrice {
+                source: Some("tibber".to_string()),
+                market_price: *market_price,
+                market_price_tax: 0.0,
+                ..SpotPrice::test_at(start + chrono::Duration::hours(i as i64))
+            })
+            .collect();
+
+        // act
+        let summaries = SpotPrice::aggregate_daily(&spot_prices, chrono_tz::Europe::Amsterdam);
+
+        // 21:00 UTC is still april 30th in amsterdam, of which only the last hour is known
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0].date,
+            NaiveDate::from_ymd_opt(2022, 5, 1).unwrap()
+        );
+        assert_eq!(summaries[0].time_zone, "Europe/Amsterdam");
+        assert_eq!(summaries[0].count, 24);
+        assert_eq!(summaries[0].min_total_price, 0.25);
+        assert_eq!(summaries[0].max_total_price, 1.0);
+        assert!((summaries[0].average_total_price - 12.5 / 24.0).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_daily_leaves_out_incomplete_days() -> Result<(), Box<dyn Error>> {
+        // daylight saving time starts on march 27th, making it 23 hours long in amsterdam
+        let start: DateTime<Utc> = "2022-03-26T23:00:00Z".parse()?;
+        let spot_prices: Vec<SpotPrice> = (0..23)
+            .map(|i| SpotPrice::test_at(start + chrono::Duration::hours(i)))
+            .collect();
+
+        // act
+        let summaries = SpotPrice::aggregate_daily(&spot_prices, chrono_tz::Europe::Amsterdam);
+        let partial_summaries =
+            SpotPrice::aggregate_daily(&spot_prices[..22], chrono_tz::Europe::Amsterdam);
+
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].count, 23);
+        assert!(partial_summaries.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn spot_prices_of_different_homes_are_equal() -> Result<(), Box<dyn Error>> {
+        let spot_price = SpotPrice {
+            id: Some("a8d1e3b6-54a1-4b8b-b3a4-4d0a4c1e2f00".to_string()),
+            home_id: Some("home-1".to_string()),
+            market_price: 0.25,
+            market_price_tax: 0.05,
+            ..SpotPrice::test_at("2022-05-01T00:00:00Z".parse()?)
+        };
+
+        assert_eq!(
+            spot_price,
+            SpotPrice {
+                id: None,
+                home_id: Some("home-2".to_string()),
+                ..spot_price.clone()
+            }
+        );
+        assert_ne!(
+            spot_price,
+            SpotPrice {
+                market_price: 0.3,
+                ..spot_price.clone()
+            }
+        );
+
+        Ok(())
+    }
 }
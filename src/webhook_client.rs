@@ -0,0 +1,113 @@
+use crate::retry::RetryPolicy;
+use crate::sink::SpotPriceSink;
+use crate::types::SpotPrice;
+use async_trait::async_trait;
+use std::env;
+use std::error::Error;
+use std::time;
+use tracing::info;
+
+pub struct WebhookClientConfig {
+    url: String,
+    token: String,
+    retry_policy: RetryPolicy,
+    client: reqwest::Client,
+}
+
+impl WebhookClientConfig {
+    pub fn new(
+        url: &str,
+        token: &str,
+        timeout: time::Duration,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+        Ok(Self {
+            url: url.to_string(),
+            token: token.to_string(),
+            retry_policy,
+            client,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let url = env::var("WEBHOOK_URL").unwrap_or_default();
+        let token = env::var("WEBHOOK_TOKEN").unwrap_or_default();
+        let timeout_seconds: u64 = env::var("WEBHOOK_TIMEOUT_SECONDS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .unwrap_or(10);
+
+        Self::new(
+            &url,
+            &token,
+            time::Duration::from_secs(timeout_seconds),
+            RetryPolicy::from_env(),
+        )
+    }
+}
+
+pub struct WebhookClient {
+    config: WebhookClientConfig,
+}
+
+impl WebhookClient {
+    pub fn new(config: WebhookClientConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(WebhookClientConfig::from_env()?))
+    }
+
+    async fn post(&self, spot_prices: &[SpotPrice]) -> Result<(), String> {
+        let mut request = self.config.client.post(&self.config.url).json(spot_prices);
+        if !self.config.token.is_empty() {
+            request = request.bearer_auth(&self.config.token);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            return Err(format!(
+                "Webhook status code {} indicates failure",
+                status_code
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Posts the spot prices newly written in this run; does nothing if there are none.
+    pub async fn send_spot_prices(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        if self.config.url.is_empty() || spot_prices.is_empty() {
+            return Ok(());
+        }
+
+        self.config
+            .retry_policy
+            .retry(
+                "Posting to webhook",
+                || self.post(spot_prices),
+                |_: &String| true,
+            )
+            .await?;
+
+        info!("Posted {} new spot prices to webhook", spot_prices.len());
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SpotPriceSink for WebhookClient {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn insert(&self, spot_prices: &[SpotPrice]) -> Result<(), Box<dyn Error>> {
+        self.send_spot_prices(spot_prices).await
+    }
+}